@@ -5,13 +5,17 @@ use serde::{Deserialize, Serialize};
 pub struct Account<T: Config> {
     pub account_id: T::Hash,
     pub balance: T::Funds,
+    // Number of transactions signed by this account that were accepted so far, i.e. the nonce
+    // its next transaction must carry
+    pub nonce: T::Nonce,
 }
 
 impl<T: Config> Clone for Account<T> {
     fn clone(&self) -> Self {
         Self {
-            account_id: self.account_id.clone(),
-            balance: self.balance.clone(),
+            account_id: self.account_id,
+            balance: self.balance,
+            nonce: self.nonce,
         }
     }
 }
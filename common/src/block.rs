@@ -1,6 +1,7 @@
 use blake2::{Blake2s256, Digest};
 use serde::{Deserialize, Serialize};
 
+use crate::merkle::{self, MerkleProof};
 use crate::{extrinsics::SignedTransaction, types::Config, types::Get};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,16 +26,19 @@ pub struct Header<T: Config> {
     pub state_root: T::Hash,
     pub extrinsics_root: T::Hash,
     pub block_weight: T::WeightType,
+    // Account credited with the block reward by the coinbase inherent
+    pub author: T::Hash,
 }
 
 impl<T: Config> Clone for Header<T> {
     fn clone(&self) -> Self {
         Self {
             block_height: self.block_height.clone(),
-            parent_hash: self.parent_hash.clone(),
-            state_root: self.state_root.clone(),
-            extrinsics_root: self.extrinsics_root.clone(),
+            parent_hash: self.parent_hash,
+            state_root: self.state_root,
+            extrinsics_root: self.extrinsics_root,
             block_weight: self.block_weight.clone(),
+            author: self.author,
         }
     }
 }
@@ -42,12 +46,14 @@ impl<T: Config> Clone for Header<T> {
 pub trait BlockTrait<T: Config> {
     fn extrinsics(&self) -> &Vec<SignedTransaction<T>>;
     fn hash(&self) -> [u8; 32];
+    fn extrinsics_root(&self) -> T::Hash;
+    fn extrinsic_proof(&self, index: usize) -> Option<MerkleProof>;
     fn add_extrinsic(&mut self, extrinsic: SignedTransaction<T>) -> Result<(), String>;
     fn can_add_extrinsic(&self, weight: T::WeightType) -> bool;
 }
 
 // Implement the BlockTrait for the Block struct
-impl<T: Config> BlockTrait<T> for Block<T> {
+impl<T: Config + Serialize> BlockTrait<T> for Block<T> {
     fn extrinsics(&self) -> &Vec<SignedTransaction<T>> {
         &self.extrinsics
     }
@@ -58,12 +64,22 @@ impl<T: Config> BlockTrait<T> for Block<T> {
         hasher.update(self.header.parent_hash);
         hasher.update(self.header.state_root);
         hasher.update(self.header.extrinsics_root);
-        hasher
-            .finalize()
-            .try_into()
-            .expect("This hash has an expected size of 32 bytes")
+        hasher.update(self.header.author);
+        hasher.finalize().into()
     }
 
+    // Merkle root over the bincode-encoded extrinsics, as they appear in the block body
+    fn extrinsics_root(&self) -> T::Hash {
+        T::Hash::from(merkle::merkle_root(&encode_extrinsics(&self.extrinsics)))
+    }
+
+    // Inclusion proof for the extrinsic at `index`, checked with `verify_extrinsic_proof`
+    fn extrinsic_proof(&self, index: usize) -> Option<MerkleProof> {
+        merkle::merkle_proof(&encode_extrinsics(&self.extrinsics), index)
+    }
+
+    // Whether `weight` more fits in the block, block builders add the weight they reserve for the
+    // hooks to the extrinsic's own
     fn can_add_extrinsic(&self, weight: T::WeightType) -> bool {
         self.header.block_weight.clone() + weight <= T::MaxBlockWeight::get()
     }
@@ -73,6 +89,7 @@ impl<T: Config> BlockTrait<T> for Block<T> {
         if self.can_add_extrinsic(extrinsic.weight()) {
             self.extrinsics.push(extrinsic.clone());
             self.header.block_weight += extrinsic.weight();
+            self.header.extrinsics_root = self.extrinsics_root();
             Ok(())
         } else {
             Err(format!(
@@ -84,3 +101,151 @@ impl<T: Config> BlockTrait<T> for Block<T> {
         }
     }
 }
+
+fn encode_extrinsics<T: Config + Serialize>(extrinsics: &[SignedTransaction<T>]) -> Vec<Vec<u8>> {
+    extrinsics
+        .iter()
+        .map(|extrinsic| bincode::serialize(extrinsic).expect("Extrinsics are always serializable"))
+        .collect()
+}
+
+/// Check that `extrinsic` is part of the block whose header holds `extrinsics_root`,
+/// without needing the rest of the block body.
+pub fn verify_extrinsic_proof<T: Config + Serialize>(
+    extrinsics_root: &T::Hash,
+    extrinsic: &SignedTransaction<T>,
+    proof: &MerkleProof,
+) -> bool {
+    match bincode::serialize(extrinsic) {
+        Ok(encoded) => merkle::verify_proof(extrinsics_root.as_ref(), &encoded, proof),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockConfig;
+    use crate::types::{BalancesCall, Height, MaxBlockWeight, TransactionType};
+
+    fn empty_block() -> Block<MockConfig> {
+        Block {
+            header: Header {
+                block_height: Height::from(1),
+                parent_hash: [0; 32],
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author: [0; 32],
+            },
+            extrinsics: Vec::new(),
+        }
+    }
+
+    fn transfer(amount: u128) -> SignedTransaction<MockConfig> {
+        SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+            from: [0; 32],
+            to: [1; 32],
+            amount,
+        }))
+    }
+
+    mod extrinsics_root {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_empty_block_extrinsics_root() {
+                let block = empty_block();
+                assert_eq!(block.extrinsics_root(), merkle::EMPTY_ROOT);
+                assert_eq!(block.header.extrinsics_root, block.extrinsics_root());
+            }
+
+            #[test]
+            fn test_add_extrinsic_updates_root() {
+                let mut block = empty_block();
+                block.add_extrinsic(transfer(10)).unwrap();
+                let single_root = block.header.extrinsics_root;
+                assert_ne!(single_root, merkle::EMPTY_ROOT);
+
+                block.add_extrinsic(transfer(20)).unwrap();
+                assert_ne!(block.header.extrinsics_root, single_root);
+                assert_eq!(block.header.extrinsics_root, block.extrinsics_root());
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_tampered_body_changes_root() {
+                let mut block = empty_block();
+                block.add_extrinsic(transfer(10)).unwrap();
+
+                // Swapping the body behind the header's back is detected
+                block.extrinsics[0] = transfer(1000);
+                assert_ne!(block.header.extrinsics_root, block.extrinsics_root());
+            }
+
+            #[test]
+            fn test_rejected_extrinsic_keeps_root() {
+                let mut block = empty_block();
+                block.header.block_weight = MaxBlockWeight::get();
+                let root_before = block.header.extrinsics_root;
+
+                assert!(block.add_extrinsic(transfer(10)).is_err());
+                assert_eq!(block.header.extrinsics_root, root_before);
+            }
+        }
+    }
+
+    mod extrinsic_proof {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_extrinsic_proof_against_header() {
+                let mut block = empty_block();
+                for amount in 1..=5 {
+                    block.add_extrinsic(transfer(amount)).unwrap();
+                }
+
+                // Only the header and the extrinsic itself are needed to check inclusion
+                let header = block.header.clone();
+                for (index, extrinsic) in block.extrinsics().iter().enumerate() {
+                    let proof = block.extrinsic_proof(index).unwrap();
+                    assert!(verify_extrinsic_proof::<MockConfig>(
+                        &header.extrinsics_root,
+                        extrinsic,
+                        &proof
+                    ));
+                }
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_extrinsic_proof_out_of_range() {
+                let mut block = empty_block();
+                block.add_extrinsic(transfer(1)).unwrap();
+                assert!(block.extrinsic_proof(1).is_none());
+            }
+
+            #[test]
+            fn test_extrinsic_proof_for_other_extrinsic() {
+                let mut block = empty_block();
+                block.add_extrinsic(transfer(1)).unwrap();
+                block.add_extrinsic(transfer(2)).unwrap();
+
+                let proof = block.extrinsic_proof(0).unwrap();
+                assert!(!verify_extrinsic_proof::<MockConfig>(
+                    &block.header.extrinsics_root,
+                    &transfer(2),
+                    &proof
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::types::Config;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+/// An ed25519 key pair used to sign transactions.
+#[derive(Debug, Clone)]
+pub struct Pair {
+    signing_key: SigningKey,
+}
+
+impl Pair {
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        Pair {
+            signing_key: SigningKey::from_bytes(seed),
+        }
+    }
+
+    pub fn generate() -> Self {
+        Pair {
+            signing_key: SigningKey::generate(&mut rand::rngs::OsRng),
+        }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+
+    pub fn account_id<T: Config>(&self) -> T::Hash {
+        account_id_from_public::<T>(&self.public())
+    }
+}
+
+/// Account ids are the signer's public key.
+pub fn account_id_from_public<T: Config>(public: &[u8; 32]) -> T::Hash {
+    T::Hash::from(*public)
+}
+
+pub fn verify(public: &[u8; 32], message: &[u8], signature: &[u8]) -> bool {
+    let Ok(verifying_key) = VerifyingKey::from_bytes(public) else {
+        return false;
+    };
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return false;
+    };
+    verifying_key.verify_strict(message, &signature).is_ok()
+}
+
+/// Well-known development accounts, derived from fixed seeds so every node agrees on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyring {
+    Alice,
+    Dave,
+}
+
+impl Keyring {
+    pub fn pair(&self) -> Pair {
+        let seed = match self {
+            Keyring::Alice => [0; 32],
+            Keyring::Dave => [1; 32],
+        };
+        Pair::from_seed(&seed)
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.pair().public()
+    }
+
+    pub fn account_id<T: Config>(&self) -> T::Hash {
+        self.pair().account_id::<T>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod sign_and_verify {
+        mod success {
+            use crate::crypto::*;
+
+            #[test]
+            fn test_sign_and_verify() {
+                let pair = Pair::generate();
+                let signature = pair.sign(b"message");
+                assert!(verify(&pair.public(), b"message", &signature));
+            }
+
+            #[test]
+            fn test_keyring_is_deterministic() {
+                assert_eq!(Keyring::Alice.public(), Keyring::Alice.public());
+                assert_ne!(Keyring::Alice.public(), Keyring::Dave.public());
+            }
+        }
+
+        mod failure {
+            use crate::crypto::*;
+
+            #[test]
+            fn test_verify_other_message() {
+                let pair = Keyring::Alice.pair();
+                let signature = pair.sign(b"message");
+                assert!(!verify(&pair.public(), b"other message", &signature));
+            }
+
+            #[test]
+            fn test_verify_other_signer() {
+                let signature = Keyring::Alice.pair().sign(b"message");
+                assert!(!verify(&Keyring::Dave.public(), b"message", &signature));
+            }
+
+            #[test]
+            fn test_verify_malformed_signature() {
+                let pair = Keyring::Alice.pair();
+                assert!(!verify(&pair.public(), b"message", &[1, 2, 3]));
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::types::{Config, TransactionError};
+use serde::{Deserialize, Serialize};
+
+/// Something that happened while executing a block, events of pallets are wrapped in their own variant.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum Event<T: Config> {
+    Balances(BalancesEvent<T>),
+    FeePaid {
+        who: T::Hash,
+        amount: T::Funds,
+    },
+    Rewarded {
+        author: T::Hash,
+        amount: T::Funds,
+    },
+    ExtrinsicFailed {
+        index: u32,
+        error: TransactionError<T>,
+    },
+}
+
+impl<T: Config> Clone for Event<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Balances(event) => Self::Balances(event.clone()),
+            Self::FeePaid { who, amount } => Self::FeePaid {
+                who: *who,
+                amount: *amount,
+            },
+            Self::Rewarded { author, amount } => Self::Rewarded {
+                author: *author,
+                amount: *amount,
+            },
+            Self::ExtrinsicFailed { index, error } => Self::ExtrinsicFailed {
+                index: *index,
+                error: error.clone(),
+            },
+        }
+    }
+}
+
+impl<T: Config> From<BalancesEvent<T>> for Event<T> {
+    fn from(event: BalancesEvent<T>) -> Self {
+        Self::Balances(event)
+    }
+}
+
+/// Events of the balances pallet.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum BalancesEvent<T: Config> {
+    Transferred {
+        from: T::Hash,
+        to: T::Hash,
+        amount: T::Funds,
+    },
+    Minted {
+        to: T::Hash,
+        amount: T::Funds,
+    },
+    Burned {
+        from: T::Hash,
+        amount: T::Funds,
+    },
+    AccountCreated {
+        account_id: T::Hash,
+        balance: T::Funds,
+    },
+}
+
+impl<T: Config> Clone for BalancesEvent<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Transferred { from, to, amount } => Self::Transferred {
+                from: *from,
+                to: *to,
+                amount: *amount,
+            },
+            Self::Minted { to, amount } => Self::Minted {
+                to: *to,
+                amount: *amount,
+            },
+            Self::Burned { from, amount } => Self::Burned {
+                from: *from,
+                amount: *amount,
+            },
+            Self::AccountCreated {
+                account_id,
+                balance,
+            } => Self::AccountCreated {
+                account_id: *account_id,
+                balance: *balance,
+            },
+        }
+    }
+}
+
+/// Events of an executed block, in the order they were emitted.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BlockEvents<T: Config> {
+    pub block_height: T::HeightType,
+    pub events: Vec<Event<T>>,
+}
+
+impl<T: Config> Clone for BlockEvents<T> {
+    fn clone(&self) -> Self {
+        Self {
+            block_height: self.block_height.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
@@ -1,25 +1,206 @@
-use crate::types::{Config, TransactionType};
+use crate::crypto::{self, Pair};
+use crate::types::{CheckedAdd, CheckedMul, Config, Get, TransactionError, TransactionType, Zero};
 use serde::{Deserialize, Serialize};
 
+/// Public key of the signer along with its ed25519 signature over the encoded call and nonce.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransactionSignature {
+    pub signer: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SignedTransaction<T: Config> {
     pub transaction_type: TransactionType<T>,
+    // Must be the signer's next nonce for the transaction to be accepted, ignored when unsigned
+    pub nonce: T::Nonce,
+    pub signature: Option<TransactionSignature>,
 }
 
 impl<T: Config> Clone for SignedTransaction<T> {
     fn clone(&self) -> Self {
         Self {
             transaction_type: self.transaction_type.clone(),
+            nonce: self.nonce,
+            signature: self.signature.clone(),
         }
     }
 }
 
 impl<T: Config> SignedTransaction<T> {
+    // Build an unsigned transaction
     pub fn new(transaction_type: TransactionType<T>) -> Self {
-        SignedTransaction { transaction_type }
+        SignedTransaction {
+            transaction_type,
+            nonce: T::Nonce::zero(),
+            signature: None,
+        }
+    }
+
+    // Build the coinbase inherent of a block authored by `author`
+    pub fn coinbase(author: T::Hash) -> Self {
+        Self::new(TransactionType::Coinbase { author })
+    }
+
+    // Build a transaction signed by `pair`, `nonce` being the signer's next nonce
+    pub fn new_signed(transaction_type: TransactionType<T>, nonce: T::Nonce, pair: &Pair) -> Self {
+        let signature = pair.sign(&Self::signing_payload(&transaction_type, nonce));
+        SignedTransaction {
+            transaction_type,
+            nonce,
+            signature: Some(TransactionSignature {
+                signer: pair.public(),
+                signature,
+            }),
+        }
+    }
+
+    // The bytes covered by the signature, the nonce is part of them so a transaction cannot be replayed
+    pub fn signing_payload(transaction_type: &TransactionType<T>, nonce: T::Nonce) -> Vec<u8> {
+        bincode::serialize(&(transaction_type, nonce))
+            .expect("Transactions are always serializable")
+    }
+
+    // Account id of the signer, `None` if the transaction is unsigned
+    pub fn signer(&self) -> Option<T::Hash> {
+        self.signature
+            .as_ref()
+            .map(|signature| crypto::account_id_from_public::<T>(&signature.signer))
+    }
+
+    // Whether the transaction carries a valid signature, unsigned transactions are never valid
+    pub fn verify_signature(&self) -> bool {
+        match &self.signature {
+            Some(signature) => crypto::verify(
+                &signature.signer,
+                &Self::signing_payload(&self.transaction_type, self.nonce),
+                &signature.signature,
+            ),
+            None => false,
+        }
+    }
+
+    // Inherents are put in the block by its producer, they never go through the transaction pool
+    pub fn is_inherent(&self) -> bool {
+        matches!(self.transaction_type, TransactionType::Coinbase { .. })
     }
 
     pub fn weight(&self) -> T::WeightType {
         self.transaction_type.weight()
     }
+
+    // Fee paid by the signer for the transaction to be included: the base fee, plus its weight
+    // and encoded length priced as per the fee schedule of `T`. A fee too large for `T::Funds`
+    // is an overflow, such a transaction can never be paid for
+    pub fn fee(&self) -> Result<T::Funds, TransactionError<T>>
+    where
+        Self: Serialize,
+    {
+        let weight: u64 = self.weight().into();
+        let length = bincode::serialized_size(self).expect("Transactions are always serializable");
+
+        let weight_fee = T::WeightFee::get()
+            .checked_mul(&T::Funds::from(weight as u128))
+            .ok_or(TransactionError::Overflow)?;
+        let length_fee = T::LengthFee::get()
+            .checked_mul(&T::Funds::from(length as u128))
+            .ok_or(TransactionError::Overflow)?;
+        T::BaseFee::get()
+            .checked_add(&weight_fee)
+            .and_then(|fee| fee.checked_add(&length_fee))
+            .ok_or(TransactionError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Keyring;
+    use crate::mock::MockConfig;
+    use crate::types::BalancesCall;
+
+    fn transfer(amount: u128) -> TransactionType<MockConfig> {
+        TransactionType::Balances(BalancesCall::Transfer {
+            from: Keyring::Alice.account_id::<MockConfig>(),
+            to: Keyring::Dave.account_id::<MockConfig>(),
+            amount,
+        })
+    }
+
+    mod fee {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_fee_follows_schedule() {
+                let transaction =
+                    SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                let length = bincode::serialized_size(&transaction).unwrap() as u128;
+
+                // Base fee of 10, a transfer weighs 10 and every byte costs 1
+                assert_eq!(transaction.fee(), Ok(10 + 10 + length));
+            }
+
+            #[test]
+            fn test_fee_grows_with_length() {
+                let unsigned = SignedTransaction::new(transfer(10));
+                let signed = SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                assert!(signed.fee().unwrap() > unsigned.fee().unwrap());
+            }
+        }
+    }
+
+    mod signature {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_signed_transaction_verifies() {
+                let transaction =
+                    SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                assert!(transaction.verify_signature());
+                assert_eq!(
+                    transaction.signer(),
+                    Some(Keyring::Alice.account_id::<MockConfig>())
+                );
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_unsigned_transaction_does_not_verify() {
+                let transaction = SignedTransaction::new(transfer(10));
+                assert!(!transaction.verify_signature());
+                assert_eq!(transaction.signer(), None);
+            }
+
+            #[test]
+            fn test_tampered_call_does_not_verify() {
+                let mut transaction =
+                    SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                transaction.transaction_type = transfer(1000);
+                assert!(!transaction.verify_signature());
+            }
+
+            #[test]
+            fn test_tampered_nonce_does_not_verify() {
+                let mut transaction =
+                    SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                transaction.nonce = 1;
+                assert!(!transaction.verify_signature());
+            }
+
+            #[test]
+            fn test_swapped_signer_does_not_verify() {
+                let mut transaction =
+                    SignedTransaction::new_signed(transfer(10), 0, &Keyring::Alice.pair());
+                if let Some(signature) = transaction.signature.as_mut() {
+                    signature.signer = Keyring::Dave.public();
+                }
+                assert!(!transaction.verify_signature());
+            }
+        }
+    }
 }
@@ -1,4 +1,11 @@
 pub mod account;
 pub mod block;
+pub mod crypto;
+pub mod event;
 pub mod extrinsics;
+pub mod merkle;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod receipt;
+pub mod trie;
 pub mod types;
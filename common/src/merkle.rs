@@ -0,0 +1,282 @@
+use blake2::{Blake2s256, Digest};
+use serde::{Deserialize, Serialize};
+
+/// Root of a tree without leaves, used by blocks that carry no extrinsics.
+pub const EMPTY_ROOT: [u8; 32] = [0; 32];
+
+// Leaves and inner nodes are hashed with a different prefix so that one can never pass for the other
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+// The root commits to the number of leaves, a proof cannot claim another shape for the tree
+const COUNT_PREFIX: u8 = 0x02;
+
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn counted_root(leaf_count: usize, top: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    hasher.update([COUNT_PREFIX]);
+    hasher.update((leaf_count as u64).to_le_bytes());
+    hasher.update(top);
+    hasher.finalize().into()
+}
+
+/// Binary Merkle root over `leaves`, in order.
+///
+/// An odd node at the end of a level is promoted to the next level as is. The top of the tree is
+/// hashed together with the number of leaves to give the root.
+pub fn merkle_root<L: AsRef<[u8]>>(leaves: &[L]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return EMPTY_ROOT;
+    }
+
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(|leaf| leaf_hash(leaf.as_ref())).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    counted_root(leaves.len(), &level[0])
+}
+
+/// Proof that a leaf sits at `leaf_index` among `leaf_count` leaves of a Merkle tree.
+///
+/// `siblings` holds the hashes needed to rebuild the root, from the leaf level upwards.
+/// Promoted nodes have no sibling on their level and are skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf_index: usize,
+    pub leaf_count: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Build the inclusion proof for the leaf at `index`, `None` if there is no such leaf.
+pub fn merkle_proof<L: AsRef<[u8]>>(leaves: &[L], index: usize) -> Option<MerkleProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut siblings = Vec::new();
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(|leaf| leaf_hash(leaf.as_ref())).collect();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if sibling < level.len() {
+            siblings.push(level[sibling]);
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        leaf_index: index,
+        leaf_count: leaves.len(),
+        siblings,
+    })
+}
+
+/// Check that `leaf` is included in the tree with the given `root`, as claimed by `proof`.
+pub fn verify_proof(root: &[u8], leaf: &[u8], proof: &MerkleProof) -> bool {
+    if proof.leaf_index >= proof.leaf_count {
+        return false;
+    }
+
+    let mut siblings = proof.siblings.iter();
+    let mut hash = leaf_hash(leaf);
+    let mut position = proof.leaf_index;
+    let mut level_len = proof.leaf_count;
+    while level_len > 1 {
+        if position % 2 == 1 {
+            match siblings.next() {
+                Some(left) => hash = node_hash(left, &hash),
+                None => return false,
+            }
+        } else if position + 1 < level_len {
+            match siblings.next() {
+                Some(right) => hash = node_hash(&hash, right),
+                None => return false,
+            }
+        }
+        position /= 2;
+        level_len = level_len.div_ceil(2);
+    }
+
+    // Every sibling must have been used, and the rebuilt root must match
+    siblings.next().is_none() && counted_root(proof.leaf_count, &hash).as_slice() == root
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks(2) yields one or two nodes"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    mod merkle_root {
+        mod success {
+            use crate::merkle::*;
+
+            #[test]
+            fn test_empty_root() {
+                let leaves: Vec<Vec<u8>> = Vec::new();
+                assert_eq!(merkle_root(&leaves), EMPTY_ROOT);
+            }
+
+            #[test]
+            fn test_single_leaf_root() {
+                assert_eq!(
+                    merkle_root(&[vec![1, 2, 3]]),
+                    counted_root(1, &leaf_hash(&[1, 2, 3]))
+                );
+            }
+
+            #[test]
+            fn test_odd_leaf_is_promoted() {
+                let leaves = [vec![1], vec![2], vec![3]];
+                let expected = counted_root(
+                    3,
+                    &node_hash(
+                        &node_hash(&leaf_hash(&[1]), &leaf_hash(&[2])),
+                        &leaf_hash(&[3]),
+                    ),
+                );
+                assert_eq!(merkle_root(&leaves), expected);
+            }
+        }
+
+        mod failure {
+            use crate::merkle::*;
+
+            #[test]
+            fn test_order_matters() {
+                let leaves = [vec![1], vec![2]];
+                let swapped = [vec![2], vec![1]];
+                assert_ne!(merkle_root(&leaves), merkle_root(&swapped));
+            }
+
+            #[test]
+            fn test_leaf_cannot_pose_as_node() {
+                // The concatenation of two leaf hashes is not accepted as a leaf of the parent
+                let left = leaf_hash(&[1]);
+                let right = leaf_hash(&[2]);
+                let forged: Vec<u8> = left.iter().chain(right.iter()).copied().collect();
+                assert_ne!(merkle_root(&[forged]), merkle_root(&[vec![1], vec![2]]));
+            }
+        }
+    }
+
+    mod merkle_proof {
+        mod success {
+            use crate::merkle::*;
+
+            #[test]
+            fn test_proof_for_every_leaf() {
+                for leaf_count in 1..=9u8 {
+                    let leaves: Vec<Vec<u8>> = (0..leaf_count).map(|i| vec![i]).collect();
+                    let root = merkle_root(&leaves);
+
+                    for (index, leaf) in leaves.iter().enumerate() {
+                        let proof = merkle_proof(&leaves, index).unwrap();
+                        assert!(verify_proof(&root, leaf, &proof));
+                    }
+                }
+            }
+
+            #[test]
+            fn test_single_leaf_proof_is_empty() {
+                let proof = merkle_proof(&[vec![1]], 0).unwrap();
+                assert!(proof.siblings.is_empty());
+                assert!(verify_proof(&merkle_root(&[vec![1]]), &[1], &proof));
+            }
+        }
+
+        mod failure {
+            use crate::merkle::*;
+
+            #[test]
+            fn test_proof_out_of_range() {
+                assert!(merkle_proof(&[vec![1], vec![2]], 2).is_none());
+                let empty: Vec<Vec<u8>> = Vec::new();
+                assert!(merkle_proof(&empty, 0).is_none());
+            }
+
+            #[test]
+            fn test_proof_for_wrong_leaf() {
+                let leaves = [vec![1], vec![2], vec![3]];
+                let root = merkle_root(&leaves);
+                let proof = merkle_proof(&leaves, 1).unwrap();
+
+                assert!(!verify_proof(&root, &[3], &proof));
+            }
+
+            #[test]
+            fn test_proof_with_wrong_index() {
+                let leaves = [vec![1], vec![2], vec![3], vec![4]];
+                let root = merkle_root(&leaves);
+                let mut proof = merkle_proof(&leaves, 1).unwrap();
+                proof.leaf_index = 0;
+
+                assert!(!verify_proof(&root, &[2], &proof));
+            }
+
+            #[test]
+            fn test_promoted_leaf_with_forged_shape() {
+                // Without the leaf count in the root, the promoted third leaf would pass for the
+                // second of two leaves, its sibling being the node over the first two
+                let leaves = [vec![1], vec![2], vec![3]];
+                let root = merkle_root(&leaves);
+                let proof = MerkleProof {
+                    leaf_index: 1,
+                    leaf_count: 2,
+                    siblings: vec![node_hash(&leaf_hash(&[1]), &leaf_hash(&[2]))],
+                };
+
+                assert!(!verify_proof(&root, &[3], &proof));
+            }
+
+            #[test]
+            fn test_proof_with_wrong_count() {
+                let leaves = [vec![1], vec![2], vec![3], vec![4]];
+                let root = merkle_root(&leaves);
+                let mut proof = merkle_proof(&leaves, 0).unwrap();
+                proof.leaf_count = 3;
+
+                assert!(!verify_proof(&root, &[1], &proof));
+            }
+
+            #[test]
+            fn test_proof_against_other_root() {
+                let leaves = [vec![1], vec![2]];
+                let proof = merkle_proof(&leaves, 0).unwrap();
+
+                assert!(!verify_proof(&EMPTY_ROOT, &[1], &proof));
+            }
+
+            #[test]
+            fn test_proof_with_extra_sibling() {
+                let leaves = [vec![1], vec![2]];
+                let root = merkle_root(&leaves);
+                let mut proof = merkle_proof(&leaves, 0).unwrap();
+                proof.siblings.push([0; 32]);
+
+                assert!(!verify_proof(&root, &[1], &proof));
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+    BaseFee, BlockReward, BurnFees, Config, Height, LengthFee, MaxBlockHeight, MaxBlockWeight,
+    NoFee, NoOverhead, TargetBlockWeight, WeightFee,
+};
+
+/// [`Config`] for tests, charging the default fees and burning them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MockConfig;
+impl Config for MockConfig {
+    type MaxBlockWeight = MaxBlockWeight;
+    type MaxBlockHeight = MaxBlockHeight;
+    type WeightType = u64;
+    type HeightType = Height;
+    type Hash = [u8; 32];
+    type Funds = u128;
+    type Nonce = u64;
+    type BaseFee = BaseFee;
+    type WeightFee = WeightFee;
+    type LengthFee = LengthFee;
+    type FeeDestination = BurnFees;
+    type TargetBlockWeight = TargetBlockWeight;
+    type BlockReward = BlockReward;
+    type BlockOverhead = NoOverhead;
+}
+
+/// [`MockConfig`] without fees, for tests following balances to the unit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeelessMockConfig;
+impl Config for FeelessMockConfig {
+    type MaxBlockWeight = MaxBlockWeight;
+    type MaxBlockHeight = MaxBlockHeight;
+    type WeightType = u64;
+    type HeightType = Height;
+    type Hash = [u8; 32];
+    type Funds = u128;
+    type Nonce = u64;
+    type BaseFee = NoFee;
+    type WeightFee = NoFee;
+    type LengthFee = NoFee;
+    type FeeDestination = BurnFees;
+    type TargetBlockWeight = TargetBlockWeight;
+    type BlockReward = BlockReward;
+    type BlockOverhead = NoOverhead;
+}
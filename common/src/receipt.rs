@@ -0,0 +1,28 @@
+use crate::types::{Config, TransactionError};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of an extrinsic applied in a block.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Receipt<T: Config> {
+    // Position of the extrinsic in the block
+    pub index: u32,
+    // Weight the extrinsic counted against the block, whether it succeeded or not
+    pub weight: T::WeightType,
+    pub result: Result<(), TransactionError<T>>,
+}
+
+impl<T: Config> Clone for Receipt<T> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            weight: self.weight.clone(),
+            result: self.result.clone(),
+        }
+    }
+}
+
+impl<T: Config> Receipt<T> {
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+}
@@ -0,0 +1,403 @@
+use crate::types::ProofError;
+use blake2::{Blake2s256, Digest};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// A node of the Merkle-Patricia trie. Keys are walked one nibble (half byte) at a time and
+/// children are always referenced by the hash of their encoding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TrieNode {
+    Empty,
+    Leaf {
+        partial: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        partial: Vec<u8>,
+        child: [u8; 32],
+    },
+    Branch {
+        children: Box<[Option<[u8; 32]>; 16]>,
+        value: Option<Vec<u8>>,
+    },
+}
+
+impl TrieNode {
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Trie nodes are always serializable")
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        hash_bytes(&self.encode())
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    hasher.update(bytes);
+    hasher.finalize().into()
+}
+
+// Split every byte of the key into its high and low nibble
+pub fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// The encoded trie nodes needed to look up a set of keys from nothing but the state root.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StorageProof {
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// An immutable Merkle-Patricia trie built from a set of key/value pairs.
+///
+/// The same set of pairs always produces the same root, regardless of insertion order.
+#[derive(Debug, Clone)]
+pub struct Trie {
+    root: [u8; 32],
+    nodes: HashMap<[u8; 32], TrieNode>,
+}
+
+impl Trie {
+    pub fn from_pairs<'a, I>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+    {
+        let mut items: Vec<(Vec<u8>, &[u8])> = pairs
+            .into_iter()
+            .map(|(key, value)| (to_nibbles(key), value.as_slice()))
+            .collect();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        items.dedup_by(|a, b| a.0 == b.0);
+
+        let mut trie = Trie {
+            root: [0; 32],
+            nodes: HashMap::new(),
+        };
+        trie.root = if items.is_empty() {
+            trie.store(TrieNode::Empty)
+        } else {
+            trie.build(&items, 0)
+        };
+        trie
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
+    pub fn node(&self, hash: &[u8; 32]) -> Option<&TrieNode> {
+        self.nodes.get(hash)
+    }
+
+    /// Build a read proof for `keys`. Keys missing from the trie get a proof of their absence.
+    pub fn prove(&self, keys: &[Vec<u8>]) -> StorageProof {
+        let mut hashes = BTreeSet::new();
+        for key in keys {
+            let nibbles = to_nibbles(key);
+            let mut rest = nibbles.as_slice();
+            let mut hash = self.root;
+
+            // Walk down the key's path, every node seen along the way is part of the proof
+            while let Some(node) = self.nodes.get(&hash) {
+                hashes.insert(hash);
+                match node {
+                    TrieNode::Extension { partial, child } if rest.starts_with(partial) => {
+                        rest = &rest[partial.len()..];
+                        hash = *child;
+                    }
+                    TrieNode::Branch { children, .. } if !rest.is_empty() => {
+                        match children[rest[0] as usize] {
+                            Some(child) => {
+                                rest = &rest[1..];
+                                hash = child;
+                            }
+                            None => break,
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        StorageProof {
+            nodes: hashes
+                .iter()
+                .map(|hash| self.nodes[hash].encode())
+                .collect(),
+        }
+    }
+
+    fn store(&mut self, node: TrieNode) -> [u8; 32] {
+        let hash = node.hash();
+        self.nodes.insert(hash, node);
+        hash
+    }
+
+    // Build the sub-trie for `items`, which are sorted and all share their first `depth` nibbles
+    fn build(&mut self, items: &[(Vec<u8>, &[u8])], depth: usize) -> [u8; 32] {
+        if let [(key, value)] = items {
+            return self.store(TrieNode::Leaf {
+                partial: key[depth..].to_vec(),
+                value: value.to_vec(),
+            });
+        }
+
+        // Sorted keys: the prefix shared by the first and last key is shared by all of them
+        let first = &items[0].0[depth..];
+        let last = &items[items.len() - 1].0[depth..];
+        let shared = first
+            .iter()
+            .zip(last.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if shared > 0 {
+            let child = self.build_branch(items, depth + shared);
+            return self.store(TrieNode::Extension {
+                partial: first[..shared].to_vec(),
+                child,
+            });
+        }
+
+        self.build_branch(items, depth)
+    }
+
+    fn build_branch(&mut self, items: &[(Vec<u8>, &[u8])], depth: usize) -> [u8; 32] {
+        let mut children: Box<[Option<[u8; 32]>; 16]> = Box::new([None; 16]);
+        let mut value = None;
+
+        // A key ending exactly here sorts first and is kept in the branch itself
+        let mut rest = items;
+        if let Some(((key, node_value), tail)) = items.split_first() {
+            if key.len() == depth {
+                value = Some(node_value.to_vec());
+                rest = tail;
+            }
+        }
+
+        for nibble in 0..16u8 {
+            let start = rest.partition_point(|(key, _)| key[depth] < nibble);
+            let end = rest.partition_point(|(key, _)| key[depth] <= nibble);
+            if start < end {
+                children[nibble as usize] = Some(self.build(&rest[start..end], depth + 1));
+            }
+        }
+
+        self.store(TrieNode::Branch { children, value })
+    }
+}
+
+/// Look up `key` in the trie with the given `root`, using only the nodes in `proof`.
+///
+/// Returns `Ok(None)` when the proof shows the key is absent, and an error when the proof
+/// does not hold enough nodes to tell either way.
+pub fn verify_proof(
+    root: &[u8; 32],
+    proof: &StorageProof,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let mut nodes = HashMap::new();
+    for encoded in &proof.nodes {
+        let node: TrieNode =
+            bincode::deserialize(encoded).map_err(|e| ProofError::InvalidNode(e.to_string()))?;
+        nodes.insert(hash_bytes(encoded), node);
+    }
+
+    let nibbles = to_nibbles(key);
+    let mut rest = nibbles.as_slice();
+    let mut hash = *root;
+    loop {
+        let node = nodes
+            .get(&hash)
+            .ok_or_else(|| ProofError::MissingNode(hex::encode(hash)))?;
+        match node {
+            TrieNode::Empty => return Ok(None),
+            TrieNode::Leaf { partial, value } => {
+                return Ok((partial.as_slice() == rest).then(|| value.clone()))
+            }
+            TrieNode::Extension { partial, child } => {
+                if !rest.starts_with(partial) {
+                    return Ok(None);
+                }
+                rest = &rest[partial.len()..];
+                hash = *child;
+            }
+            TrieNode::Branch { children, value } => {
+                let Some((nibble, tail)) = rest.split_first() else {
+                    return Ok(value.clone());
+                };
+                match children[*nibble as usize] {
+                    Some(child) => {
+                        rest = tail;
+                        hash = child;
+                    }
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn pairs(entries: &[(&[u8], &[u8])]) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_vec(), value.to_vec()))
+            .collect()
+    }
+
+    mod trie_root {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_empty_trie_root() {
+                let empty = BTreeMap::new();
+                let trie = Trie::from_pairs(&empty);
+                assert_eq!(trie.root(), TrieNode::Empty.hash());
+            }
+
+            #[test]
+            fn test_root_is_order_independent() {
+                let entries = pairs(&[(&[1, 2], &[10]), (&[1, 3], &[20]), (&[200], &[30])]);
+
+                let forward = Trie::from_pairs(entries.iter());
+                let reversed = Trie::from_pairs(entries.iter().rev());
+                assert_eq!(forward.root(), reversed.root());
+            }
+
+            #[test]
+            fn test_key_prefix_of_another_key() {
+                let entries = pairs(&[(&[1], &[10]), (&[1, 2], &[20]), (&[1, 2, 3], &[30])]);
+
+                let trie = Trie::from_pairs(&entries);
+                assert!(trie.node(&trie.root()).is_some());
+                assert_ne!(trie.root(), TrieNode::Empty.hash());
+            }
+
+            #[test]
+            fn test_to_nibbles() {
+                assert_eq!(to_nibbles(&[0xab, 0x01]), vec![0x0a, 0x0b, 0x00, 0x01]);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_different_values_different_root() {
+                let first = pairs(&[(&[1, 2], &[10]), (&[1, 3], &[20])]);
+                let second = pairs(&[(&[1, 2], &[10]), (&[1, 3], &[21])]);
+
+                assert_ne!(
+                    Trie::from_pairs(&first).root(),
+                    Trie::from_pairs(&second).root()
+                );
+            }
+
+            #[test]
+            fn test_different_keys_different_root() {
+                let first = pairs(&[(&[1, 2], &[10])]);
+                let second = pairs(&[(&[1, 3], &[10])]);
+
+                assert_ne!(
+                    Trie::from_pairs(&first).root(),
+                    Trie::from_pairs(&second).root()
+                );
+            }
+        }
+    }
+
+    mod storage_proof {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_proof_of_present_keys() {
+                let entries = pairs(&[
+                    (&[1], &[10]),
+                    (&[1, 2], &[20]),
+                    (&[1, 2, 3], &[30]),
+                    (&[7, 7], &[40]),
+                ]);
+                let trie = Trie::from_pairs(&entries);
+
+                let keys: Vec<Vec<u8>> = entries.keys().cloned().collect();
+                let proof = trie.prove(&keys);
+                for (key, value) in &entries {
+                    assert_eq!(
+                        verify_proof(&trie.root(), &proof, key).unwrap(),
+                        Some(value.clone())
+                    );
+                }
+            }
+
+            #[test]
+            fn test_proof_of_absent_key() {
+                let entries = pairs(&[(&[1, 2], &[20]), (&[1, 3], &[30])]);
+                let trie = Trie::from_pairs(&entries);
+
+                let proof = trie.prove(&[vec![1, 4]]);
+                assert_eq!(verify_proof(&trie.root(), &proof, &[1, 4]).unwrap(), None);
+            }
+
+            #[test]
+            fn test_proof_only_holds_the_path() {
+                let entries = pairs(&[(&[1], &[10]), (&[2], &[20]), (&[3], &[30])]);
+                let trie = Trie::from_pairs(&entries);
+
+                // Extension over the shared high nibble, the branch below it and a single leaf
+                let proof = trie.prove(&[vec![1]]);
+                assert_eq!(proof.nodes.len(), 3);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_proof_against_other_root() {
+                let entries = pairs(&[(&[1], &[10]), (&[2], &[20])]);
+                let trie = Trie::from_pairs(&entries);
+                let proof = trie.prove(&[vec![1]]);
+
+                let other = Trie::from_pairs(&pairs(&[(&[1], &[11]), (&[2], &[20])]));
+                assert!(matches!(
+                    verify_proof(&other.root(), &proof, &[1]),
+                    Err(ProofError::MissingNode(_))
+                ));
+            }
+
+            #[test]
+            fn test_proof_missing_the_key_path() {
+                let entries = pairs(&[(&[1], &[10]), (&[2], &[20])]);
+                let trie = Trie::from_pairs(&entries);
+                let proof = trie.prove(&[vec![1]]);
+
+                assert!(matches!(
+                    verify_proof(&trie.root(), &proof, &[2]),
+                    Err(ProofError::MissingNode(_))
+                ));
+            }
+
+            #[test]
+            fn test_proof_with_garbage_node() {
+                let trie = Trie::from_pairs(&pairs(&[(&[1], &[10])]));
+                let mut proof = trie.prove(&[vec![1]]);
+                proof.nodes.push(vec![0xff; 3]);
+
+                assert!(matches!(
+                    verify_proof(&trie.root(), &proof, &[1]),
+                    Err(ProofError::InvalidNode(_))
+                ));
+            }
+        }
+    }
+}
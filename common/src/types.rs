@@ -1,6 +1,6 @@
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::ops::Add;
+use std::ops::{Add, Div, Mul};
 use std::{
     // Add this line to import the Display trait
     fmt::{Debug, Display},
@@ -19,14 +19,19 @@ pub trait Config {
         + From<u64>
         + AddAssign
         + PartialOrd
-        + Display;
+        + Display
+        + Into<u64>;
     type HeightType: Clone
         + Serialize
         + DeserializeOwned
         + Debug
         + Display
         + PartialEq
+        + Eq
+        + Ord
+        + std::hash::Hash
         + From<u64>
+        + Into<u64>
         + Sub<Output = Self::HeightType>
         + Into<Vec<u8>>
         + Zero
@@ -38,6 +43,9 @@ pub trait Config {
         + AsRef<[u8]>
         + Copy
         + PartialEq
+        + Eq
+        + Ord
+        + std::hash::Hash
         + From<[u8; 32]>
         + Default;
     type Funds: Copy
@@ -47,12 +55,38 @@ pub trait Config {
         + From<u128>
         + PartialOrd
         + Add<Output = Self::Funds>
-        + Sub<Output = Self::Funds>;
+        + Sub<Output = Self::Funds>
+        + Mul<Output = Self::Funds>
+        + Div<Output = Self::Funds>
+        + CheckedAdd
+        + CheckedSub
+        + CheckedMul;
+    type Nonce: Copy
+        + Debug
+        + Serialize
+        + DeserializeOwned
+        + PartialEq
+        + PartialOrd
+        + Add<Output = Self::Nonce>
+        + Zero
+        + One;
+    // Fee schedule of signed transactions, see `SignedTransaction::fee`
+    type BaseFee: Get<Self::Funds>;
+    type WeightFee: Get<Self::Funds>;
+    type LengthFee: Get<Self::Funds>;
+    type FeeDestination: Get<FeeDestination<Self::Hash>>;
+    // Block weight the fee multiplier steers towards, see `FeeMultiplier::next`
+    type TargetBlockWeight: Get<Self::WeightType>;
+    // Funds credited to the author of every block by its coinbase inherent
+    type BlockReward: Get<Self::Funds>;
+    // Weight of the runtime's own work in every block, consumed by its `on_initialize` hook
+    type BlockOverhead: Get<Self::WeightType>;
 }
 
 pub struct MaxBlockHeight;
 pub struct FundSum;
 pub struct MaxBlockWeight;
+pub struct TargetBlockWeight;
 
 impl Get<u64> for MaxBlockWeight {
     fn get() -> u64 {
@@ -60,18 +94,160 @@ impl Get<u64> for MaxBlockWeight {
     }
 }
 
+// Half full blocks
+impl Get<u64> for TargetBlockWeight {
+    fn get() -> u64 {
+        100
+    }
+}
+
+// For runtimes doing no work of their own in a block
+pub struct NoOverhead;
+
+impl Get<u64> for NoOverhead {
+    fn get() -> u64 {
+        0
+    }
+}
+
 impl Get<Height> for MaxBlockHeight {
     fn get() -> Height {
         Height::from(100000)
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BaseFee;
+pub struct WeightFee;
+pub struct LengthFee;
+// For chains where transactions are free
+pub struct NoFee;
+
+impl Get<u128> for BaseFee {
+    fn get() -> u128 {
+        10
+    }
+}
+
+impl Get<u128> for WeightFee {
+    fn get() -> u128 {
+        1
+    }
+}
+
+impl Get<u128> for LengthFee {
+    fn get() -> u128 {
+        1
+    }
+}
+
+impl Get<u128> for NoFee {
+    fn get() -> u128 {
+        0
+    }
+}
+
+pub struct BlockReward;
+
+impl Get<u128> for BlockReward {
+    fn get() -> u128 {
+        100
+    }
+}
+
+/// Where the fees paid by transactions end up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FeeDestination<Hash> {
+    // Credited to the given account, which is created if needed
+    Treasury(Hash),
+    // Taken out of the total issuance
+    Burn,
+    // Credited to the author of the block including the transaction
+    Author,
+}
+
+pub struct BurnFees;
+
+impl<Hash> Get<FeeDestination<Hash>> for BurnFees {
+    fn get() -> FeeDestination<Hash> {
+        FeeDestination::Burn
+    }
+}
+
+pub struct AuthorFees;
+
+impl<Hash> Get<FeeDestination<Hash>> for AuthorFees {
+    fn get() -> FeeDestination<Hash> {
+        FeeDestination::Author
+    }
+}
+
+/// Factor applied to transaction fees, a fixed point number where `ACCURACY` stands for one.
+///
+/// It follows block fullness: it goes up after blocks heavier than the target weight and decays
+/// after lighter ones, never going below one.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct FeeMultiplier(pub u128);
+
+impl FeeMultiplier {
+    pub const ACCURACY: u128 = 1_000_000;
+    pub const MIN: FeeMultiplier = FeeMultiplier(Self::ACCURACY);
+    pub const MAX: FeeMultiplier = FeeMultiplier(100 * Self::ACCURACY);
+    // A block moves the multiplier by at most an eighth, as in EIP-1559
+    const MAX_CHANGE_DENOMINATOR: u128 = 8;
+
+    /// Scale `fee` by the multiplier, `None` if it overflows.
+    pub fn apply<F>(&self, fee: F) -> Option<F>
+    where
+        F: CheckedMul + Div<Output = F> + From<u128>,
+    {
+        Some(fee.checked_mul(&F::from(self.0))? / F::from(Self::ACCURACY))
+    }
+
+    /// Multiplier of the next block, given the weight of the current one.
+    pub fn next(&self, block_weight: u64, target_weight: u64) -> FeeMultiplier {
+        if target_weight == 0 {
+            return *self;
+        }
+
+        let (weight, target) = (block_weight as u128, target_weight as u128);
+        let next = if weight >= target {
+            self.0 + self.0 * (weight - target) / target / Self::MAX_CHANGE_DENOMINATOR
+        } else {
+            self.0 - self.0 * (target - weight) / target / Self::MAX_CHANGE_DENOMINATOR
+        };
+        FeeMultiplier(next.clamp(Self::MIN.0, Self::MAX.0))
+    }
+}
+
+impl One for FeeMultiplier {
+    fn one() -> Self {
+        FeeMultiplier(Self::ACCURACY)
+    }
+}
+
+impl Default for FeeMultiplier {
+    fn default() -> Self {
+        Self::one()
+    }
+}
+
+/// Who a call is dispatched on behalf of.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Origin<Hash> {
+    // The signer of the transaction
+    Signed(Hash),
+    // The chain itself, reached through the sudo key
+    Root,
+    // Unsigned transactions
+    None,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Height(pub u64);
 
-impl Into<Vec<u8>> for Height {
-    fn into(self) -> Vec<u8> {
-        self.0.to_le_bytes().to_vec()
+impl From<Height> for Vec<u8> {
+    fn from(val: Height) -> Self {
+        val.0.to_le_bytes().to_vec()
     }
 }
 
@@ -81,6 +257,12 @@ impl From<u64> for Height {
     }
 }
 
+impl From<Height> for u64 {
+    fn from(val: Height) -> Self {
+        val.0
+    }
+}
+
 impl Sub for Height {
     type Output = Height;
 
@@ -121,12 +303,101 @@ pub trait One {
     fn one() -> Self;
 }
 
+impl Zero for u64 {
+    fn zero() -> Self {
+        0
+    }
+}
+
+impl One for u64 {
+    fn one() -> Self {
+        1
+    }
+}
+
+/// Addition that reports overflow instead of panicking or wrapping.
+pub trait CheckedAdd: Sized {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl CheckedAdd for u128 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u128::checked_add(*self, *other)
+    }
+}
+
+/// Subtraction that reports underflow instead of panicking or wrapping.
+pub trait CheckedSub: Sized {
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+}
+
+impl CheckedSub for u128 {
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        u128::checked_sub(*self, *other)
+    }
+}
+
+/// Multiplication that reports overflow instead of panicking or wrapping.
+pub trait CheckedMul: Sized {
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+}
+
+impl CheckedMul for u128 {
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        u128::checked_mul(*self, *other)
+    }
+}
+
 pub trait Get<T> {
     fn get() -> T;
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Outer call enum of the runtime, every variant is routed to the pallet owning it.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// The bounds of `Config` are enough, `T` itself never gets encoded
+#[serde(bound = "")]
 pub enum TransactionType<T>
+where
+    T: Config,
+{
+    Balances(BalancesCall<T>),
+    // Inherent inserted first in every block by its producer, issues the block reward to the author
+    Coinbase { author: T::Hash },
+    // Dispatch `call` with the Root origin, only the sudo key can sign it
+    Sudo { call: Box<TransactionType<T>> },
+}
+
+impl<T: Config> Clone for TransactionType<T> {
+    fn clone(&self) -> Self {
+        match &self {
+            Self::Balances(call) => Self::Balances(call.clone()),
+            Self::Coinbase { author } => Self::Coinbase { author: *author },
+            Self::Sudo { call } => Self::Sudo { call: call.clone() },
+        }
+    }
+}
+
+impl<T: Config> TransactionType<T> {
+    pub fn weight(&self) -> T::WeightType {
+        match self {
+            Self::Balances(call) => call.weight(),
+            // Inherents are mandatory, they must fit in any block
+            Self::Coinbase { .. } => T::WeightType::from(0),
+            Self::Sudo { call } => call.weight(),
+        }
+    }
+}
+
+impl<T: Config> From<BalancesCall<T>> for TransactionType<T> {
+    fn from(call: BalancesCall<T>) -> Self {
+        Self::Balances(call)
+    }
+}
+
+/// Calls of the balances pallet.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum BalancesCall<T>
 where
     T: Config,
 {
@@ -149,34 +420,34 @@ where
     },
 }
 
-impl<T: Config> Clone for TransactionType<T> {
+impl<T: Config> Clone for BalancesCall<T> {
     fn clone(&self) -> Self {
         match &self {
             Self::Transfer { from, to, amount } => Self::Transfer {
-                from: from.clone(),
-                to: to.clone(),
-                amount: amount.clone(),
+                from: *from,
+                to: *to,
+                amount: *amount,
             },
             Self::Mint { to, amount } => Self::Mint {
-                to: to.clone(),
-                amount: amount.clone(),
+                to: *to,
+                amount: *amount,
             },
             Self::Burn { from, amount } => Self::Burn {
-                from: from.clone(),
-                amount: amount.clone(),
+                from: *from,
+                amount: *amount,
             },
             Self::AccountCreation {
                 account_id,
                 balance,
             } => Self::AccountCreation {
-                account_id: account_id.clone(),
-                balance: balance.clone(),
+                account_id: *account_id,
+                balance: *balance,
             },
         }
     }
 }
 
-impl<T: Config> TransactionType<T> {
+impl<T: Config> BalancesCall<T> {
     pub fn weight(&self) -> T::WeightType {
         match self {
             Self::Transfer { .. } => T::WeightType::from(10),
@@ -187,18 +458,73 @@ impl<T: Config> TransactionType<T> {
     }
 }
 
-// TODO: Implement it, may have issues with current Option usage
-#[derive(Debug)]
+/// Why an extrinsic of a block was not applied.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Error)]
 pub enum TransactionError<T: Config> {
+    #[error("Account not found: {0:?}")]
     AccountNotFound(T::Hash),
+    #[error("Insufficient balance for {account_id:?}. Balance: {balance:?}, Amount: {amount:?}")]
     InsufficientBalance {
         account_id: T::Hash,
         balance: T::Funds,
         amount: T::Funds,
     },
+    #[error("Account already exists: {0:?}")]
+    AccountAlreadyExists(T::Hash),
+    #[error("Invalid transaction signature")]
+    InvalidSignature,
+    #[error("Call is not allowed from this origin")]
+    BadOrigin,
+    #[error("Invalid transaction nonce. Expected: {expected:?}, Got: {got:?}")]
+    InvalidNonce { expected: T::Nonce, got: T::Nonce },
+    #[error("{account_id:?} cannot pay the transaction fee. Balance: {balance:?}, Fee: {fee:?}")]
+    CannotPayFee {
+        account_id: T::Hash,
+        balance: T::Funds,
+        fee: T::Funds,
+    },
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
 }
 
-#[derive(Debug, Clone, Error)]
+impl<T: Config> Clone for TransactionError<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::AccountNotFound(account_id) => Self::AccountNotFound(*account_id),
+            Self::InsufficientBalance {
+                account_id,
+                balance,
+                amount,
+            } => Self::InsufficientBalance {
+                account_id: *account_id,
+                balance: *balance,
+                amount: *amount,
+            },
+            Self::AccountAlreadyExists(account_id) => Self::AccountAlreadyExists(*account_id),
+            Self::InvalidSignature => Self::InvalidSignature,
+            Self::BadOrigin => Self::BadOrigin,
+            Self::InvalidNonce { expected, got } => Self::InvalidNonce {
+                expected: *expected,
+                got: *got,
+            },
+            Self::CannotPayFee {
+                account_id,
+                balance,
+                fee,
+            } => Self::CannotPayFee {
+                account_id: *account_id,
+                balance: *balance,
+                fee: *fee,
+            },
+            Self::Overflow => Self::Overflow,
+            Self::Storage(e) => Self::Storage(e.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Error)]
 pub enum StorageError {
     #[error("Serialization Error: {0}")]
     SerializationError(String),
@@ -222,6 +548,16 @@ pub enum StorageError {
 pub enum StfError {
     #[error("Failed to execute block: {0}")]
     BlockExecutionError(String),
+    #[error("Extrinsics root mismatch. Expected: {expected}, Computed: {computed}")]
+    ExtrinsicsRootMismatch { expected: String, computed: String },
+    #[error("State root mismatch. Expected: {expected}, Computed: {computed}")]
+    StateRootMismatch { expected: String, computed: String },
+    #[error("Total issuance mismatch. Issuance: {issuance}, Sum of balances: {balances}")]
+    IssuanceMismatch { issuance: String, balances: String },
+    #[error("Invalid inherent: {0}")]
+    InvalidInherent(String),
+    #[error("Invalid genesis config: {0}")]
+    InvalidGenesis(String),
     #[error("Storage error: {0}")]
     Storage(#[from] StorageError),
 }
@@ -232,6 +568,35 @@ pub enum ConsensusError {
     ImportBlockError(String),
     #[error("Stf error: {0}")]
     Stf(#[from] StfError),
+    #[error("Block tree error: {0}")]
+    BlockTree(#[from] BlockTreeError),
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum BlockTreeError {
+    #[error("Block is already in the tree: {0}")]
+    AlreadyKnown(String),
+    #[error("Parent block is not in the tree: {0}")]
+    UnknownParent(String),
+    #[error("Block height {height} does not follow its parent at {parent_height}")]
+    InvalidHeight {
+        height: String,
+        parent_height: String,
+    },
+    #[error("Tree already has a genesis block: {0}")]
+    GenesisExists(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum ProofError {
+    #[error("State root is not a 32 byte hash: {0}")]
+    InvalidRoot(String),
+    #[error("Proof is missing trie node: {0}")]
+    MissingNode(String),
+    #[error("Proof contains an invalid trie node: {0}")]
+    InvalidNode(String),
+    #[error("Failed to decode proven value: {0}")]
+    ValueDecodeError(String),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -239,25 +604,23 @@ pub enum StoragePrefix {
     Account,
     Block,
     Extrinsic,
+    TotalIssuance,
+    Receipt,
+    Event,
+    FeeMultiplier,
+    Sudo,
+    BestBlock,
+}
+
+impl StoragePrefix {
+    /// Prefixes of the block bookkeeping. It is stored along with the state but left out of the
+    /// state root, which the stored blocks carry themselves.
+    pub const BOOKKEEPING: [StoragePrefix; 2] = [StoragePrefix::Block, StoragePrefix::BestBlock];
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
-    // Define a mock configuration struct
-    #[derive(Debug, PartialEq)]
-    struct MockConfig;
-
-    // Implement the Config trait for MockConfig
-    impl Config for MockConfig {
-        type MaxBlockWeight = MaxBlockWeight;
-        type MaxBlockHeight = MaxBlockHeight;
-        type WeightType = u64;
-        type HeightType = Height;
-        type Hash = [u8; 32];
-        type Funds = u128;
-    }
+    use crate::mock::MockConfig;
 
     mod test_height {
         mod success {
@@ -304,45 +667,98 @@ mod tests {
         }
     }
 
+    mod test_fee_multiplier {
+        mod success {
+            use crate::types::{FeeMultiplier, One};
+
+            #[test]
+            fn test_apply_multiplier() {
+                assert_eq!(FeeMultiplier::one().apply(250u128), Some(250));
+                assert_eq!(FeeMultiplier(1_500_000).apply(250u128), Some(375));
+            }
+
+            #[test]
+            fn test_multiplier_rises_above_target() {
+                // A full block, twice the target, adds an eighth
+                let next = FeeMultiplier::one().next(200, 100);
+                assert_eq!(next, FeeMultiplier(1_125_000));
+                assert!(next.next(200, 100) > next);
+            }
+
+            #[test]
+            fn test_multiplier_decays_below_target() {
+                let multiplier = FeeMultiplier(2_000_000);
+                assert_eq!(multiplier.next(0, 100), FeeMultiplier(1_750_000));
+                assert_eq!(multiplier.next(100, 100), multiplier);
+            }
+        }
+
+        mod failure {
+            use crate::types::{FeeMultiplier, One};
+
+            #[test]
+            fn test_multiplier_stays_within_bounds() {
+                assert_eq!(FeeMultiplier::one().next(0, 100), FeeMultiplier::MIN);
+                assert_eq!(FeeMultiplier::MAX.next(200, 100), FeeMultiplier::MAX);
+            }
+
+            #[test]
+            fn test_multiplier_without_target() {
+                let multiplier = FeeMultiplier(2_000_000);
+                assert_eq!(multiplier.next(200, 0), multiplier);
+            }
+
+            #[test]
+            fn test_apply_multiplier_overflow() {
+                assert_eq!(FeeMultiplier(2_000_000).apply(u128::MAX), None);
+            }
+        }
+    }
+
     mod test_transaction_type {
         mod success {
-            use crate::types::{tests::MockConfig, TransactionType};
+            use crate::types::{tests::MockConfig, BalancesCall, TransactionType};
 
             #[test]
             fn test_transaction_type_weight() {
-                let transfer = TransactionType::<MockConfig>::Transfer {
+                let transfer = BalancesCall::<MockConfig>::Transfer {
                     from: [0; 32],
                     to: [1; 32],
                     amount: 100,
                 };
                 assert_eq!(transfer.weight(), 10);
 
-                let mint = TransactionType::<MockConfig>::Mint {
+                let mint = BalancesCall::<MockConfig>::Mint {
                     to: [2; 32],
                     amount: 50,
                 };
                 assert_eq!(mint.weight(), 15);
 
-                let burn = TransactionType::<MockConfig>::Burn {
+                let burn = BalancesCall::<MockConfig>::Burn {
                     from: [3; 32],
                     amount: 25,
                 };
                 assert_eq!(burn.weight(), 20);
 
-                let account_creation = TransactionType::<MockConfig>::AccountCreation {
+                let account_creation = BalancesCall::<MockConfig>::AccountCreation {
                     account_id: [4; 32],
                     balance: 1000,
                 };
                 assert_eq!(account_creation.weight(), 7);
+
+                let sudo = TransactionType::<MockConfig>::Sudo {
+                    call: Box::new(mint.into()),
+                };
+                assert_eq!(sudo.weight(), 15);
             }
 
             #[test]
             fn test_transaction_type_clone() {
-                let transfer = TransactionType::<MockConfig>::Transfer {
+                let transfer = TransactionType::<MockConfig>::Balances(BalancesCall::Transfer {
                     from: [0; 32],
                     to: [1; 32],
                     amount: 100,
-                };
+                });
                 let cloned_transfer = transfer.clone();
                 assert_eq!(transfer, cloned_transfer);
             }
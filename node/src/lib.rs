@@ -1,42 +1,115 @@
-use common::types::{self, StfError};
-use common::types::{Config, ConsensusError};
+use common::block::BlockTrait;
+use common::crypto::Keyring;
+use common::event::BlockEvents;
+use common::types::{BlockTreeError, StfError};
+use common::types::{Config, ConsensusError, FeeMultiplier, Get, One, Zero};
 use common::{block, extrinsics};
+use runtime::plugin::StoragePlugin;
 use runtime::stf::{self, Stf};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::Debug;
 use std::{
     collections::VecDeque,
+    sync::mpsc::{self, Receiver, Sender},
     sync::{Arc, Mutex},
 };
 
-use crate::{block::Block, extrinsics::SignedTransaction};
+use crate::block::Block;
+use crate::extrinsics::SignedTransaction;
+use crate::tree::{BlockTree, TreeRoute};
+
+pub mod simulator;
+pub mod tree;
+
+/// Most blocks asked for in a single request while syncing.
+pub const MAX_BLOCKS_PER_REQUEST: u64 = 64;
+
+/// A block asked for by its height on the best chain, or by its hash on any branch.
+#[derive(Debug, Clone)]
+pub enum BlockId<T: Config> {
+    Height(T::HeightType),
+    Hash(T::Hash),
+}
 
 /// A simulated network of nodes that can send blocks to other nodes.
 pub trait Nodes<T: Config>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    /// Return the block with the given number.
+    /// Return the block with the given height or hash, from the first peer that has it.
     ///
     /// Should be used when a node encounters a new canonical fork and needs to reorg to the new chain.
-    fn request_block(&self, block_number: T::MaxBlockHeight) -> Block<T>;
+    fn request_block(&self, block_id: BlockId<T>) -> Option<Block<T>>;
+
+    /// Return up to `count` blocks of the best chain among the peers, starting at height `from`.
+    fn request_blocks(&self, from: T::HeightType, count: u64) -> Vec<Block<T>>;
+
+    /// Height and hash of the best block among the peers.
+    fn best_peer_head(&self) -> Option<(T::HeightType, T::Hash)>;
+}
+
+/// Blocks a node serves to the peers syncing from it.
+pub trait BlockSource<T: Config> {
+    /// Height and hash of the best block.
+    fn best_head(&self) -> Option<(T::HeightType, T::Hash)>;
+
+    fn block(&self, block_id: &BlockId<T>) -> Option<Block<T>>;
+
+    /// Up to `count` blocks of the best chain, starting at height `from`.
+    fn blocks(&self, from: &T::HeightType, count: u64) -> Vec<Block<T>>;
 }
 
+/// A node blocks can be requested from.
+pub type Peer<T> = Arc<dyn BlockSource<T> + Send + Sync>;
+
 pub trait ConsensusT<T: Config>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
     /// Import and validate a new block and execute the STF.
     ///
-    /// The consensus protocol should assume the highest block is the best block (i.e. the canonical chain).
-    /// Since this is a simplified example of a consensus protocol, when a reorg happens, we can call into the [`Nodes`]
-    /// to request every block number we don't have.
-    fn import_block(
+    /// The block is authored locally: it is sealed on top of the block below it in the best chain,
+    /// its parent hash and state root being set here.
+    fn import_block<S: StoragePlugin>(
         &self,
         block: &mut Block<T>,
-        stf: &mut stf::SimpleStf<T>,
+        stf: &mut stf::SimpleStf<T, S>,
     ) -> Result<(), ConsensusError>;
+
+    /// Import a block sealed elsewhere, e.g. received from a peer, on whichever branch it extends.
+    ///
+    /// The highest block is the best block (i.e. the canonical chain), the lowest hash winning
+    /// among blocks of the same height. When the block makes another branch the best one, the
+    /// STF is reverted to the common ancestor and the new branch executed on top of it. Since
+    /// this is a simplified example of a consensus protocol, blocks we don't have can be requested
+    /// from the [`Nodes`].
+    fn import_sealed_block<S: StoragePlugin>(
+        &self,
+        block: Block<T>,
+        stf: &mut stf::SimpleStf<T, S>,
+    ) -> Result<ImportOutcome<T>, ConsensusError>;
+
+    /// Catch up with the best chain among the peers, importing its blocks we don't have in order.
+    ///
+    /// Blocks are requested by height from above our best block. When the peers' chain forks off
+    /// below it, the missing ancestors are requested by hash first. Returns what importing every
+    /// block did to the best chain.
+    fn sync<S: StoragePlugin>(
+        &self,
+        stf: &mut stf::SimpleStf<T, S>,
+    ) -> Result<Vec<ImportOutcome<T>>, ConsensusError>;
+}
+
+/// What importing a sealed block did to the best chain.
+#[derive(Debug, Clone)]
+pub enum ImportOutcome<T: Config> {
+    /// The block was executed on top of the best block, and is the best block now.
+    Extended(T::Hash),
+    /// The block is kept on a branch that is not the best one, without being executed.
+    SideBranch(T::Hash),
+    /// The block made another branch the best one, the chain was reorganised onto it.
+    Reorg(TreeRoute<T>),
 }
 
 #[derive(Debug)]
@@ -45,44 +118,181 @@ where
     T: Serialize + DeserializeOwned + Debug,
 {
     pub node_network: N,
+    // Every imported block, whichever branch it is on
+    pub block_tree: Mutex<BlockTree<T>>,
     pub phantom: std::marker::PhantomData<T>,
 }
 
+impl<T: Config, N: Nodes<T>> Consensus<T, N>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    pub fn new(node_network: N) -> Self {
+        Consensus {
+            node_network,
+            block_tree: Mutex::new(BlockTree::new()),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    // Hash of the best block executed by the STF. The tree is empty after a restart, it is seeded
+    // then with the best chain down to the oldest block the STF can still be reverted to, so that
+    // a branch forking off any of them can still be switched to
+    fn head<S: StoragePlugin>(
+        tree: &mut BlockTree<T>,
+        stf: &stf::SimpleStf<T, S>,
+    ) -> Result<Option<T::Hash>, ConsensusError> {
+        let Some(best_block) = stf.best_block().map_err(StfError::Storage)? else {
+            return Ok(None);
+        };
+        let hash = stf
+            .get_block_hash(best_block.clone())
+            .map_err(StfError::Storage)?;
+        if tree.is_empty() {
+            let mut height = best_block.clone();
+            while height != T::HeightType::zero() {
+                let parent = height.clone() - T::HeightType::one();
+                let parent_hash = stf
+                    .get_block_hash(parent.clone())
+                    .map_err(StfError::Storage)?;
+                if stf.state_at(parent_hash).is_err() {
+                    break;
+                }
+                height = parent;
+            }
+
+            // Oldest first, every block extends the one before it
+            while height <= best_block {
+                let hash = stf
+                    .get_block_hash(height.clone())
+                    .map_err(StfError::Storage)?;
+                tree.insert(stf.get_block(hash).map_err(StfError::Storage)?)?;
+                height += T::HeightType::one();
+            }
+        }
+        Ok(Some(hash))
+    }
+
+    // Validate a sealed block against the best block of the STF and execute it
+    fn execute<S: StoragePlugin>(
+        stf: &mut stf::SimpleStf<T, S>,
+        block: &Block<T>,
+    ) -> Result<(), ConsensusError> {
+        stf.validate_block(block.clone())
+            .map_err(|e| ConsensusError::ImportBlockError(e.to_string()))?;
+        stf.execute_block(block.clone()).map_err(|e| match e {
+            // A block not leading to the state root it claims is as invalid as one failing validation
+            StfError::StateRootMismatch { .. } => ConsensusError::ImportBlockError(e.to_string()),
+            e => ConsensusError::Stf(e),
+        })?;
+        Ok(())
+    }
+
+    // Move the STF from `head` over to `new_best`, both being in the tree. Should a block of the
+    // new branch be invalid, or the branch fork off below the pruning depth, the whole branch is
+    // dropped from the tree and the STF is put back on `head`, which is the best block again
+    fn switch_head<S: StoragePlugin>(
+        tree: &mut BlockTree<T>,
+        stf: &mut stf::SimpleStf<T, S>,
+        head: T::Hash,
+        new_best: T::Hash,
+    ) -> Result<TreeRoute<T>, ConsensusError> {
+        let route = tree.route(&head, &new_best).ok_or_else(|| {
+            ConsensusError::ImportBlockError(format!(
+                "No route from {} to {} in the block tree",
+                hex::encode(head),
+                hex::encode(new_best)
+            ))
+        })?;
+
+        // A branch forking off below the pruning depth can never be switched to
+        if let Err(e) = stf.state_at(route.common_ancestor) {
+            tree.remove(&route.enacted[0]);
+            return Err(ConsensusError::ImportBlockError(format!(
+                "Branch forks off at {}, whose state is gone: {}",
+                hex::encode(route.common_ancestor),
+                e
+            )));
+        }
+
+        for _ in &route.retracted {
+            stf.revert_block()?;
+        }
+
+        for (executed, hash) in route.enacted.iter().enumerate() {
+            let block = tree
+                .block(hash)
+                .expect("Routes go through the tree")
+                .clone();
+            if let Err(e) = Self::execute(stf, &block) {
+                // The blocks below it were valid, but none of them is executed once back on `head`
+                tree.remove(&route.enacted[0]);
+                for _ in 0..executed {
+                    stf.revert_block()?;
+                }
+                for hash in route.retracted.iter().rev() {
+                    let block = tree
+                        .block(hash)
+                        .expect("Routes go through the tree")
+                        .clone();
+                    stf.execute_block(block)?;
+                }
+                return Err(match e {
+                    ConsensusError::ImportBlockError(reason) => {
+                        ConsensusError::ImportBlockError(format!(
+                            "Dropped the branch of invalid block {}: {}",
+                            hex::encode(hash),
+                            reason
+                        ))
+                    }
+                    e => e,
+                });
+            }
+        }
+
+        Ok(route)
+    }
+}
+
 impl<T: Config, N: Nodes<T>> ConsensusT<T> for Consensus<T, N>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    fn import_block(
+    fn import_block<S: StoragePlugin>(
         &self,
         block: &mut Block<T>,
-        stf: &mut stf::SimpleStf<T>,
+        stf: &mut stf::SimpleStf<T, S>,
     ) -> Result<(), ConsensusError> {
-        // Here we inject test accounts into the genesis block
+        // Here we start the chain with test accounts, ALICE holding the sudo key
         if block.header.block_height == T::HeightType::from(0) {
-            block.extrinsics.push(extrinsics::SignedTransaction::new(
-                types::TransactionType::AccountCreation {
-                    account_id: T::Hash::from([0; 32]), // ALICE
-                    balance: T::Funds::from(10000000000),
-                },
-            ));
-            block.extrinsics.push(extrinsics::SignedTransaction::new(
-                types::TransactionType::AccountCreation {
-                    account_id: T::Hash::from([1; 32]), // DAVE
-                    balance: T::Funds::from(1000),
-                },
-            ));
-            stf.execute_block(block.clone())
+            stf.apply_genesis(&stf::GenesisConfig {
+                accounts: vec![
+                    (
+                        Keyring::Alice.account_id::<T>(),
+                        T::Funds::from(10000000000),
+                    ),
+                    (Keyring::Dave.account_id::<T>(), T::Funds::from(1000)),
+                ],
+                sudo_key: Some(Keyring::Alice.account_id::<T>()),
+            })?;
+            // Seal the block with the state root it leads to, as for any other block
+            stf.execute_authored_block(block)
                 .map_err(ConsensusError::Stf)?;
+            self.block_tree.lock().unwrap().insert(block.clone())?;
         } else {
+            let mut tree = self.block_tree.lock().unwrap();
+            Self::head(&mut tree, stf)?;
+
             // Set the parent hash of the imported block
             block.header.parent_hash = stf
                 .get_block_hash(block.header.block_height.clone() - T::HeightType::from(1))
                 .map_err(|e| ConsensusError::Stf(StfError::Storage(e)))?;
             match stf.validate_block(block.clone()) {
                 Ok(_) => {
-                    // Execute the block
-                    stf.execute_block(block.clone())
+                    // Execute the block, sealing it with the state root it leads to
+                    stf.execute_authored_block(block)
                         .map_err(ConsensusError::Stf)?;
+                    tree.insert(block.clone())?;
                 }
                 Err(e) => {
                     println!("Error: {}", e);
@@ -93,31 +303,348 @@ where
             println!("~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
             println!(
                 "Account ALICE: {:?}",
-                stf.get_account(T::Hash::from([0; 32]))
+                stf.get_account(Keyring::Alice.account_id::<T>())
             );
             println!(
                 "Account DAVE: {:?}",
-                stf.get_account(T::Hash::from([1; 32]))
+                stf.get_account(Keyring::Dave.account_id::<T>())
             );
             println!("~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
         }
 
         Ok(())
     }
+
+    fn import_sealed_block<S: StoragePlugin>(
+        &self,
+        block: Block<T>,
+        stf: &mut stf::SimpleStf<T, S>,
+    ) -> Result<ImportOutcome<T>, ConsensusError> {
+        let mut tree = self.block_tree.lock().unwrap();
+        let head = Self::head(&mut tree, stf)?.ok_or_else(|| {
+            ConsensusError::ImportBlockError("The genesis block is not imported yet".to_string())
+        })?;
+
+        let hash = tree.insert(block)?;
+        if tree.best() != Some(hash) {
+            return Ok(ImportOutcome::SideBranch(hash));
+        }
+
+        let route = Self::switch_head(&mut tree, stf, head, hash)?;
+        if route.retracted.is_empty() {
+            Ok(ImportOutcome::Extended(hash))
+        } else {
+            Ok(ImportOutcome::Reorg(route))
+        }
+    }
+
+    fn sync<S: StoragePlugin>(
+        &self,
+        stf: &mut stf::SimpleStf<T, S>,
+    ) -> Result<Vec<ImportOutcome<T>>, ConsensusError> {
+        let mut outcomes = Vec::new();
+        let Some((peer_height, _)) = self.node_network.best_peer_head() else {
+            return Ok(outcomes);
+        };
+        let peer_height: u64 = peer_height.into();
+
+        // Right above our best block, or the peers' best block when they are not higher
+        let mut next = match stf.best_block().map_err(StfError::Storage)? {
+            Some(best_block) => (Into::<u64>::into(best_block) + 1).min(peer_height),
+            None => 0,
+        };
+        while next <= peer_height {
+            let blocks = self
+                .node_network
+                .request_blocks(T::HeightType::from(next), MAX_BLOCKS_PER_REQUEST);
+            if blocks.is_empty() {
+                break;
+            }
+            next += blocks.len() as u64;
+            for block in blocks {
+                self.import_with_ancestors(block, stf, &mut outcomes)?;
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+impl<T: Config, N: Nodes<T>> Consensus<T, N>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    // Import a block received from the peers, requesting the ancestors we are missing first
+    fn import_with_ancestors<S: StoragePlugin>(
+        &self,
+        block: Block<T>,
+        stf: &mut stf::SimpleStf<T, S>,
+        outcomes: &mut Vec<ImportOutcome<T>>,
+    ) -> Result<(), ConsensusError> {
+        let mut branch = vec![block];
+        loop {
+            let parent_hash = branch
+                .last()
+                .expect("Branch is never empty")
+                .header
+                .parent_hash;
+            let known = self.block_tree.lock().unwrap().contains(&parent_hash);
+            if known {
+                break;
+            }
+            let parent = self
+                .node_network
+                .request_block(BlockId::Hash(parent_hash))
+                .ok_or_else(|| {
+                    ConsensusError::ImportBlockError(format!(
+                        "No peer has block {}",
+                        hex::encode(parent_hash)
+                    ))
+                })?;
+            branch.push(parent);
+        }
+
+        for block in branch.into_iter().rev() {
+            match self.import_sealed_block(block, stf) {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(ConsensusError::BlockTree(BlockTreeError::AlreadyKnown(_))) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Config, N: Nodes<T>> BlockSource<T> for Consensus<T, N>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    fn best_head(&self) -> Option<(T::HeightType, T::Hash)> {
+        let tree = self.block_tree.lock().unwrap();
+        let best = tree.best()?;
+        Some((tree.header(&best)?.block_height.clone(), best))
+    }
+
+    fn block(&self, block_id: &BlockId<T>) -> Option<Block<T>> {
+        let tree = self.block_tree.lock().unwrap();
+        let hash = match block_id {
+            BlockId::Hash(hash) => *hash,
+            BlockId::Height(height) => *tree
+                .best_chain(height)
+                .first()
+                .filter(|hash| tree.header(hash).is_some_and(|h| h.block_height == *height))?,
+        };
+        tree.block(&hash).cloned()
+    }
+
+    fn blocks(&self, from: &T::HeightType, count: u64) -> Vec<Block<T>> {
+        let tree = self.block_tree.lock().unwrap();
+        tree.best_chain(from)
+            .iter()
+            .take(count as usize)
+            .filter_map(|hash| tree.block(hash).cloned())
+            .collect()
+    }
 }
 
-#[derive(Debug)]
 pub struct Node<T: Config> {
     pub transaction_pool: VecDeque<SignedTransaction<T>>,
+    // Listeners of the events of every imported block, see `forward_events`
+    pub event_listeners: Vec<Sender<BlockEvents<T>>>,
+    // Nodes blocks are requested from, see `Nodes`
+    pub peers: Vec<Peer<T>>,
+}
+
+impl<T: Config + Debug> Debug for Node<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Peers are left out, they may well know this node in turn
+        f.debug_struct("Node")
+            .field("transaction_pool", &self.transaction_pool)
+            .field("event_listeners", &self.event_listeners)
+            .field("peers", &self.peers.len())
+            .finish()
+    }
+}
+
+impl<T: Config> Default for Node<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Config> Node<T> {
+    pub fn new() -> Self {
+        Node {
+            transaction_pool: VecDeque::new(),
+            event_listeners: Vec::new(),
+            peers: Vec::new(),
+        }
+    }
+
+    pub fn add_peer(&mut self, peer: Peer<T>) {
+        self.peers.push(peer);
+    }
+
+    pub fn subscribe_events(&mut self) -> Receiver<BlockEvents<T>> {
+        let (sender, receiver) = mpsc::channel();
+        self.event_listeners.push(sender);
+        receiver
+    }
+
+    /// Forward the events of a block, as received from the STF, to every listener still listening.
+    pub fn forward_events(&mut self, block_events: BlockEvents<T>) {
+        self.event_listeners
+            .retain(|listener| listener.send(block_events.clone()).is_ok());
+    }
+}
+
+impl<T: Config> Node<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    /// Priority of a transaction in the pool: the fee it pays per unit of weight, under the chain's
+    /// current fee multiplier. `None` when that fee overflows, the transaction cannot be paid for.
+    pub fn priority(
+        transaction: &SignedTransaction<T>,
+        fee_multiplier: FeeMultiplier,
+    ) -> Option<T::Funds> {
+        let weight: u64 = transaction.weight().into();
+        let fee = fee_multiplier.apply(transaction.fee().ok()?)?;
+        Some(fee / T::Funds::from(weight.max(1) as u128))
+    }
+
+    /// Move the ready transactions of the pool into `block`, highest priority first, until it is full.
+    ///
+    /// The block first gets its coinbase inherent, rewarding `block.header.author`, unless it
+    /// already starts with one. Inherents found in the pool are dropped. Room is left for the
+    /// weight the hooks take in the block.
+    ///
+    /// A signed transaction is ready once its nonce is the signer's next one, counting the ones
+    /// already taken into the block. Future nonces wait in the pool until the gap is filled, while
+    /// nonces that were already used can never be included and are dropped, as are transactions
+    /// whose signer has no account to pay for them or whose fee overflows. A fee that only
+    /// overflows under the current multiplier keeps the transaction waiting. Among transactions
+    /// of the same priority, the oldest goes first.
+    pub fn collect_extrinsics<S: Stf<T>>(&mut self, block: &mut Block<T>, stf: &mut S) {
+        let fee_multiplier = stf.fee_multiplier().unwrap_or_default();
+        // Hooks that cannot even be dry-run leave no room for transactions
+        let hooks_weight = stf
+            .hooks_weight(&block.header.block_height)
+            .unwrap_or_else(|_| T::MaxBlockWeight::get());
+
+        if !block
+            .extrinsics
+            .first()
+            .is_some_and(|extrinsic| extrinsic.is_inherent())
+        {
+            block
+                .extrinsics
+                .insert(0, SignedTransaction::coinbase(block.header.author));
+            block.header.extrinsics_root = block.extrinsics_root();
+        }
+
+        // Next nonce of every signer seen so far
+        let mut next_nonces: Vec<(T::Hash, T::Nonce)> = Vec::new();
+
+        loop {
+            self.transaction_pool.retain(|transaction| {
+                if transaction.is_inherent() || transaction.fee().is_err() {
+                    return false;
+                }
+                match transaction.signer() {
+                    None => true,
+                    Some(signer) => Self::next_nonce(stf, &next_nonces, signer)
+                        .is_some_and(|next_nonce| transaction.nonce >= next_nonce),
+                }
+            });
+
+            // The oldest transactions sit at the back of the pool
+            let mut best: Option<(usize, T::Funds)> = None;
+            for (index, transaction) in self.transaction_pool.iter().enumerate().rev() {
+                let ready = match transaction.signer() {
+                    None => true,
+                    Some(signer) => Self::next_nonce(stf, &next_nonces, signer)
+                        .is_some_and(|next_nonce| transaction.nonce == next_nonce),
+                };
+                if !ready || !block.can_add_extrinsic(transaction.weight() + hooks_weight.clone()) {
+                    continue;
+                }
+
+                let Some(priority) = Self::priority(transaction, fee_multiplier) else {
+                    continue;
+                };
+                if best.is_none_or(|(_, best_priority)| priority > best_priority) {
+                    best = Some((index, priority));
+                }
+            }
+
+            let Some((index, _)) = best else {
+                break;
+            };
+            let transaction = self
+                .transaction_pool
+                .remove(index)
+                .expect("Index comes from the pool");
+            if let Some(signer) = transaction.signer() {
+                let next_nonce = transaction.nonce + T::Nonce::one();
+                match next_nonces.iter_mut().find(|(id, _)| *id == signer) {
+                    Some((_, nonce)) => *nonce = next_nonce,
+                    None => next_nonces.push((signer, next_nonce)),
+                }
+            }
+            block
+                .add_extrinsic(transaction)
+                .expect("Weight was checked with can_add_extrinsic");
+        }
+    }
+
+    // Next nonce of `signer`, counting the transactions already taken into the block
+    fn next_nonce<S: Stf<T>>(
+        stf: &S,
+        next_nonces: &[(T::Hash, T::Nonce)],
+        signer: T::Hash,
+    ) -> Option<T::Nonce> {
+        match next_nonces.iter().find(|(id, _)| *id == signer) {
+            Some((_, nonce)) => Some(*nonce),
+            None => stf.get_account(signer).ok().map(|account| account.nonce),
+        }
+    }
 }
 
 impl<T: Config> Nodes<T> for Arc<Mutex<Node<T>>>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    fn request_block(&self, _block_number: T::MaxBlockHeight) -> Block<T> {
-        todo!()
+    fn request_block(&self, block_id: BlockId<T>) -> Option<Block<T>> {
+        // The lock is not held while peers answer, they may be waiting on this node
+        let peers = self.lock().unwrap().peers.clone();
+        peers.iter().find_map(|peer| peer.block(&block_id))
+    }
+
+    fn request_blocks(&self, from: T::HeightType, count: u64) -> Vec<Block<T>> {
+        // Every block comes from the same peer, the one with the best chain
+        let peers = self.lock().unwrap().peers.clone();
+        match best_peer(&peers) {
+            Some(peer) => peer.blocks(&from, count),
+            None => Vec::new(),
+        }
     }
+
+    fn best_peer_head(&self) -> Option<(T::HeightType, T::Hash)> {
+        let peers = self.lock().unwrap().peers.clone();
+        best_peer(&peers).and_then(|peer| peer.best_head())
+    }
+}
+
+// Peer with the best chain. As in fork choice, the highest chain wins and the lowest hash breaks ties
+fn best_peer<T: Config>(peers: &[Peer<T>]) -> Option<&Peer<T>> {
+    peers
+        .iter()
+        .filter_map(|peer| Some((peer.best_head()?, peer)))
+        .min_by(|((a_height, a_hash), _), ((b_height, b_hash), _)| {
+            b_height.cmp(a_height).then(a_hash.cmp(b_hash))
+        })
+        .map(|(_, peer)| peer)
 }
 
 pub trait RpcNode<T: Config> {
@@ -140,34 +667,79 @@ where
 
 #[cfg(test)]
 mod tests {
+    use common::mock::MockConfig;
+    use common::types::{
+        BaseFee, BlockReward, BurnFees, Height, LengthFee, MaxBlockHeight, MaxBlockWeight,
+        TargetBlockWeight, WeightFee,
+    };
     use serde::Deserialize;
-    use types::{Height, MaxBlockHeight, MaxBlockWeight};
 
     use super::*;
+    use crate::block::Header;
     use crate::stf::SimpleStf;
-    use crate::types::TransactionType;
+    use common::types::{BalancesCall, TransactionType, Zero};
     use std::sync::{Arc, Mutex};
 
-    // Mock implementation of Config trait for testing
-    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-    struct MockConfig;
-    impl Config for MockConfig {
-        type MaxBlockWeight = MaxBlockWeight;
-        type MaxBlockHeight = MaxBlockHeight;
-        type WeightType = u64;
-        type HeightType = Height;
-        type Hash = [u8; 32];
-        type Funds = u128;
+    type TestConsensus = Consensus<MockConfig, Arc<Mutex<Node<MockConfig>>>>;
+
+    // A node that imported the genesis block, which is the same on every node
+    fn node() -> (TestConsensus, SimpleStf<MockConfig>) {
+        let consensus = Consensus::new(Arc::new(Mutex::new(Node::new())));
+        let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+        let mut genesis_block = Block {
+            header: Header {
+                block_height: Height::zero(),
+                parent_hash: [0; 32],
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author: [0; 32],
+            },
+            extrinsics: Vec::new(),
+        };
+        consensus
+            .import_block(&mut genesis_block, &mut stf)
+            .unwrap();
+        (consensus, stf)
+    }
+
+    // Author a block rewarding `author` on top of the node's best block, returned as sealed
+    fn author(
+        consensus: &TestConsensus,
+        stf: &mut SimpleStf<MockConfig>,
+        author: [u8; 32],
+    ) -> Block<MockConfig> {
+        let block_height = Height(stf.best_block().unwrap().unwrap().0 + 1);
+        let mut block = Block {
+            header: Header {
+                block_height,
+                parent_hash: [0; 32],
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author,
+            },
+            extrinsics: Vec::new(),
+        };
+        block
+            .add_extrinsic(SignedTransaction::coinbase(author))
+            .unwrap();
+        consensus.import_block(&mut block, stf).unwrap();
+        stf.get_block(stf.get_block_hash(block_height).unwrap())
+            .unwrap()
+    }
+
+    fn head(stf: &SimpleStf<MockConfig>) -> [u8; 32] {
+        stf.get_block_hash(stf.best_block().unwrap().unwrap())
+            .unwrap()
     }
 
     mod test_import_block {
         use super::*;
 
         mod success {
-            use crate::{
-                block::{self, BlockTrait, Header},
-                types::{One, Zero},
-            };
+            use crate::block::{self, BlockTrait, Header};
+            use common::types::{One, Zero};
 
             use super::*;
 
@@ -175,14 +747,10 @@ mod tests {
             fn test_import_genesis_block() {
                 let block_height = Height::zero();
 
-                let node = Arc::new(Mutex::new(Node {
-                    transaction_pool: vec![].into(),
-                }));
+                let node = Arc::new(Mutex::new(Node::<MockConfig>::new()));
 
-                let consensus = Arc::new(Consensus {
-                    node_network: Arc::clone(&node), // Here, the node itself serves as the node network
-                    phantom: std::marker::PhantomData::<MockConfig>,
-                });
+                // Here, the node itself serves as the node network
+                let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
 
                 let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
                 let mut genesis_block = block::Block {
@@ -192,6 +760,7 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
@@ -200,22 +769,22 @@ mod tests {
 
                 // Because the genesis block is hardcoded to create accounts we will use these
                 // to test functionality
-                assert!(stf.get_account([0; 32]).is_ok());
-                assert!(stf.get_account([1; 32]).is_ok());
+                assert!(stf
+                    .get_account(Keyring::Alice.account_id::<MockConfig>())
+                    .is_ok());
+                assert!(stf
+                    .get_account(Keyring::Dave.account_id::<MockConfig>())
+                    .is_ok());
             }
 
             #[test]
             fn test_import_regular_block() {
                 let mut block_height = Height::zero();
 
-                let node = Arc::new(Mutex::new(Node {
-                    transaction_pool: vec![].into(),
-                }));
+                let node = Arc::new(Mutex::new(Node::<MockConfig>::new()));
 
-                let consensus = Arc::new(Consensus {
-                    node_network: Arc::clone(&node), // Here, the node itself serves as the node network
-                    phantom: std::marker::PhantomData::<MockConfig>,
-                });
+                // Here, the node itself serves as the node network
+                let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
                 let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
 
                 // Import genesis block first
@@ -226,6 +795,7 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
@@ -242,18 +812,62 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
+                regular_block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
                 assert!(consensus.import_block(&mut regular_block, &mut stf).is_ok());
             }
+
+            #[test]
+            fn test_imported_blocks_join_the_tree() {
+                let consensus = Consensus::new(Arc::new(Mutex::new(Node::<MockConfig>::new())));
+                let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+
+                let mut genesis_block = block::Block {
+                    header: Header {
+                        block_height: Height::zero(),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                consensus
+                    .import_block(&mut genesis_block, &mut stf)
+                    .unwrap();
+
+                let mut next_block = block::Block {
+                    header: Header {
+                        block_height: Height::one(),
+                        ..genesis_block.header.clone()
+                    },
+                    extrinsics: Vec::new(),
+                };
+                next_block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
+                consensus.import_block(&mut next_block, &mut stf).unwrap();
+
+                // Both blocks are in the tree as sealed by the import, one on top of the other
+                let tree = consensus.block_tree.lock().unwrap();
+                assert_eq!(tree.root(), Some(genesis_block.hash()));
+                assert_eq!(tree.children(&genesis_block.hash()), &[next_block.hash()]);
+                assert_eq!(
+                    tree.at_height(&Height::one()),
+                    &[stf.get_block_hash(Height::one()).unwrap()]
+                );
+            }
         }
 
         mod failure {
-            use crate::{
-                block::{self, Header},
-                types::{One, Zero},
-            };
+            use crate::block::{self, Header};
+            use common::types::{One, Zero};
 
             use super::*;
 
@@ -261,14 +875,10 @@ mod tests {
             fn test_import_block_with_invalid_height() {
                 let mut block_height = Height::zero();
 
-                let node = Arc::new(Mutex::new(Node {
-                    transaction_pool: vec![].into(),
-                }));
+                let node = Arc::new(Mutex::new(Node::new()));
 
-                let consensus = Arc::new(Consensus {
-                    node_network: Arc::clone(&node), // Here, the node itself serves as the node network
-                    phantom: std::marker::PhantomData::<MockConfig>,
-                });
+                // Here, the node itself serves as the node network
+                let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
                 let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
 
                 // We need a prior block before knowing if the parent hash is invalid...
@@ -279,6 +889,7 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
@@ -298,102 +909,1115 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
                 assert!(consensus
                     .import_block(&mut invalid_block, &mut stf)
                     .is_err());
+                assert_eq!(consensus.block_tree.lock().unwrap().len(), 1);
             }
         }
     }
 
-    mod test_submit_extrinsic {
+    mod test_fork_choice {
         use super::*;
+        use common::types::BlockTreeError;
 
         mod success {
             use super::*;
 
             #[test]
-            fn test_submit_single_extrinsic() {
-                let mut node = Node::<MockConfig> {
-                    transaction_pool: VecDeque::new(),
-                };
+            fn test_shorter_branch_is_kept_aside() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                author(&consensus, &mut stf, [1; 32]);
+                let best = author(&consensus, &mut stf, [1; 32]).hash();
+                let fork = author(&other, &mut other_stf, [2; 32]);
+                let state_root = stf.state_root();
 
-                let transaction = SignedTransaction::new(TransactionType::Transfer {
-                    from: [0; 32],
-                    to: [1; 32],
-                    amount: 100,
-                });
+                let outcome = consensus.import_sealed_block(fork.clone(), &mut stf);
+                assert!(
+                    matches!(outcome, Ok(ImportOutcome::SideBranch(hash)) if hash == fork.hash())
+                );
+                assert_eq!(head(&stf), best);
+                assert_eq!(stf.state_root(), state_root);
+                assert_eq!(
+                    consensus
+                        .block_tree
+                        .lock()
+                        .unwrap()
+                        .at_height(&Height(1))
+                        .len(),
+                    2
+                );
+            }
 
-                node.submit_extrinsic(transaction.clone());
+            #[test]
+            fn test_lowest_hash_wins_at_same_height() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                let ours = author(&consensus, &mut stf, [1; 32]).hash();
+                let theirs = author(&other, &mut other_stf, [2; 32]);
 
-                assert_eq!(node.transaction_pool.len(), 1);
-                assert_eq!(node.transaction_pool[0], transaction);
+                let outcome = consensus
+                    .import_sealed_block(theirs.clone(), &mut stf)
+                    .unwrap();
+                if theirs.hash() < ours {
+                    assert!(matches!(outcome, ImportOutcome::Reorg(_)));
+                    assert_eq!(stf.state_root(), other_stf.state_root());
+                } else {
+                    assert!(matches!(outcome, ImportOutcome::SideBranch(_)));
+                }
+                assert_eq!(head(&stf), theirs.hash().min(ours));
             }
 
             #[test]
-            fn test_submit_multiple_extrinsics() {
-                let mut node = Node::<MockConfig> {
-                    transaction_pool: VecDeque::new(),
-                };
+            fn test_longer_branch_takes_over() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                let (genesis, ours) = (head(&stf), author(&consensus, &mut stf, [1; 32]).hash());
+                let fork = [
+                    author(&other, &mut other_stf, [2; 32]),
+                    author(&other, &mut other_stf, [2; 32]),
+                ];
 
-                let transaction1 = SignedTransaction::new(TransactionType::Transfer {
-                    from: [0; 32],
-                    to: [1; 32],
-                    amount: 100,
-                });
-                let transaction2 = SignedTransaction::new(TransactionType::Transfer {
-                    from: [1; 32],
-                    to: [0; 32],
-                    amount: 50,
-                });
+                let outcomes: Vec<_> = fork
+                    .iter()
+                    .map(|block| {
+                        consensus
+                            .import_sealed_block(block.clone(), &mut stf)
+                            .unwrap()
+                    })
+                    .collect();
 
-                node.submit_extrinsic(transaction1.clone());
-                node.submit_extrinsic(transaction2.clone());
+                // Whether the first block of the fork wins the tie or not, the chain reorganises once
+                let reorgs: Vec<_> = outcomes
+                    .iter()
+                    .filter_map(|outcome| match outcome {
+                        ImportOutcome::Reorg(route) => Some(route),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(reorgs.len(), 1);
+                assert_eq!(reorgs[0].common_ancestor, genesis);
+                assert_eq!(reorgs[0].retracted, vec![ours]);
+                assert_eq!(reorgs[0].enacted.first(), Some(&fork[0].hash()));
 
-                assert_eq!(node.transaction_pool.len(), 2);
-                assert_eq!(node.transaction_pool[0], transaction2);
-                assert_eq!(node.transaction_pool[1], transaction1);
+                // The node ends up exactly where the other one is
+                assert_eq!(head(&stf), fork[1].hash());
+                assert_eq!(stf.state_root(), other_stf.state_root());
+                assert!(stf.get_account([1; 32]).is_err());
+                assert_eq!(stf.get_account([2; 32]).unwrap().balance, 200);
             }
         }
 
         mod failure {
-            // There are no failure cases for submit_extrinsic as it always succeeds
+            use super::*;
+
+            #[test]
+            fn test_invalid_branch_is_dropped() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                author(&consensus, &mut stf, [1; 32]);
+                let fork_base = author(&other, &mut other_stf, [2; 32]);
+                let mut invalid = author(&other, &mut other_stf, [2; 32]);
+                invalid.header.state_root = [9; 32];
+
+                consensus.import_sealed_block(fork_base, &mut stf).unwrap();
+                let (best, state_root) = (head(&stf), stf.state_root());
+
+                // The fork is the best branch until its invalid block is executed
+                let result = consensus.import_sealed_block(invalid.clone(), &mut stf);
+                assert!(matches!(
+                    result,
+                    Err(ConsensusError::ImportBlockError(reason))
+                        if reason.contains(&hex::encode(invalid.hash()))
+                ));
+                assert_eq!(head(&stf), best);
+                assert_eq!(stf.state_root(), state_root);
+
+                let tree = consensus.block_tree.lock().unwrap();
+                assert!(!tree.contains(&invalid.hash()));
+                assert_eq!(tree.best(), Some(best));
+            }
+
+            #[test]
+            fn test_branch_with_invalid_tip_is_dropped() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                let best = author(&consensus, &mut stf, [1; 32]).hash();
+                let state_root = stf.state_root();
+                let mut fork = [
+                    author(&other, &mut other_stf, [2; 32]),
+                    author(&other, &mut other_stf, [2; 32]),
+                    author(&other, &mut other_stf, [2; 32]),
+                ];
+                fork[2].header.state_root = [9; 32];
+
+                // The valid blocks of the fork are known but were never the best ones
+                for block in &fork[..2] {
+                    consensus
+                        .block_tree
+                        .lock()
+                        .unwrap()
+                        .insert(block.clone())
+                        .unwrap();
+                }
+                let result = consensus.import_sealed_block(fork[2].clone(), &mut stf);
+                assert!(matches!(result, Err(ConsensusError::ImportBlockError(_))));
+
+                // The best block of the tree is the one the STF is on
+                assert_eq!(head(&stf), best);
+                assert_eq!(stf.state_root(), state_root);
+                let tree = consensus.block_tree.lock().unwrap();
+                assert!(fork.iter().all(|block| !tree.contains(&block.hash())));
+                assert_eq!(tree.best(), Some(best));
+            }
+
+            #[test]
+            fn test_fork_below_pruning_depth() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                stf.set_pruning_depth(0);
+                let best = author(&consensus, &mut stf, [1; 32]).hash();
+                let state_root = stf.state_root();
+                let fork = [
+                    author(&other, &mut other_stf, [2; 32]),
+                    author(&other, &mut other_stf, [2; 32]),
+                ];
+
+                // The fork is refused once it is the best branch, at the first or the second block
+                let results: Vec<_> = fork
+                    .iter()
+                    .map(|block| consensus.import_sealed_block(block.clone(), &mut stf))
+                    .collect();
+                assert!(results
+                    .iter()
+                    .any(|result| matches!(result, Err(ConsensusError::ImportBlockError(_)))));
+
+                assert_eq!(head(&stf), best);
+                assert_eq!(stf.state_root(), state_root);
+                let tree = consensus.block_tree.lock().unwrap();
+                assert!(!tree.contains(&fork[1].hash()));
+                assert_eq!(tree.best(), Some(best));
+            }
+
+            #[test]
+            fn test_unknown_parent() {
+                let (consensus, mut stf) = node();
+                let (other, mut other_stf) = node();
+                author(&other, &mut other_stf, [2; 32]);
+                let orphan = author(&other, &mut other_stf, [2; 32]);
+
+                assert!(matches!(
+                    consensus.import_sealed_block(orphan, &mut stf),
+                    Err(ConsensusError::BlockTree(BlockTreeError::UnknownParent(_)))
+                ));
+            }
+
+            #[test]
+            fn test_import_before_genesis() {
+                let (other, mut other_stf) = node();
+                let block = author(&other, &mut other_stf, [2; 32]);
+                let consensus: TestConsensus = Consensus::new(Arc::new(Mutex::new(Node::new())));
+                let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+
+                assert!(matches!(
+                    consensus.import_sealed_block(block, &mut stf),
+                    Err(ConsensusError::ImportBlockError(_))
+                ));
+            }
         }
     }
 
-    mod test_pending_extrinsics {
+    mod test_sync {
         use super::*;
 
+        // Serve the blocks of `peer` to `consensus`
+        fn connect(consensus: &TestConsensus, peer: &Arc<TestConsensus>) {
+            consensus
+                .node_network
+                .lock()
+                .unwrap()
+                .add_peer(peer.clone());
+        }
+
         mod success {
             use super::*;
 
             #[test]
-            fn test_pending_extrinsics_empty() {
-                let node = Node::<MockConfig> {
-                    transaction_pool: VecDeque::new(),
-                };
+            fn test_catch_up_with_peer() {
+                let (peer, mut peer_stf) = node();
+                let peer = Arc::new(peer);
+                for _ in 0..3 {
+                    author(&peer, &mut peer_stf, [1; 32]);
+                }
+                let (consensus, mut stf) = node();
+                connect(&consensus, &peer);
 
-                assert!(node.pending_extrinsics().is_empty());
+                let outcomes = consensus.sync(&mut stf).unwrap();
+                assert_eq!(outcomes.len(), 3);
+                assert!(outcomes
+                    .iter()
+                    .all(|outcome| matches!(outcome, ImportOutcome::Extended(_))));
+                assert_eq!(head(&stf), head(&peer_stf));
+                assert_eq!(stf.state_root(), peer_stf.state_root());
+
+                // Nothing left to import once in sync
+                assert!(consensus.sync(&mut stf).unwrap().is_empty());
             }
 
             #[test]
-            fn test_pending_extrinsics_with_transactions() {
-                let mut node = Node::<MockConfig> {
-                    transaction_pool: VecDeque::new(),
-                };
+            fn test_catch_up_across_fork() {
+                let (peer, mut peer_stf) = node();
+                let peer = Arc::new(peer);
+                for _ in 0..3 {
+                    author(&peer, &mut peer_stf, [1; 32]);
+                }
+                let (consensus, mut stf) = node();
+                author(&consensus, &mut stf, [2; 32]);
+                author(&consensus, &mut stf, [2; 32]);
+                connect(&consensus, &peer);
 
-                let transaction1 = SignedTransaction::new(TransactionType::Transfer {
-                    from: [0; 32],
-                    to: [1; 32],
-                    amount: 100,
-                });
-                let transaction2 = SignedTransaction::new(TransactionType::Transfer {
-                    from: [1; 32],
-                    to: [0; 32],
-                    amount: 50,
-                });
+                // The missing ancestors of the peer's chain are requested by hash
+                let outcomes = consensus.sync(&mut stf).unwrap();
+                assert!(outcomes
+                    .iter()
+                    .any(|outcome| matches!(outcome, ImportOutcome::Reorg(_))));
+                assert_eq!(head(&stf), head(&peer_stf));
+                assert_eq!(stf.state_root(), peer_stf.state_root());
+                assert!(stf.get_account([2; 32]).is_err());
+            }
+
+            #[test]
+            fn test_follow_best_peer() {
+                let (short, mut short_stf) = node();
+                let (long, mut long_stf) = node();
+                let (short, long) = (Arc::new(short), Arc::new(long));
+                for _ in 0..2 {
+                    author(&short, &mut short_stf, [1; 32]);
+                }
+                for _ in 0..3 {
+                    author(&long, &mut long_stf, [2; 32]);
+                }
+                let (consensus, mut stf) = node();
+                connect(&consensus, &short);
+                connect(&consensus, &long);
+
+                consensus.sync(&mut stf).unwrap();
+                assert_eq!(head(&stf), head(&long_stf));
+                assert_eq!(stf.state_root(), long_stf.state_root());
+            }
+
+            #[test]
+            fn test_request_block() {
+                let (peer, mut peer_stf) = node();
+                let peer = Arc::new(peer);
+                let block = author(&peer, &mut peer_stf, [1; 32]);
+                let (consensus, _) = node();
+                connect(&consensus, &peer);
+
+                let network = &consensus.node_network;
+                assert_eq!(
+                    network
+                        .request_block(BlockId::Height(Height(1)))
+                        .map(|block| block.hash()),
+                    Some(block.hash())
+                );
+                assert_eq!(
+                    network
+                        .request_block(BlockId::Hash(block.hash()))
+                        .map(|block| block.hash()),
+                    Some(block.hash())
+                );
+                assert!(network.request_block(BlockId::Height(Height(2))).is_none());
+                assert!(network.request_block(BlockId::Hash([9; 32])).is_none());
+                assert_eq!(network.best_peer_head(), Some((Height(1), block.hash())));
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_sync_without_peers() {
+                let (consensus, mut stf) = node();
+                let best = head(&stf);
+
+                assert!(consensus.sync(&mut stf).unwrap().is_empty());
+                assert_eq!(head(&stf), best);
+            }
+
+            #[test]
+            fn test_invalid_block_from_peer() {
+                let (peer, mut peer_stf) = node();
+                let peer = Arc::new(peer);
+                let valid = author(&peer, &mut peer_stf, [1; 32]);
+                let mut invalid = author(&peer, &mut peer_stf, [1; 32]);
+
+                // The peer serves a block whose state root does not match its execution
+                {
+                    let mut tree = peer.block_tree.lock().unwrap();
+                    tree.remove(&invalid.hash());
+                    invalid.header.state_root = [9; 32];
+                    tree.insert(invalid.clone()).unwrap();
+                }
+                let (consensus, mut stf) = node();
+                connect(&consensus, &peer);
+
+                assert!(matches!(
+                    consensus.sync(&mut stf),
+                    Err(ConsensusError::ImportBlockError(_))
+                ));
+                assert_eq!(head(&stf), valid.hash());
+                assert!(!consensus
+                    .block_tree
+                    .lock()
+                    .unwrap()
+                    .contains(&invalid.hash()));
+            }
+
+            #[test]
+            fn test_peer_with_other_genesis() {
+                let peer: TestConsensus = Consensus::new(Arc::new(Mutex::new(Node::new())));
+                let mut peer_stf = SimpleStf::new(runtime::plugin::Plugin::new());
+                let mut genesis_block = Block {
+                    header: Header {
+                        block_height: Height::zero(),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [5; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                peer.import_block(&mut genesis_block, &mut peer_stf)
+                    .unwrap();
+                let peer = Arc::new(peer);
+                author(&peer, &mut peer_stf, [1; 32]);
+                let (consensus, mut stf) = node();
+                let best = head(&stf);
+                connect(&consensus, &peer);
+
+                assert!(matches!(
+                    consensus.sync(&mut stf),
+                    Err(ConsensusError::ImportBlockError(_))
+                ));
+                assert_eq!(head(&stf), best);
+            }
+        }
+    }
+
+    mod test_submit_extrinsic {
+        use super::*;
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_submit_single_extrinsic() {
+                let mut node = Node::<MockConfig>::new();
+
+                let transaction =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: [0; 32],
+                        to: [1; 32],
+                        amount: 100,
+                    }));
+
+                node.submit_extrinsic(transaction.clone());
+
+                assert_eq!(node.transaction_pool.len(), 1);
+                assert_eq!(node.transaction_pool[0], transaction);
+            }
+
+            #[test]
+            fn test_submit_multiple_extrinsics() {
+                let mut node = Node::<MockConfig>::new();
+
+                let transaction1 =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: [0; 32],
+                        to: [1; 32],
+                        amount: 100,
+                    }));
+                let transaction2 =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: [1; 32],
+                        to: [0; 32],
+                        amount: 50,
+                    }));
+
+                node.submit_extrinsic(transaction1.clone());
+                node.submit_extrinsic(transaction2.clone());
+
+                assert_eq!(node.transaction_pool.len(), 2);
+                assert_eq!(node.transaction_pool[0], transaction2);
+                assert_eq!(node.transaction_pool[1], transaction1);
+            }
+        }
+
+        mod failure {
+            // There are no failure cases for submit_extrinsic as it always succeeds
+        }
+    }
+
+    mod test_collect_extrinsics {
+        use super::*;
+        use crate::block::Header;
+        use common::crypto::Pair;
+        use common::types::Zero;
+
+        // STF with the genesis accounts, and an empty block on top of it
+        fn setup() -> (SimpleStf<MockConfig>, Block<MockConfig>) {
+            let node = Arc::new(Mutex::new(Node::new()));
+            let consensus = Consensus::new(Arc::clone(&node));
+            let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+            let mut genesis_block = empty_block(Height::zero());
+            consensus
+                .import_block(&mut genesis_block, &mut stf)
+                .unwrap();
+
+            (stf, empty_block(Height::from(1)))
+        }
+
+        fn empty_block<C>(block_height: Height) -> Block<C>
+        where
+            C: Config<WeightType = u64, HeightType = Height, Hash = [u8; 32]>,
+        {
+            Block {
+                header: Header {
+                    block_height,
+                    parent_hash: [0; 32],
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author: [0; 32],
+                },
+                extrinsics: Vec::new(),
+            }
+        }
+
+        // Inherent the producer puts first in every block
+        fn coinbase() -> SignedTransaction<MockConfig> {
+            SignedTransaction::coinbase([0; 32])
+        }
+
+        fn transfer(nonce: u64) -> SignedTransaction<MockConfig> {
+            SignedTransaction::new_signed(
+                TransactionType::Balances(BalancesCall::Transfer {
+                    from: Keyring::Alice.account_id::<MockConfig>(),
+                    to: Keyring::Dave.account_id::<MockConfig>(),
+                    amount: 100,
+                }),
+                nonce,
+                &Keyring::Alice.pair(),
+            )
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_future_nonce_waits_for_the_gap() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                // Submitted out of order, the block still gets them in nonce order
+                node.submit_extrinsic(transfer(1));
+                node.submit_extrinsic(transfer(0));
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase(), transfer(0), transfer(1)]);
+                assert!(node.transaction_pool.is_empty());
+            }
+
+            #[test]
+            fn test_higher_priority_goes_first() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                // Mints are unsigned and short, so they pay less per unit of weight than a transfer
+                let burn = SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Burn {
+                        from: Keyring::Dave.account_id::<MockConfig>(),
+                        amount: 10,
+                    }),
+                    0,
+                    &Keyring::Dave.pair(),
+                );
+                let mint = SignedTransaction::new(TransactionType::Balances(BalancesCall::Mint {
+                    to: Keyring::Dave.account_id::<MockConfig>(),
+                    amount: 50,
+                }));
+                let multiplier = stf.fee_multiplier().unwrap();
+                assert!(
+                    Node::priority(&transfer(0), multiplier) > Node::priority(&mint, multiplier)
+                );
+
+                node.submit_extrinsic(mint.clone());
+                node.submit_extrinsic(burn.clone());
+                node.submit_extrinsic(transfer(0));
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                let mut expected = vec![mint, burn, transfer(0)];
+                expected.sort_by_key(|transaction| {
+                    std::cmp::Reverse(Node::priority(transaction, multiplier))
+                });
+                expected.insert(0, coinbase());
+                assert_eq!(block.extrinsics, expected);
+                assert!(node.transaction_pool.is_empty());
+            }
+
+            #[test]
+            fn test_priority_follows_multiplier() {
+                let transaction = transfer(0);
+                assert!(
+                    Node::priority(&transaction, FeeMultiplier(2_000_000))
+                        > Node::priority(&transaction, FeeMultiplier::one())
+                );
+            }
+
+            #[test]
+            fn test_unsigned_transactions_are_always_ready() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                let mint = SignedTransaction::new(TransactionType::Balances(BalancesCall::Mint {
+                    to: Keyring::Dave.account_id::<MockConfig>(),
+                    amount: 50,
+                }));
+                node.submit_extrinsic(transfer(2));
+                node.submit_extrinsic(mint.clone());
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase(), mint]);
+                assert_eq!(node.transaction_pool.len(), 1);
+            }
+
+            #[test]
+            fn test_coinbase_is_inserted_once() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                node.submit_extrinsic(transfer(0));
+                node.collect_extrinsics(&mut block, &mut stf);
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase(), transfer(0)]);
+                assert_eq!(block.header.extrinsics_root, block.extrinsics_root());
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_future_nonce_stays_in_pool() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                node.submit_extrinsic(transfer(1));
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase()]);
+                assert_eq!(node.transaction_pool[0], transfer(1));
+            }
+
+            #[test]
+            fn test_inherent_in_pool_is_dropped() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                node.submit_extrinsic(SignedTransaction::coinbase(
+                    Keyring::Dave.account_id::<MockConfig>(),
+                ));
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase()]);
+                assert!(node.transaction_pool.is_empty());
+            }
+
+            #[test]
+            fn test_stale_nonce_is_dropped() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                // The second transaction reuses the nonce of the first one
+                node.submit_extrinsic(transfer(0));
+                node.submit_extrinsic(transfer(0));
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase(), transfer(0)]);
+                assert!(node.transaction_pool.is_empty());
+            }
+
+            #[test]
+            fn test_signer_without_account_is_dropped() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                let stranger = Pair::from_seed(&[7; 32]);
+                let transaction = SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Transfer {
+                        from: stranger.account_id::<MockConfig>(),
+                        to: Keyring::Dave.account_id::<MockConfig>(),
+                        amount: 1,
+                    }),
+                    0,
+                    &stranger,
+                );
+                node.submit_extrinsic(transaction);
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics, vec![coinbase()]);
+                assert!(node.transaction_pool.is_empty());
+            }
+
+            #[test]
+            fn test_full_block_leaves_ready_transactions_in_pool() {
+                let (mut stf, mut block) = setup();
+                let mut node = Node::<MockConfig>::new();
+
+                // A transfer weighs 10 and a block at most 200
+                for nonce in 0..25 {
+                    node.submit_extrinsic(transfer(nonce));
+                }
+                node.collect_extrinsics(&mut block, &mut stf);
+
+                assert_eq!(block.extrinsics.len(), 21);
+                assert_eq!(node.transaction_pool.len(), 5);
+                assert_eq!(node.transaction_pool.back(), Some(&transfer(20)));
+            }
+
+            #[test]
+            fn test_full_block_leaves_room_for_the_hooks() {
+                struct Overhead;
+                impl Get<u64> for Overhead {
+                    fn get() -> u64 {
+                        50
+                    }
+                }
+
+                #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+                struct OverheadConfig;
+                impl Config for OverheadConfig {
+                    type MaxBlockWeight = MaxBlockWeight;
+                    type MaxBlockHeight = MaxBlockHeight;
+                    type WeightType = u64;
+                    type HeightType = Height;
+                    type Hash = [u8; 32];
+                    type Funds = u128;
+                    type Nonce = u64;
+                    type BaseFee = BaseFee;
+                    type WeightFee = WeightFee;
+                    type LengthFee = LengthFee;
+                    type FeeDestination = BurnFees;
+                    type TargetBlockWeight = TargetBlockWeight;
+                    type BlockReward = BlockReward;
+                    type BlockOverhead = Overhead;
+                }
+
+                let node = Arc::new(Mutex::new(Node::<OverheadConfig>::new()));
+                let consensus = Consensus::new(Arc::clone(&node));
+                let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+                let mut genesis_block = empty_block(Height::zero());
+                consensus
+                    .import_block(&mut genesis_block, &mut stf)
+                    .unwrap();
+
+                // A transfer weighs 10, 15 of them leave the 50 the hooks take in a block of 200
+                for nonce in 0..20 {
+                    node.lock()
+                        .unwrap()
+                        .submit_extrinsic(SignedTransaction::new_signed(
+                            TransactionType::Balances(BalancesCall::Transfer {
+                                from: Keyring::Alice.account_id::<OverheadConfig>(),
+                                to: Keyring::Dave.account_id::<OverheadConfig>(),
+                                amount: 100,
+                            }),
+                            nonce,
+                            &Keyring::Alice.pair(),
+                        ));
+                }
+                let mut block = empty_block(Height::from(1));
+                node.lock()
+                    .unwrap()
+                    .collect_extrinsics(&mut block, &mut stf);
+                assert_eq!(block.extrinsics.len(), 16);
+                assert_eq!(node.lock().unwrap().transaction_pool.len(), 5);
+
+                // The block runs along with its hooks
+                consensus.import_block(&mut block, &mut stf).unwrap();
+                assert_eq!(stf.best_block().unwrap(), Some(Height::from(1)));
+            }
+        }
+    }
+
+    mod test_restart {
+        use super::*;
+        use crate::block::Header;
+        use common::types::Zero;
+        use runtime::backend::FileBackend;
+        use runtime::plugin::Plugin;
+        use std::path::{Path, PathBuf};
+
+        // A chain log of its own for every test, removed beforehand in case an earlier run left it
+        fn log_path(name: &str) -> PathBuf {
+            let path =
+                std::env::temp_dir().join(format!("node-{}-{}.log", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            path
+        }
+
+        fn open(path: &Path) -> SimpleStf<MockConfig, Plugin<FileBackend>> {
+            SimpleStf::new(Plugin::with_backend(FileBackend::open(path).unwrap()))
+        }
+
+        // Import a block at `block_height` holding a transfer signed by ALICE with `nonce`
+        fn import(
+            stf: &mut SimpleStf<MockConfig, Plugin<FileBackend>>,
+            block_height: Height,
+            nonce: u64,
+        ) {
+            let node = Arc::new(Mutex::new(Node::new()));
+            let consensus = Consensus::new(Arc::clone(&node));
+            let mut block = Block {
+                header: Header {
+                    block_height,
+                    parent_hash: [0; 32],
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author: [0; 32],
+                },
+                extrinsics: Vec::new(),
+            };
+            if block_height != Height::zero() {
+                let mut node = node.lock().unwrap();
+                node.submit_extrinsic(SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Transfer {
+                        from: Keyring::Alice.account_id::<MockConfig>(),
+                        to: Keyring::Dave.account_id::<MockConfig>(),
+                        amount: 100,
+                    }),
+                    nonce,
+                    &Keyring::Alice.pair(),
+                ));
+                node.collect_extrinsics(&mut block, stf);
+            }
+            consensus.import_block(&mut block, stf).unwrap();
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_restart_resumes_from_last_block() {
+                let path = log_path("resume");
+                let mut stf = open(&path);
+                assert_eq!(stf.best_block().unwrap(), None);
+                import(&mut stf, Height::zero(), 0);
+                import(&mut stf, Height::from(1), 0);
+                let state_root = stf.state_root();
+                drop(stf);
+
+                // Everything imported before the restart is still there
+                let mut stf = open(&path);
+                assert_eq!(stf.best_block().unwrap(), Some(Height::from(1)));
+                assert_eq!(stf.state_root(), state_root);
+                assert_eq!(
+                    stf.get_account(Keyring::Alice.account_id::<MockConfig>())
+                        .unwrap()
+                        .nonce,
+                    1
+                );
+
+                // And the chain carries on from there
+                import(&mut stf, Height::from(2), 1);
+                assert_eq!(stf.best_block().unwrap(), Some(Height::from(2)));
+                std::fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_reorg_after_restart() {
+                let path = log_path("reorg");
+                let mut stf = open(&path);
+                import(&mut stf, Height::zero(), 0);
+                import(&mut stf, Height::from(1), 0);
+                drop(stf);
+
+                // A peer builds a longer branch forking off the genesis block
+                let (peer, mut peer_stf) = node();
+                let dave = Keyring::Dave.account_id::<MockConfig>();
+                let fork = vec![
+                    author(&peer, &mut peer_stf, dave),
+                    author(&peer, &mut peer_stf, dave),
+                ];
+                let fork_head = fork[1].hash();
+
+                // The restarted node can still revert its block 1 to switch to it
+                let mut stf = open(&path);
+                let consensus = Consensus::new(Arc::new(Mutex::new(Node::new())));
+                for block in fork {
+                    consensus.import_sealed_block(block, &mut stf).unwrap();
+                }
+                assert_eq!(stf.get_block_hash(Height::from(2)).unwrap(), fork_head);
+                assert_eq!(
+                    stf.get_account(Keyring::Alice.account_id::<MockConfig>())
+                        .unwrap()
+                        .nonce,
+                    0
+                );
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_failed_block_is_not_persisted() {
+                let path = log_path("failed");
+                let mut stf = open(&path);
+                import(&mut stf, Height::zero(), 0);
+                let state_root = stf.state_root();
+
+                // Heavier than any block may be, so it fails halfway through
+                let transfer =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: Keyring::Alice.account_id::<MockConfig>(),
+                        to: Keyring::Dave.account_id::<MockConfig>(),
+                        amount: 1,
+                    }));
+                let block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: vec![transfer; 21],
+                };
+                assert!(stf.execute_block(block).is_err());
+                drop(stf);
+
+                let stf = open(&path);
+                assert_eq!(stf.best_block().unwrap(), Some(Height::zero()));
+                assert_eq!(stf.state_root(), state_root);
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+    }
+
+    mod test_storage_faults {
+        use super::*;
+        use crate::block::Header;
+        use common::types::{StorageError, Zero};
+        use runtime::mock::{Fault, MockPlugin};
+
+        fn consensus() -> Consensus<MockConfig, Arc<Mutex<Node<MockConfig>>>> {
+            Consensus::new(Arc::new(Mutex::new(Node::new())))
+        }
+
+        // Block authored by ALICE, holding nothing but her coinbase after genesis
+        fn empty_block(block_height: Height) -> Block<MockConfig> {
+            let author = Keyring::Alice.account_id::<MockConfig>();
+            let mut block = Block {
+                header: Header {
+                    block_height,
+                    parent_hash: [0; 32],
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author,
+                },
+                extrinsics: Vec::new(),
+            };
+            if block.header.block_height != Height::zero() {
+                block.extrinsics.push(SignedTransaction::coinbase(author));
+                block.header.extrinsics_root = block.extrinsics_root();
+            }
+            block
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_import_succeeds_once_fault_has_fired() {
+                let consensus = consensus();
+                let mut stf = SimpleStf::new(MockPlugin::new());
+                consensus
+                    .import_block(&mut empty_block(Height::zero()), &mut stf)
+                    .unwrap();
+                stf.plugin_mut().fault_write(
+                    0,
+                    Fault::Fail(StorageError::DataInsertionError("disk full".to_string())),
+                );
+
+                let mut block = empty_block(Height::from(1));
+                assert!(consensus.import_block(&mut block, &mut stf).is_err());
+                assert!(consensus.import_block(&mut block, &mut stf).is_ok());
+                assert_eq!(stf.best_block().unwrap(), Some(Height::from(1)));
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_failing_genesis_write() {
+                let error = StorageError::DataInsertionError("disk full".to_string());
+                let mut plugin = MockPlugin::new();
+                plugin.fault_write(0, Fault::Fail(error.clone()));
+                let mut stf = SimpleStf::new(plugin);
+
+                let result = consensus().import_block(&mut empty_block(Height::zero()), &mut stf);
+                assert!(matches!(
+                    result,
+                    Err(ConsensusError::Stf(StfError::Storage(e))) if e == error
+                ));
+                assert!(stf.best_block().unwrap().is_none());
+            }
+
+            #[test]
+            fn test_corrupt_read_during_import() {
+                let consensus = consensus();
+                let mut stf = SimpleStf::new(MockPlugin::new());
+                consensus
+                    .import_block(&mut empty_block(Height::zero()), &mut stf)
+                    .unwrap();
+                let state_root = stf.state_root();
+                // Read 0 is the parent hash, read 1 ALICE's account as her coinbase credits it
+                stf.plugin_mut().fault_read(1, Fault::Corrupt);
+
+                let result = consensus.import_block(&mut empty_block(Height::from(1)), &mut stf);
+                assert!(matches!(
+                    result,
+                    Err(ConsensusError::Stf(StfError::Storage(
+                        StorageError::DeserializationError(_)
+                    )))
+                ));
+                assert_eq!(stf.state_root(), state_root);
+                assert_eq!(stf.best_block().unwrap(), Some(Height::zero()));
+            }
+        }
+    }
+
+    mod test_forward_events {
+        use super::*;
+        use common::event::{BalancesEvent, Event};
+
+        fn block_events() -> BlockEvents<MockConfig> {
+            BlockEvents {
+                block_height: Height::from(1),
+                events: vec![Event::Balances(BalancesEvent::Minted {
+                    to: Keyring::Dave.account_id::<MockConfig>(),
+                    amount: 50,
+                })],
+            }
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_forward_events_to_listeners() {
+                let mut node = Node::<MockConfig>::new();
+                let first = node.subscribe_events();
+                let second = node.subscribe_events();
+
+                node.forward_events(block_events());
+
+                assert_eq!(first.try_recv().unwrap(), block_events());
+                assert_eq!(second.try_recv().unwrap(), block_events());
+            }
+
+            #[test]
+            fn test_forward_stf_events() {
+                let node = Arc::new(Mutex::new(Node::<MockConfig>::new()));
+                let consensus = Consensus::new(Arc::clone(&node));
+                let mut stf = SimpleStf::new(runtime::plugin::Plugin::new());
+                let stf_events = stf.subscribe_events();
+                let listener = node.lock().unwrap().subscribe_events();
+
+                let mut genesis_block = Block {
+                    header: crate::block::Header {
+                        block_height: Height::from(0),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                consensus
+                    .import_block(&mut genesis_block, &mut stf)
+                    .unwrap();
+                for block_events in stf_events.try_iter() {
+                    node.lock().unwrap().forward_events(block_events);
+                }
+
+                // The genesis accounts come from the genesis config, before the block
+                let block_events = listener.try_recv().unwrap();
+                assert_eq!(block_events.block_height, Height::from(0));
+                assert!(block_events.events.is_empty());
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_dropped_listener_is_forgotten() {
+                let mut node = Node::<MockConfig>::new();
+                let listener = node.subscribe_events();
+                drop(node.subscribe_events());
+
+                node.forward_events(block_events());
+
+                assert_eq!(node.event_listeners.len(), 1);
+                assert!(listener.try_recv().is_ok());
+            }
+        }
+    }
+
+    mod test_pending_extrinsics {
+        use super::*;
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_pending_extrinsics_empty() {
+                let node = Node::<MockConfig>::new();
+
+                assert!(node.pending_extrinsics().is_empty());
+            }
+
+            #[test]
+            fn test_pending_extrinsics_with_transactions() {
+                let mut node = Node::<MockConfig>::new();
+
+                let transaction1 =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: [0; 32],
+                        to: [1; 32],
+                        amount: 100,
+                    }));
+                let transaction2 =
+                    SignedTransaction::new(TransactionType::Balances(BalancesCall::Transfer {
+                        from: [1; 32],
+                        to: [0; 32],
+                        amount: 50,
+                    }));
 
                 node.submit_extrinsic(transaction1.clone());
                 node.submit_extrinsic(transaction2.clone());
@@ -1,6 +1,6 @@
 use common::block;
-use common::block::BlockTrait;
 use common::block::Header;
+use common::crypto::Keyring;
 use common::extrinsics;
 use common::types;
 use common::types::Config;
@@ -8,8 +8,9 @@ use common::types::One;
 use common::types::Zero;
 use node::{Consensus, ConsensusT, Node, RpcNode};
 use rand::Rng;
+use runtime::backend::FileBackend;
 use runtime::plugin;
-use runtime::stf;
+use runtime::stf::{self, Stf};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::{
@@ -17,7 +18,10 @@ use std::{
     thread,
     time::Duration,
 };
-use types::{Height, MaxBlockHeight, MaxBlockWeight};
+use types::{
+    BaseFee, BlockReward, BurnFees, Height, LengthFee, MaxBlockHeight, MaxBlockWeight, NoOverhead,
+    TargetBlockWeight, WeightFee,
+};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 struct MainNetConfig;
@@ -29,58 +33,115 @@ impl Config for MainNetConfig {
     type HeightType = Height;
     type Hash = [u8; 32];
     type Funds = u128;
+    type Nonce = u64;
+    type BaseFee = BaseFee;
+    type WeightFee = WeightFee;
+    type LengthFee = LengthFee;
+    type FeeDestination = BurnFees;
+    type TargetBlockWeight = TargetBlockWeight;
+    type BlockReward = BlockReward;
+    type BlockOverhead = NoOverhead;
 }
 
 fn main() {
-    let mut block_height = Height::zero();
-    let plugin = plugin::Plugin::new();
-    let node = Arc::new(Mutex::new(Node {
-        transaction_pool: vec![].into(),
-    }));
-    let consensus = Arc::new(Consensus {
-        node_network: Arc::clone(&node), // Here, the node itself serves as the node network
-        phantom: std::marker::PhantomData::<MainNetConfig>,
-    });
-    let mut stf: stf::SimpleStf<MainNetConfig> = stf::SimpleStf::new(plugin);
+    // The chain is kept on disk, a restarted node resumes after the last block it imported
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "chain.log".to_string());
+    let backend = FileBackend::open(&path).unwrap();
+    let plugin = plugin::Plugin::with_backend(backend);
+    let node = Arc::new(Mutex::new(Node::new()));
+    // Here, the node itself serves as the node network
+    let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
+    let mut stf: stf::SimpleStf<MainNetConfig, plugin::Plugin<FileBackend>> =
+        stf::SimpleStf::new(plugin);
+    let stf_events = stf.subscribe_events();
+    let mut block_height = match stf.best_block().unwrap() {
+        Some(mut best_block) => {
+            println!("Resuming from block #{} in {}", best_block, path);
+            best_block += Height::one();
+            best_block
+        }
+        None => Height::zero(),
+    };
+    // Nonce of the next transaction signed by ALICE
+    let mut alice_nonce = stf
+        .get_account(Keyring::Alice.account_id::<MainNetConfig>())
+        .map_or(0, |account| account.nonce);
+    let event_listener = node.lock().unwrap().subscribe_events();
 
     println!("BLOCKCHAIN BEGIN ~>");
 
     thread::scope(|s| {
+        s.spawn(move || {
+            for block_events in event_listener {
+                for event in block_events.events {
+                    println!("Event #{} -> {:?}", block_events.block_height, event);
+                }
+            }
+        });
         let node_clone = Arc::clone(&node);
-        s.spawn(move || loop {
-            {
-                let mut node = node_clone.lock().unwrap();
-                let num: u32 = rand::thread_rng().gen_range(0..=2);
-
-                match num {
-                    0 => node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                        types::TransactionType::Transfer {
-                            from: [0; 32],
-                            to: [1; 32],
-                            amount: 100,
-                        },
-                    )),
-                    1 => node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                        types::TransactionType::Mint {
-                            to: [1; 32],
-                            amount: 100,
-                        },
-                    )),
-                    2 => node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                        types::TransactionType::Burn {
-                            from: [0; 32],
-                            amount: 100,
-                        },
-                    )),
-                    _default => node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                        types::TransactionType::Burn {
-                            from: [0; 32],
-                            amount: 0,
-                        },
-                    )),
+        s.spawn(move || {
+            loop {
+                {
+                    let mut node = node_clone.lock().unwrap();
+                    let num: u32 = rand::thread_rng().gen_range(0..=2);
+
+                    match num {
+                        0 => {
+                            node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                                types::TransactionType::Balances(types::BalancesCall::Transfer {
+                                    from: Keyring::Alice.account_id::<MainNetConfig>(),
+                                    to: Keyring::Dave.account_id::<MainNetConfig>(),
+                                    amount: 100,
+                                }),
+                                alice_nonce,
+                                &Keyring::Alice.pair(),
+                            ));
+                            alice_nonce += 1;
+                        }
+                        // Only ALICE, holding the sudo key, can mint
+                        1 => {
+                            node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                                types::TransactionType::Sudo {
+                                    call: Box::new(types::TransactionType::Balances(
+                                        types::BalancesCall::Mint {
+                                            to: Keyring::Dave.account_id::<MainNetConfig>(),
+                                            amount: 100,
+                                        },
+                                    )),
+                                },
+                                alice_nonce,
+                                &Keyring::Alice.pair(),
+                            ));
+                            alice_nonce += 1;
+                        }
+                        2 => {
+                            node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                                types::TransactionType::Balances(types::BalancesCall::Burn {
+                                    from: Keyring::Alice.account_id::<MainNetConfig>(),
+                                    amount: 100,
+                                }),
+                                alice_nonce,
+                                &Keyring::Alice.pair(),
+                            ));
+                            alice_nonce += 1;
+                        }
+                        _default => {
+                            node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                                types::TransactionType::Balances(types::BalancesCall::Burn {
+                                    from: Keyring::Alice.account_id::<MainNetConfig>(),
+                                    amount: 0,
+                                }),
+                                alice_nonce,
+                                &Keyring::Alice.pair(),
+                            ));
+                            alice_nonce += 1;
+                        }
+                    }
                 }
+                thread::sleep(Duration::from_millis(400));
             }
-            thread::sleep(Duration::from_millis(400));
         });
         s.spawn(move || loop {
             let mut block = block::Block {
@@ -90,6 +151,7 @@ fn main() {
                     state_root: [0; 32],
                     extrinsics_root: [0; 32],
                     block_weight: 0,
+                    author: Keyring::Dave.account_id::<MainNetConfig>(),
                 },
                 extrinsics: Vec::new(),
             };
@@ -97,23 +159,20 @@ fn main() {
             // The genesis block shouldn't contain transactions other than the ones
             // currently hard-coded @ startup
             if block_height != Height::zero() {
-                let mut node = node.lock().unwrap();
-
-                // Keep pulling from the transaction pool until the block weight limit is reached
-                while let Some(transaction) = node.transaction_pool.back() {
-                    // Check if the extrinsic can be added
-                    if block.can_add_extrinsic(transaction.weight()) {
-                        block.add_extrinsic(transaction.clone()).unwrap();
-                        node.transaction_pool.pop_back();
-                    } else {
-                        break;
-                    }
-                }
+                // Keep pulling ready transactions from the pool until the block weight limit is reached
+                node.lock()
+                    .unwrap()
+                    .collect_extrinsics(&mut block, &mut stf);
             }
 
             // Import the block with the collected transactions and final weight
             consensus.import_block(&mut block, &mut stf).unwrap();
 
+            // Let the node's listeners know what happened in the block
+            for block_events in stf_events.try_iter() {
+                node.lock().unwrap().forward_events(block_events);
+            }
+
             // Increment block height for the next block
             block_height += Height::one();
 
@@ -138,17 +197,14 @@ mod tests {
             #[test]
             fn test_basic_blockchain_functionality() {
                 let plugin = plugin::Plugin::new();
-                let node = Arc::new(Mutex::new(Node {
-                    transaction_pool: vec![].into(),
-                }));
-                let consensus = Arc::new(Consensus {
-                    node_network: Arc::clone(&node),
-                    phantom: std::marker::PhantomData::<MainNetConfig>,
-                });
+                let node = Arc::new(Mutex::new(Node::new()));
+                let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
                 let mut stf: stf::SimpleStf<MainNetConfig> = stf::SimpleStf::new(plugin);
+                stf.set_check_invariants(true);
 
                 // Simulate blockchain operation for a few blocks
                 let mut block_height = Height::zero();
+                let mut alice_nonce = 0;
                 for _ in 0..5 {
                     let mut block = block::Block {
                         header: Header {
@@ -157,6 +213,7 @@ mod tests {
                             state_root: [0; 32],
                             extrinsics_root: [0; 32],
                             block_weight: 0,
+                            author: Keyring::Dave.account_id::<MainNetConfig>(),
                         },
                         extrinsics: Vec::new(),
                     };
@@ -164,29 +221,32 @@ mod tests {
                     if block_height != Height::zero() {
                         // Add some transactions to the pool
                         let mut node = node.lock().unwrap();
-                        node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                            types::TransactionType::Transfer {
-                                from: [0; 32],
-                                to: [1; 32],
+                        node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                            types::TransactionType::Balances(types::BalancesCall::Transfer {
+                                from: Keyring::Alice.account_id::<MainNetConfig>(),
+                                to: Keyring::Dave.account_id::<MainNetConfig>(),
                                 amount: 100,
-                            },
+                            }),
+                            alice_nonce,
+                            &Keyring::Alice.pair(),
                         ));
-                        node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                            types::TransactionType::Mint {
-                                to: [1; 32],
-                                amount: 50,
+                        alice_nonce += 1;
+                        node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                            types::TransactionType::Sudo {
+                                call: Box::new(types::TransactionType::Balances(
+                                    types::BalancesCall::Mint {
+                                        to: Keyring::Dave.account_id::<MainNetConfig>(),
+                                        amount: 50,
+                                    },
+                                )),
                             },
+                            alice_nonce,
+                            &Keyring::Alice.pair(),
                         ));
+                        alice_nonce += 1;
 
                         // Process transactions
-                        while let Some(transaction) = node.transaction_pool.pop_back() {
-                            // Check if the extrinsic can be added
-                            if block.can_add_extrinsic(transaction.weight()) {
-                                block.add_extrinsic(transaction.clone()).unwrap();
-                            } else {
-                                break;
-                            }
-                        }
+                        node.collect_extrinsics(&mut block, &mut stf);
                     }
 
                     // Import the block
@@ -197,8 +257,12 @@ mod tests {
                 }
 
                 // Verify final state
-                let alice_account = stf.get_account([0; 32]).unwrap();
-                let dave_account = stf.get_account([1; 32]).unwrap();
+                let alice_account = stf
+                    .get_account(Keyring::Alice.account_id::<MainNetConfig>())
+                    .unwrap();
+                let dave_account = stf
+                    .get_account(Keyring::Dave.account_id::<MainNetConfig>())
+                    .unwrap();
 
                 assert!(
                     alice_account.balance < 10000000000,
@@ -223,17 +287,14 @@ mod tests {
             #[test]
             fn test_high_transaction_volume() {
                 let plugin = plugin::Plugin::new();
-                let node = Arc::new(Mutex::new(Node {
-                    transaction_pool: vec![].into(),
-                }));
-                let consensus = Arc::new(Consensus {
-                    node_network: Arc::clone(&node),
-                    phantom: std::marker::PhantomData::<MainNetConfig>,
-                });
+                let node = Arc::new(Mutex::new(Node::new()));
+                let consensus = Arc::new(Consensus::new(Arc::clone(&node)));
                 let mut stf: stf::SimpleStf<MainNetConfig> = stf::SimpleStf::new(plugin);
+                stf.set_check_invariants(true);
 
                 // Simulate high transaction volume
                 let mut block_height = Height::zero();
+                let mut alice_nonce = 0;
                 for _ in 0..10 {
                     let mut block = block::Block {
                         header: Header {
@@ -242,6 +303,7 @@ mod tests {
                             state_root: [0; 32],
                             extrinsics_root: [0; 32],
                             block_weight: 0,
+                            author: Keyring::Dave.account_id::<MainNetConfig>(),
                         },
                         extrinsics: Vec::new(),
                     };
@@ -249,21 +311,19 @@ mod tests {
                     if block_height != Height::zero() {
                         let mut node = node.lock().unwrap();
                         for _ in 0..100 {
-                            node.submit_extrinsic(extrinsics::SignedTransaction::new(
-                                types::TransactionType::Transfer {
-                                    from: [0; 32],
-                                    to: [1; 32],
+                            node.submit_extrinsic(extrinsics::SignedTransaction::new_signed(
+                                types::TransactionType::Balances(types::BalancesCall::Transfer {
+                                    from: Keyring::Alice.account_id::<MainNetConfig>(),
+                                    to: Keyring::Dave.account_id::<MainNetConfig>(),
                                     amount: 1,
-                                },
+                                }),
+                                alice_nonce,
+                                &Keyring::Alice.pair(),
                             ));
+                            alice_nonce += 1;
                         }
 
-                        while let Some(transaction) = node.transaction_pool.pop_back() {
-                            if block.add_extrinsic(transaction.clone()).is_err() {
-                                node.transaction_pool.push_back(transaction);
-                                break;
-                            }
-                        }
+                        node.collect_extrinsics(&mut block, &mut stf);
                     }
 
                     consensus.import_block(&mut block, &mut stf).unwrap();
@@ -271,8 +331,12 @@ mod tests {
                 }
 
                 // Verify final state after stress test
-                let alice_account = stf.get_account([0; 32]).unwrap();
-                let dave_account = stf.get_account([1; 32]).unwrap();
+                let alice_account = stf
+                    .get_account(Keyring::Alice.account_id::<MainNetConfig>())
+                    .unwrap();
+                let dave_account = stf
+                    .get_account(Keyring::Dave.account_id::<MainNetConfig>())
+                    .unwrap();
 
                 assert!(
                     alice_account.balance < 10000000000,
@@ -283,7 +347,7 @@ mod tests {
                     "Dave's balance should have increased significantly"
                 );
                 assert!(
-                    node.lock().unwrap().transaction_pool.len() > 0,
+                    !node.lock().unwrap().transaction_pool.is_empty(),
                     "Transaction pool should still have pending transactions"
                 );
             }
@@ -0,0 +1,551 @@
+use common::block::{Block, BlockTrait, Header};
+use common::crypto::Keyring;
+use common::extrinsics::SignedTransaction;
+use common::types::{BlockTreeError, Config, ConsensusError, StfError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use runtime::plugin::Plugin;
+use runtime::stf::{SimpleStf, Stf};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use crate::{Consensus, ConsensusT, Node, RpcNode};
+
+/// Settings of a simulated network.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// Seed of every random choice made by the simulator. Two runs with the same seed and the same
+    /// inputs go through the exact same steps.
+    pub seed: u64,
+    /// Ticks a message takes to be delivered, picked uniformly in the range. A message is never
+    /// delivered on the tick it was sent.
+    pub latency: RangeInclusive<u64>,
+    /// Chance of a message being lost, between 0 and 1.
+    pub drop_rate: f64,
+    /// Chance of every node authoring a block on a given tick, between 0 and 1.
+    pub block_probability: f64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            latency: 1..=3,
+            drop_rate: 0.0,
+            block_probability: 0.1,
+        }
+    }
+}
+
+/// What nodes send each other over the simulated network.
+#[derive(Debug)]
+pub enum Message<T: Config> {
+    Block(Block<T>),
+    Transaction(SignedTransaction<T>),
+    /// Ask for a block by its hash, sent when a block arrives before its parent.
+    Request(T::Hash),
+}
+
+impl<T: Config> Clone for Message<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Message::Block(block) => Message::Block(block.clone()),
+            Message::Transaction(transaction) => Message::Transaction(transaction.clone()),
+            Message::Request(hash) => Message::Request(*hash),
+        }
+    }
+}
+
+// A message on its way from one node to another
+struct Envelope<T: Config> {
+    from: usize,
+    to: usize,
+    message: Message<T>,
+}
+
+/// How the messages sent over the network fared.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimStats {
+    pub sent: usize,
+    pub delivered: usize,
+    /// Lost to the drop rate.
+    pub dropped: usize,
+    /// Sent across a partition.
+    pub partitioned: usize,
+    /// Blocks nodes received but failed to import.
+    pub rejected: usize,
+}
+
+/// A node of the simulated network, with its own state and transaction pool.
+pub struct SimNode<T: Config>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    pub consensus: Consensus<T, Arc<Mutex<Node<T>>>>,
+    pub stf: SimpleStf<T>,
+    /// Account rewarded by the blocks the node authors.
+    pub author: T::Hash,
+    // Blocks received before their parent, by parent hash
+    orphans: HashMap<T::Hash, Vec<Block<T>>>,
+}
+
+impl<T: Config> SimNode<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    /// Hash of the best block.
+    pub fn head(&self) -> Option<T::Hash> {
+        self.consensus.block_tree.lock().unwrap().best()
+    }
+
+    pub fn best_block(&self) -> Option<T::HeightType> {
+        self.stf.best_block().ok().flatten()
+    }
+
+    pub fn state_root(&self) -> T::Hash {
+        self.stf.state_root()
+    }
+}
+
+/// A deterministic network of nodes running in-process.
+///
+/// Time goes by in ticks. On every tick, the messages due are delivered, then every node authors a
+/// block on top of its best block with `SimConfig::block_probability`. Blocks and transactions are
+/// sent to every other node, each copy with its own latency and chance of being lost. A node
+/// receiving a block whose parent it does not know asks the sender for it.
+pub struct Simulator<T: Config>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    config: SimConfig,
+    rng: StdRng,
+    nodes: Vec<SimNode<T>>,
+    // Messages in flight, by delivery tick then send order
+    in_flight: BTreeMap<(u64, u64), Envelope<T>>,
+    // Side of the partition every node is on, nodes on different sides cannot reach each other
+    sides: Vec<usize>,
+    now: u64,
+    sent: u64,
+    stats: SimStats,
+}
+
+impl<T: Config> Simulator<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    /// A network with one node per author, all of them having imported the same genesis block.
+    pub fn new(authors: Vec<T::Hash>, config: SimConfig) -> Result<Self, ConsensusError> {
+        let nodes = authors
+            .into_iter()
+            .map(|author| {
+                let consensus = Consensus::new(Arc::new(Mutex::new(Node::new())));
+                let mut stf = SimpleStf::new(Plugin::new());
+                let mut genesis_block =
+                    Self::new_block(T::HeightType::from(0), Keyring::Dave.account_id::<T>());
+                consensus.import_block(&mut genesis_block, &mut stf)?;
+                Ok(SimNode {
+                    consensus,
+                    stf,
+                    author,
+                    orphans: HashMap::new(),
+                })
+            })
+            .collect::<Result<Vec<_>, ConsensusError>>()?;
+
+        Ok(Simulator {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            sides: vec![0; nodes.len()],
+            nodes,
+            in_flight: BTreeMap::new(),
+            now: 0,
+            sent: 0,
+            stats: SimStats::default(),
+        })
+    }
+
+    pub fn nodes(&self) -> &[SimNode<T>] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &SimNode<T> {
+        &self.nodes[index]
+    }
+
+    /// Current tick.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn stats(&self) -> &SimStats {
+        &self.stats
+    }
+
+    /// Split the network, the nodes of every group only reach each other. Nodes left out of every
+    /// group form one more group. Messages already in flight across the partition are lost.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        self.sides = vec![0; self.nodes.len()];
+        for (side, group) in groups.iter().enumerate() {
+            for &index in group.iter() {
+                self.sides[index] = side + 1;
+            }
+        }
+    }
+
+    /// Let every node reach every other one again.
+    pub fn heal(&mut self) {
+        self.sides = vec![0; self.nodes.len()];
+    }
+
+    /// Submit a transaction to a node, which sends it to the others.
+    pub fn submit_extrinsic(&mut self, index: usize, transaction: SignedTransaction<T>) {
+        self.nodes[index]
+            .consensus
+            .node_network
+            .lock()
+            .unwrap()
+            .submit_extrinsic(transaction.clone());
+        self.broadcast(index, Message::Transaction(transaction));
+    }
+
+    /// Have a node author a block on top of its best block and send it to the others. Returns the
+    /// hash of the block, unless it was found invalid.
+    pub fn author_block(&mut self, index: usize) -> Result<Option<T::Hash>, ConsensusError> {
+        let node = &mut self.nodes[index];
+        let best_block: u64 = node
+            .stf
+            .best_block()
+            .map_err(StfError::Storage)?
+            .map_or(0, Into::into);
+        let mut block = Self::new_block(T::HeightType::from(best_block + 1), node.author);
+        node.consensus
+            .node_network
+            .lock()
+            .unwrap()
+            .collect_extrinsics(&mut block, &mut node.stf);
+        node.consensus.import_block(&mut block, &mut node.stf)?;
+
+        // An invalid block is left out of the tree
+        let hash = T::Hash::from(block.hash());
+        if !node.consensus.block_tree.lock().unwrap().contains(&hash) {
+            return Ok(None);
+        }
+        self.broadcast(index, Message::Block(block));
+        Ok(Some(hash))
+    }
+
+    /// Run a single tick.
+    pub fn step(&mut self) -> Result<(), ConsensusError> {
+        // Messages sent while handling these ones are due on a later tick
+        while let Some(entry) = self.in_flight.first_entry() {
+            if entry.key().0 > self.now {
+                break;
+            }
+            let envelope = entry.remove();
+            if self.sides[envelope.from] != self.sides[envelope.to] {
+                self.stats.partitioned += 1;
+                continue;
+            }
+            self.stats.delivered += 1;
+            self.deliver(envelope);
+        }
+
+        for index in 0..self.nodes.len() {
+            if self.rng.gen_bool(self.config.block_probability) {
+                self.author_block(index)?;
+            }
+        }
+
+        self.now += 1;
+        Ok(())
+    }
+
+    /// Run `ticks` ticks.
+    pub fn run(&mut self, ticks: u64) -> Result<(), ConsensusError> {
+        (0..ticks).try_for_each(|_| self.step())
+    }
+
+    /// Run until no message is in flight, without authoring any block, or until `max_ticks` ticks
+    /// went by. Returns whether the network settled.
+    pub fn settle(&mut self, max_ticks: u64) -> bool {
+        let block_probability = std::mem::replace(&mut self.config.block_probability, 0.0);
+        for _ in 0..max_ticks {
+            if self.in_flight.is_empty() {
+                break;
+            }
+            self.step().expect("No block is authored");
+        }
+        self.config.block_probability = block_probability;
+        self.in_flight.is_empty()
+    }
+
+    fn deliver(&mut self, envelope: Envelope<T>) {
+        let Envelope { from, to, message } = envelope;
+        match message {
+            Message::Transaction(transaction) => {
+                self.nodes[to]
+                    .consensus
+                    .node_network
+                    .lock()
+                    .unwrap()
+                    .submit_extrinsic(transaction);
+            }
+            Message::Request(hash) => {
+                let block = self.nodes[to]
+                    .consensus
+                    .block_tree
+                    .lock()
+                    .unwrap()
+                    .block(&hash)
+                    .cloned();
+                if let Some(block) = block {
+                    self.send(to, from, Message::Block(block));
+                }
+            }
+            Message::Block(block) => {
+                let parent_hash = block.header.parent_hash;
+                let node = &mut self.nodes[to];
+                let (known, parent_known) = {
+                    let tree = node.consensus.block_tree.lock().unwrap();
+                    (
+                        tree.contains(&T::Hash::from(block.hash())),
+                        tree.contains(&parent_hash),
+                    )
+                };
+                if known {
+                    return;
+                }
+                if !parent_known {
+                    node.orphans.entry(parent_hash).or_default().push(block);
+                    self.send(to, from, Message::Request(parent_hash));
+                    return;
+                }
+                self.import(to, block);
+            }
+        }
+    }
+
+    // Import a block whose parent is known, then the orphans that were waiting on it
+    fn import(&mut self, index: usize, block: Block<T>) {
+        let node = &mut self.nodes[index];
+        let mut pending = vec![block];
+        while let Some(block) = pending.pop() {
+            let hash = T::Hash::from(block.hash());
+            match node.consensus.import_sealed_block(block, &mut node.stf) {
+                Ok(_) | Err(ConsensusError::BlockTree(BlockTreeError::AlreadyKnown(_))) => {
+                    pending.extend(node.orphans.remove(&hash).unwrap_or_default());
+                }
+                Err(_) => self.stats.rejected += 1,
+            }
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, message: Message<T>) {
+        for to in 0..self.nodes.len() {
+            if to != from {
+                self.send(from, to, message.clone());
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message<T>) {
+        self.stats.sent += 1;
+        if self.rng.gen_bool(self.config.drop_rate) {
+            self.stats.dropped += 1;
+            return;
+        }
+        let latency = self.rng.gen_range(self.config.latency.clone()).max(1);
+        self.in_flight.insert(
+            (self.now + latency, self.sent),
+            Envelope { from, to, message },
+        );
+        self.sent += 1;
+    }
+
+    fn new_block(block_height: T::HeightType, author: T::Hash) -> Block<T> {
+        Block {
+            header: Header {
+                block_height,
+                parent_hash: T::Hash::default(),
+                state_root: T::Hash::default(),
+                extrinsics_root: T::Hash::default(),
+                block_weight: T::WeightType::from(0),
+                author,
+            },
+            extrinsics: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::mock::MockConfig;
+    use common::types::{BalancesCall, Height, TransactionType};
+    use std::collections::HashSet;
+
+    // A network of `nodes` nodes, node `i` being rewarded on account [i + 1; 32]
+    fn simulator(nodes: u8, config: SimConfig) -> Simulator<MockConfig> {
+        Simulator::new((1..=nodes).map(|i| [i; 32]).collect(), config).unwrap()
+    }
+
+    fn heads(simulator: &Simulator<MockConfig>) -> HashSet<[u8; 32]> {
+        simulator
+            .nodes()
+            .iter()
+            .map(|node| node.head().unwrap())
+            .collect()
+    }
+
+    fn transfer(nonce: u64) -> SignedTransaction<MockConfig> {
+        SignedTransaction::new_signed(
+            TransactionType::Balances(BalancesCall::Transfer {
+                from: Keyring::Alice.account_id::<MockConfig>(),
+                to: Keyring::Dave.account_id::<MockConfig>(),
+                amount: 100,
+            }),
+            nonce,
+            &Keyring::Alice.pair(),
+        )
+    }
+
+    mod run {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_same_seed_same_run() {
+                let config = SimConfig {
+                    seed: 7,
+                    drop_rate: 0.2,
+                    block_probability: 0.3,
+                    ..SimConfig::default()
+                };
+                let mut first = simulator(3, config.clone());
+                let mut second = simulator(3, config);
+                first.run(40).unwrap();
+                second.run(40).unwrap();
+
+                assert_eq!(first.stats(), second.stats());
+                for (a, b) in first.nodes().iter().zip(second.nodes()) {
+                    assert_eq!(a.head(), b.head());
+                    assert_eq!(a.state_root(), b.state_root());
+                }
+            }
+
+            #[test]
+            fn test_nodes_converge() {
+                let mut simulator = simulator(4, SimConfig::default());
+                simulator.run(50).unwrap();
+                assert!(simulator.settle(100));
+
+                assert_eq!(heads(&simulator).len(), 1);
+                assert!(simulator.node(0).best_block() > Some(Height(0)));
+                let state_root = simulator.node(0).state_root();
+                assert!(simulator
+                    .nodes()
+                    .iter()
+                    .all(|node| node.state_root() == state_root));
+                assert_eq!(simulator.stats().delivered, simulator.stats().sent);
+                assert_eq!(simulator.stats().rejected, 0);
+            }
+
+            #[test]
+            fn test_partition_heals() {
+                let config = SimConfig {
+                    block_probability: 0.3,
+                    ..SimConfig::default()
+                };
+                let mut simulator = simulator(4, config);
+                simulator.partition(&[&[0, 1], &[2, 3]]);
+                simulator.run(30).unwrap();
+                assert!(simulator.settle(100));
+
+                // Each side agrees on its own chain
+                assert_eq!(simulator.node(0).head(), simulator.node(1).head());
+                assert_eq!(simulator.node(2).head(), simulator.node(3).head());
+                assert_ne!(simulator.node(0).head(), simulator.node(2).head());
+                assert!(simulator.stats().partitioned > 0);
+
+                // Once both sides hear of each other, the best chain wins everywhere
+                simulator.heal();
+                simulator.author_block(0).unwrap();
+                simulator.author_block(2).unwrap();
+                assert!(simulator.settle(100));
+                assert_eq!(heads(&simulator).len(), 1);
+            }
+
+            #[test]
+            fn test_transactions_are_gossiped() {
+                let config = SimConfig {
+                    block_probability: 0.0,
+                    ..SimConfig::default()
+                };
+                let mut simulator = simulator(2, config);
+                simulator.submit_extrinsic(0, transfer(0));
+                assert!(simulator.settle(10));
+
+                // The other node includes the transaction in its block
+                let dave = Keyring::Dave.account_id::<MockConfig>();
+                let balance = simulator.node(1).stf.get_account(dave).unwrap().balance;
+                let hash = simulator.author_block(1).unwrap().unwrap();
+                assert_eq!(
+                    simulator.node(1).stf.get_account(dave).unwrap().balance,
+                    balance + 100
+                );
+                assert!(simulator.settle(10));
+                assert_eq!(simulator.node(0).head(), Some(hash));
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_every_message_dropped() {
+                let config = SimConfig {
+                    drop_rate: 1.0,
+                    block_probability: 0.5,
+                    ..SimConfig::default()
+                };
+                let mut simulator = simulator(3, config);
+                simulator.run(20).unwrap();
+
+                // Every node is left on its own chain
+                assert_eq!(simulator.stats().delivered, 0);
+                assert_eq!(simulator.stats().dropped, simulator.stats().sent);
+                assert_eq!(heads(&simulator).len(), 3);
+            }
+
+            #[test]
+            fn test_partitioned_node_misses_transaction() {
+                let config = SimConfig {
+                    block_probability: 0.0,
+                    ..SimConfig::default()
+                };
+                let mut simulator = simulator(3, config);
+                simulator.partition(&[&[0, 1]]);
+                simulator.submit_extrinsic(0, transfer(0));
+                assert!(simulator.settle(10));
+
+                let pool = |index: usize| {
+                    simulator
+                        .node(index)
+                        .consensus
+                        .node_network
+                        .lock()
+                        .unwrap()
+                        .transaction_pool
+                        .len()
+                };
+                assert_eq!(pool(1), 1);
+                assert_eq!(pool(2), 0);
+                assert_eq!(simulator.stats().partitioned, 1);
+            }
+        }
+    }
+}
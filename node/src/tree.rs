@@ -0,0 +1,400 @@
+use common::block::{Block, BlockTrait, Header};
+use common::types::{BlockTreeError, Config, One};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Every block the node knows of, across all competing branches.
+///
+/// The tree grows from a root, the first block inserted: the genesis block, or after a restart
+/// the oldest block of the best chain the node can still revert to. Any later block must extend
+/// a block already in the tree, so the tree never holds a branch it cannot trace back to the
+/// root. It is kept apart from the runtime state, which only follows a single chain.
+#[derive(Debug)]
+pub struct BlockTree<T: Config> {
+    blocks: HashMap<T::Hash, Block<T>>,
+    children: HashMap<T::Hash, Vec<T::Hash>>,
+    // Every block at a given height, in the order they were inserted
+    heights: BTreeMap<T::HeightType, Vec<T::Hash>>,
+    root: Option<T::Hash>,
+}
+
+/// Path through the tree from one block to another, by way of their common ancestor.
+#[derive(Debug, Clone)]
+pub struct TreeRoute<T: Config> {
+    pub common_ancestor: T::Hash,
+    /// Blocks from the start of the route down to the common ancestor, newest first.
+    pub retracted: Vec<T::Hash>,
+    /// Blocks from the common ancestor up to the end of the route, oldest first.
+    pub enacted: Vec<T::Hash>,
+}
+
+impl<T: Config + Serialize> Default for BlockTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Config + Serialize> BlockTree<T> {
+    pub fn new() -> Self {
+        BlockTree {
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+            heights: BTreeMap::new(),
+            root: None,
+        }
+    }
+
+    /// Add a sealed block to the tree, returning its hash.
+    ///
+    /// Unless the tree is empty, the parent of the block must already be in the tree, one height
+    /// below it.
+    pub fn insert(&mut self, block: Block<T>) -> Result<T::Hash, BlockTreeError> {
+        let hash = T::Hash::from(block.hash());
+        if self.blocks.contains_key(&hash) {
+            return Err(BlockTreeError::AlreadyKnown(hex::encode(hash)));
+        }
+
+        if self.root.is_some() {
+            let parent_hash = block.header.parent_hash;
+            let parent = self
+                .blocks
+                .get(&parent_hash)
+                .ok_or_else(|| BlockTreeError::UnknownParent(hex::encode(parent_hash)))?;
+
+            let mut expected_height = parent.header.block_height.clone();
+            expected_height += T::HeightType::one();
+            if block.header.block_height != expected_height {
+                return Err(BlockTreeError::InvalidHeight {
+                    height: block.header.block_height.to_string(),
+                    parent_height: parent.header.block_height.to_string(),
+                });
+            }
+
+            self.children.entry(parent_hash).or_default().push(hash);
+        } else {
+            self.root = Some(hash);
+        }
+
+        self.heights
+            .entry(block.header.block_height.clone())
+            .or_default()
+            .push(hash);
+        self.blocks.insert(hash, block);
+        Ok(hash)
+    }
+
+    pub fn contains(&self, hash: &T::Hash) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    pub fn block(&self, hash: &T::Hash) -> Option<&Block<T>> {
+        self.blocks.get(hash)
+    }
+
+    pub fn header(&self, hash: &T::Hash) -> Option<&Header<T>> {
+        self.blocks.get(hash).map(|block| &block.header)
+    }
+
+    /// Hash of the parent of a block, none for the root or a block not in the tree.
+    pub fn parent(&self, hash: &T::Hash) -> Option<T::Hash> {
+        if self.root.as_ref() == Some(hash) {
+            return None;
+        }
+        self.header(hash).map(|header| header.parent_hash)
+    }
+
+    /// Blocks built directly on top of a block, in the order they were inserted.
+    pub fn children(&self, hash: &T::Hash) -> &[T::Hash] {
+        self.children.get(hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// Competing blocks at a height, in the order they were inserted.
+    pub fn at_height(&self, height: &T::HeightType) -> &[T::Hash] {
+        self.heights.get(height).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn root(&self) -> Option<T::Hash> {
+        self.root
+    }
+
+    /// Head of the best chain: the highest block, the lowest hash among blocks of the same height.
+    pub fn best(&self) -> Option<T::Hash> {
+        self.heights
+            .last_key_value()
+            .and_then(|(_, hashes)| hashes.iter().min().copied())
+    }
+
+    /// Blocks of the best chain from height `from` up to the best block, oldest first.
+    pub fn best_chain(&self, from: &T::HeightType) -> Vec<T::Hash> {
+        let mut chain = Vec::new();
+        let mut next = self.best();
+        while let Some(hash) = next {
+            match self.header(&hash) {
+                Some(header) if header.block_height >= *from => chain.push(hash),
+                _ => break,
+            }
+            next = self.parent(&hash);
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// Route from `from` to `to`, none unless both are in the tree.
+    pub fn route(&self, from: &T::Hash, to: &T::Hash) -> Option<TreeRoute<T>> {
+        let (mut from, mut to) = (*from, *to);
+        let mut retracted = Vec::new();
+        let mut enacted = Vec::new();
+
+        // Walk down from the higher block first, then from both until they meet
+        while from != to {
+            let from_height = &self.header(&from)?.block_height;
+            let to_height = &self.header(&to)?.block_height;
+            if from_height >= to_height {
+                retracted.push(from);
+                from = self.parent(&from)?;
+            }
+            if to_height >= from_height {
+                enacted.push(to);
+                to = self.parent(&to)?;
+            }
+        }
+
+        enacted.reverse();
+        Some(TreeRoute {
+            common_ancestor: from,
+            retracted,
+            enacted,
+        })
+    }
+
+    /// Take a block out of the tree along with every block built on it, returning their hashes.
+    pub fn remove(&mut self, hash: &T::Hash) -> Vec<T::Hash> {
+        let Some(block) = self.blocks.get(hash) else {
+            return Vec::new();
+        };
+        if let Some(siblings) = self.children.get_mut(&block.header.parent_hash) {
+            siblings.retain(|sibling| sibling != hash);
+        }
+
+        let mut removed = Vec::new();
+        let mut pending = vec![*hash];
+        while let Some(hash) = pending.pop() {
+            let Some(block) = self.blocks.remove(&hash) else {
+                continue;
+            };
+            let height = &block.header.block_height;
+            if let Some(hashes) = self.heights.get_mut(height) {
+                hashes.retain(|other| *other != hash);
+                if hashes.is_empty() {
+                    self.heights.remove(height);
+                }
+            }
+            pending.extend(self.children.remove(&hash).unwrap_or_default());
+            removed.push(hash);
+        }
+
+        if self.root == Some(*hash) {
+            self.root = None;
+        }
+        removed
+    }
+
+    /// Tips of every branch, i.e. the blocks nothing was built on yet, lowest first.
+    pub fn leaves(&self) -> Vec<T::Hash> {
+        self.heights
+            .values()
+            .flatten()
+            .filter(|hash| self.children(hash).is_empty())
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::mock::MockConfig;
+    use common::types::Height;
+
+    // Empty block at `height` on top of `parent_hash`, siblings told apart by their author
+    fn block(height: u64, parent_hash: [u8; 32], author: u8) -> Block<MockConfig> {
+        Block {
+            header: Header {
+                block_height: Height(height),
+                parent_hash,
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author: [author; 32],
+            },
+            extrinsics: Vec::new(),
+        }
+    }
+
+    // Genesis, then 1a <- 2a and a competing 1b on top of genesis
+    fn forked_tree() -> (BlockTree<MockConfig>, [[u8; 32]; 4]) {
+        let mut tree = BlockTree::new();
+        let genesis = tree.insert(block(0, [0; 32], 0)).unwrap();
+        let one_a = tree.insert(block(1, genesis, 1)).unwrap();
+        let two_a = tree.insert(block(2, one_a, 1)).unwrap();
+        let one_b = tree.insert(block(1, genesis, 2)).unwrap();
+        (tree, [genesis, one_a, two_a, one_b])
+    }
+
+    mod insert {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_branches_are_kept_side_by_side() {
+                let (tree, [genesis, one_a, two_a, one_b]) = forked_tree();
+
+                assert_eq!(tree.len(), 4);
+                assert_eq!(tree.root(), Some(genesis));
+                assert_eq!(tree.children(&genesis), &[one_a, one_b]);
+                assert_eq!(tree.at_height(&Height(1)), &[one_a, one_b]);
+                assert_eq!(tree.at_height(&Height(3)), &[] as &[[u8; 32]]);
+                assert_eq!(tree.parent(&two_a), Some(one_a));
+                assert_eq!(tree.parent(&genesis), None);
+                assert_eq!(tree.header(&one_b).unwrap().author, [2; 32]);
+            }
+
+            #[test]
+            fn test_leaves_are_branch_tips() {
+                let (tree, [_, _, two_a, one_b]) = forked_tree();
+                assert_eq!(tree.leaves(), vec![one_b, two_a]);
+            }
+
+            #[test]
+            fn test_root_at_any_height() {
+                // A node restarting from its chain picks up the tree at the oldest block it can revert to
+                let mut tree = BlockTree::<MockConfig>::new();
+                let root = tree.insert(block(7, [9; 32], 0)).unwrap();
+                let next = tree.insert(block(8, root, 0)).unwrap();
+
+                assert_eq!(tree.parent(&root), None);
+                assert_eq!(tree.parent(&next), Some(root));
+                assert!(!tree.contains(&[9; 32]));
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_already_known() {
+                let (mut tree, [genesis, ..]) = forked_tree();
+                assert!(matches!(
+                    tree.insert(block(1, genesis, 1)),
+                    Err(BlockTreeError::AlreadyKnown(_))
+                ));
+                assert_eq!(tree.len(), 4);
+            }
+
+            #[test]
+            fn test_unknown_parent() {
+                let (mut tree, _) = forked_tree();
+                assert!(matches!(
+                    tree.insert(block(2, [7; 32], 1)),
+                    Err(BlockTreeError::UnknownParent(_))
+                ));
+                assert!(tree.at_height(&Height(2)).len() == 1);
+            }
+
+            #[test]
+            fn test_height_must_follow_parent() {
+                let (mut tree, [genesis, ..]) = forked_tree();
+                assert_eq!(
+                    tree.insert(block(2, genesis, 3)),
+                    Err(BlockTreeError::InvalidHeight {
+                        height: "2".to_string(),
+                        parent_height: "0".to_string(),
+                    })
+                );
+                assert_eq!(tree.children(&genesis).len(), 2);
+            }
+        }
+    }
+
+    mod fork_choice {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_best_is_highest_block() {
+                let (mut tree, [_, _, two_a, one_b]) = forked_tree();
+                assert_eq!(tree.best(), Some(two_a));
+
+                // The lowest hash wins among blocks of the same height
+                let two_b = tree.insert(block(2, one_b, 2)).unwrap();
+                assert_eq!(tree.best(), Some(two_a.min(two_b)));
+            }
+
+            #[test]
+            fn test_route_between_branches() {
+                let (tree, [genesis, one_a, two_a, one_b]) = forked_tree();
+
+                let route = tree.route(&two_a, &one_b).unwrap();
+                assert_eq!(route.common_ancestor, genesis);
+                assert_eq!(route.retracted, vec![two_a, one_a]);
+                assert_eq!(route.enacted, vec![one_b]);
+
+                // Along a single branch, one side of the route is empty
+                let route = tree.route(&genesis, &two_a).unwrap();
+                assert_eq!(route.common_ancestor, genesis);
+                assert!(route.retracted.is_empty());
+                assert_eq!(route.enacted, vec![one_a, two_a]);
+            }
+
+            #[test]
+            fn test_best_chain_from_height() {
+                let (tree, [genesis, one_a, two_a, _]) = forked_tree();
+                assert_eq!(tree.best_chain(&Height(0)), vec![genesis, one_a, two_a]);
+                assert_eq!(tree.best_chain(&Height(2)), vec![two_a]);
+                assert!(tree.best_chain(&Height(3)).is_empty());
+            }
+
+            #[test]
+            fn test_remove_takes_descendants() {
+                let (mut tree, [genesis, one_a, two_a, one_b]) = forked_tree();
+
+                let mut removed = tree.remove(&one_a);
+                removed.sort();
+                let mut expected = vec![one_a, two_a];
+                expected.sort();
+                assert_eq!(removed, expected);
+
+                assert_eq!(tree.len(), 2);
+                assert_eq!(tree.children(&genesis), &[one_b]);
+                assert_eq!(tree.at_height(&Height(1)), &[one_b]);
+                assert_eq!(tree.best(), Some(one_b));
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_route_to_unknown_block() {
+                let (tree, [genesis, ..]) = forked_tree();
+                assert!(tree.route(&genesis, &[7; 32]).is_none());
+                assert!(BlockTree::<MockConfig>::new().best().is_none());
+            }
+
+            #[test]
+            fn test_remove_unknown_block() {
+                let (mut tree, _) = forked_tree();
+                assert!(tree.remove(&[7; 32]).is_empty());
+                assert_eq!(tree.len(), 4);
+            }
+        }
+    }
+}
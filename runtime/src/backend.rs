@@ -0,0 +1,511 @@
+use common::types::StorageError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Batch of writes to committed data, a `None` value removing the key.
+pub type Changes = HashMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Committed key/value data underneath [`crate::State`].
+///
+/// Open transactions stay in memory on top of the backend, which only ever receives whole
+/// committed batches of writes.
+pub trait Backend {
+    fn read(&self, key: &[u8]) -> Option<&Vec<u8>>;
+    /// Apply a batch of writes, either all of them or none.
+    fn write(&mut self, changes: Changes) -> Result<(), StorageError>;
+    fn entries(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)> + '_>;
+
+    /// Apply the batch of an outermost commit, `undo` holding the values it overwrote. A backend
+    /// outliving the process keeps `undo` along with it, and forgets what all but the latest
+    /// `depth` commits overwrote, see [`Backend::take_journal`].
+    fn commit(
+        &mut self,
+        changes: Changes,
+        _undo: &Changes,
+        _depth: usize,
+    ) -> Result<(), StorageError> {
+        self.write(changes)
+    }
+
+    /// Apply `undo` to revert the latest commit that was not reverted yet.
+    fn revert(&mut self, undo: Changes) -> Result<(), StorageError> {
+        self.write(undo)
+    }
+
+    /// What every commit that was not reverted overwrote, oldest first, as found when the backend
+    /// was opened. Empty for a backend that does not outlive the process, or once taken.
+    fn take_journal(&mut self) -> VecDeque<Changes> {
+        VecDeque::new()
+    }
+}
+
+// Pairs written together, as they are laid out in the log of a `FileBackend`
+type Batch = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+// Entry of the log of a `FileBackend`
+#[derive(Serialize, Deserialize)]
+enum Record {
+    // Writes made outside of any commit
+    Write(Batch),
+    // An outermost commit, along with the values it overwrote and the number of latest commits,
+    // this one included, whose undo is kept
+    Commit {
+        pairs: Batch,
+        undo: Batch,
+        depth: usize,
+    },
+    // Undo of the latest commit that was not reverted yet
+    Revert(Batch),
+}
+
+// Length-prefixed encoding of `record`, as laid out in the log
+fn encode(record: &Record) -> Result<Vec<u8>, StorageError> {
+    let encoded =
+        bincode::serialize(record).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+    let length = u32::try_from(encoded.len())
+        .map_err(|_| StorageError::DataInsertionError("Batch too large".to_string()))?;
+
+    let mut bytes = length.to_le_bytes().to_vec();
+    bytes.extend(encoded);
+    Ok(bytes)
+}
+
+fn apply(
+    data: &mut HashMap<Vec<u8>, Vec<u8>>,
+    changes: impl IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
+) {
+    for (key, value) in changes {
+        match value {
+            Some(value) => data.insert(key, value),
+            None => data.remove(&key),
+        };
+    }
+}
+
+/// Keeps everything in memory, lost when the process exits.
+pub type MemoryBackend = HashMap<Vec<u8>, Vec<u8>>;
+
+impl Backend for MemoryBackend {
+    fn read(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        self.get(key)
+    }
+
+    fn write(&mut self, changes: Changes) -> Result<(), StorageError> {
+        apply(self, changes);
+        Ok(())
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)> + '_> {
+        Box::new(self.iter())
+    }
+}
+
+/// Append-only log of committed batches, with an in-memory index rebuilt from it on open.
+///
+/// Every batch is a little-endian `u32` length followed by the bincode-encoded record: the pairs
+/// written, a pair without a value removing its key, along with what they overwrote for a commit.
+/// The journal of the latest commits that were not reverted, as many as the pruning depth they
+/// were made with, is rebuilt on open as well, so that they can still be reverted after a
+/// restart. A batch cut short by a crash is dropped on the next open, so the log always holds
+/// whole batches.
+///
+/// The log grows with every batch while the backend is open. Opening it compacts it into a single
+/// batch of the live entries, followed by the journal.
+#[derive(Debug)]
+pub struct FileBackend {
+    path: PathBuf,
+    file: File,
+    index: HashMap<Vec<u8>, Vec<u8>>,
+    // Until taken by the state on top of the backend
+    journal: VecDeque<Changes>,
+    // Length of the unfinished batch found at the end of the log on open
+    truncated: usize,
+}
+
+impl FileBackend {
+    /// Open the log at `path`, creating it if needed, replay it into the index and compact it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let failed =
+            |e: std::io::Error| StorageError::OperationFailed(format!("{}: {}", path.display(), e));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(failed)?;
+
+        let mut log = Vec::new();
+        file.read_to_end(&mut log).map_err(failed)?;
+
+        let mut index = HashMap::new();
+        let mut journal = VecDeque::new();
+        let mut offset = 0;
+        while let Some(batch) = Self::next_batch(&log, offset) {
+            let (record, next) = batch?;
+            match record {
+                Record::Write(pairs) => apply(&mut index, pairs),
+                Record::Commit { pairs, undo, depth } => {
+                    apply(&mut index, pairs);
+                    journal.push_back(undo.into_iter().collect());
+                    while journal.len() > depth {
+                        journal.pop_front();
+                    }
+                }
+                Record::Revert(pairs) => {
+                    apply(&mut index, pairs);
+                    journal.pop_back();
+                }
+            }
+            offset = next;
+        }
+
+        // A batch the last run did not finish writing is left out of the compacted log
+        let truncated = log.len() - offset;
+        let file = Self::compact(&path, &index, &journal)?;
+
+        Ok(FileBackend {
+            path,
+            file,
+            index,
+            journal,
+            truncated,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of bytes of an unfinished batch, cut short by a crash, that were dropped from the
+    /// end of the log when it was opened.
+    pub fn truncated(&self) -> usize {
+        self.truncated
+    }
+
+    // Write the log that `index` and `journal` were replayed from anew, with one batch for the
+    // index and one per commit of the journal. It replaces the log only once it is whole on disk
+    fn compact(
+        path: &Path,
+        index: &HashMap<Vec<u8>, Vec<u8>>,
+        journal: &VecDeque<Changes>,
+    ) -> Result<File, StorageError> {
+        let failed =
+            |e: std::io::Error| StorageError::OperationFailed(format!("{}: {}", path.display(), e));
+        let mut compacted = path.as_os_str().to_owned();
+        compacted.push(".compact");
+
+        let mut records = Vec::new();
+        if !index.is_empty() {
+            let pairs = index
+                .iter()
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect();
+            records.extend(encode(&Record::Write(pairs))?);
+        }
+        for undo in journal {
+            records.extend(encode(&Record::Commit {
+                pairs: Batch::new(),
+                undo: undo.clone().into_iter().collect(),
+                depth: journal.len(),
+            })?);
+        }
+        let mut file = File::create(&compacted).map_err(failed)?;
+        file.write_all(&records)
+            .and_then(|_| file.sync_data())
+            .map_err(failed)?;
+        std::fs::rename(&compacted, path).map_err(failed)?;
+
+        OpenOptions::new().append(true).open(path).map_err(failed)
+    }
+
+    // Decode the batch starting at `offset`, `None` if the log does not hold a whole one there
+    fn next_batch(log: &[u8], offset: usize) -> Option<Result<(Record, usize), StorageError>> {
+        let header = log.get(offset..offset + 4)?;
+        let length = u32::from_le_bytes(header.try_into().expect("Header is 4 bytes")) as usize;
+        let start = offset + 4;
+        let encoded = log.get(start..start + length)?;
+        Some(
+            bincode::deserialize(encoded)
+                .map(|pairs| (pairs, start + length))
+                .map_err(|e| StorageError::DeserializationError(e.to_string())),
+        )
+    }
+}
+
+impl FileBackend {
+    // Append `record` to the log, the index only follows once it is safely on disk
+    fn append(&mut self, record: &Record) -> Result<(), StorageError> {
+        let record = encode(record)?;
+        let end = self
+            .file
+            .metadata()
+            .map_err(|e| StorageError::DataInsertionError(e.to_string()))?
+            .len();
+        if let Err(e) = self
+            .file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data())
+        {
+            // Cut off whatever part of the record made it, later batches must follow a whole one
+            let _ = self.file.set_len(end);
+            return Err(StorageError::DataInsertionError(e.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Backend for FileBackend {
+    fn read(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        self.index.get(key)
+    }
+
+    fn write(&mut self, changes: Changes) -> Result<(), StorageError> {
+        let pairs: Batch = changes.into_iter().collect();
+        self.append(&Record::Write(pairs.clone()))?;
+        apply(&mut self.index, pairs);
+        Ok(())
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)> + '_> {
+        Box::new(self.index.iter())
+    }
+
+    fn commit(
+        &mut self,
+        changes: Changes,
+        undo: &Changes,
+        depth: usize,
+    ) -> Result<(), StorageError> {
+        let pairs: Batch = changes.into_iter().collect();
+        self.append(&Record::Commit {
+            pairs: pairs.clone(),
+            undo: undo.clone().into_iter().collect(),
+            depth,
+        })?;
+        apply(&mut self.index, pairs);
+        Ok(())
+    }
+
+    fn revert(&mut self, undo: Changes) -> Result<(), StorageError> {
+        let pairs: Batch = undo.into_iter().collect();
+        self.append(&Record::Revert(pairs.clone()))?;
+        apply(&mut self.index, pairs);
+        Ok(())
+    }
+
+    fn take_journal(&mut self) -> VecDeque<Changes> {
+        std::mem::take(&mut self.journal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::State;
+    use std::fs;
+
+    // A log path of its own for every test, removed beforehand in case an earlier run left it
+    fn log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("backend-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn batch(pairs: &[(u8, u8)]) -> Changes {
+        pairs
+            .iter()
+            .map(|(key, value)| (vec![*key], Some(vec![*value])))
+            .collect()
+    }
+
+    mod file_backend {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_reopen_replays_batches() {
+                let path = log_path("reopen");
+                {
+                    let mut backend = FileBackend::open(&path).unwrap();
+                    backend.write(batch(&[(1, 10), (2, 20)])).unwrap();
+                    backend.write(batch(&[(1, 11)])).unwrap();
+                }
+
+                let backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[1]), Some(&vec![11]));
+                assert_eq!(backend.read(&[2]), Some(&vec![20]));
+                assert_eq!(backend.entries().count(), 2);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_removals_are_replayed() {
+                let path = log_path("removals");
+                {
+                    let mut backend = FileBackend::open(&path).unwrap();
+                    backend.write(batch(&[(1, 10), (2, 20)])).unwrap();
+                    backend.write(Changes::from([(vec![1], None)])).unwrap();
+                    assert_eq!(backend.read(&[1]), None);
+                }
+
+                let backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[1]), None);
+                assert_eq!(backend.read(&[2]), Some(&vec![20]));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_journal_is_replayed() {
+                let path = log_path("journal");
+                {
+                    let mut backend = FileBackend::open(&path).unwrap();
+                    backend.write(batch(&[(1, 10)])).unwrap();
+                    backend
+                        .commit(batch(&[(1, 11)]), &batch(&[(1, 10)]), 2)
+                        .unwrap();
+                    let undo = Changes::from([(vec![2], None)]);
+                    backend.commit(batch(&[(2, 20)]), &undo, 2).unwrap();
+                    backend.revert(undo).unwrap();
+                }
+
+                // Only the commit that was not reverted is left to revert
+                let mut backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[1]), Some(&vec![11]));
+                assert_eq!(backend.read(&[2]), None);
+                assert_eq!(backend.take_journal(), VecDeque::from([batch(&[(1, 10)])]));
+                assert!(backend.take_journal().is_empty());
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_state_reverts_commits_of_an_earlier_run() {
+                let path = log_path("state");
+                {
+                    let mut state = State::with_backend(FileBackend::open(&path).unwrap());
+                    state.start_transaction();
+                    state.insert(vec![1], vec![10]).unwrap();
+                    state.commit_transaction().unwrap();
+                    state.start_transaction();
+                    state.insert(vec![1], vec![11]).unwrap();
+                    state.commit_transaction().unwrap();
+                }
+
+                let mut state = State::with_backend(FileBackend::open(&path).unwrap());
+                assert!(state.view(2).is_some());
+                state.revert_commit().unwrap();
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+                drop(state);
+
+                // The revert is logged as well
+                let mut state = State::with_backend(FileBackend::open(&path).unwrap());
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+                state.revert_commit().unwrap();
+                assert!(state.get(vec![1]).is_none());
+                assert!(state.revert_commit().is_err());
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_log_is_compacted_on_open() {
+                let path = log_path("compact");
+                {
+                    let mut backend = FileBackend::open(&path).unwrap();
+                    for value in 0..10 {
+                        backend.write(batch(&[(1, value), (2, value)])).unwrap();
+                    }
+                }
+                let grown = fs::metadata(&path).unwrap().len();
+
+                // Only the last value of each key is left
+                let backend = FileBackend::open(&path).unwrap();
+                let compacted = fs::metadata(&path).unwrap().len();
+                assert!(compacted < grown / 5);
+                assert_eq!(backend.truncated(), 0);
+                drop(backend);
+
+                let backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[1]), Some(&vec![9]));
+                assert_eq!(backend.read(&[2]), Some(&vec![9]));
+                assert_eq!(fs::metadata(&path).unwrap().len(), compacted);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_journal_is_pruned_in_the_log() {
+                let path = log_path("pruned");
+                {
+                    let mut state = State::with_backend(FileBackend::open(&path).unwrap());
+                    state.set_pruning_depth(2);
+                    for value in 0..5 {
+                        state.start_transaction();
+                        state.insert(vec![1], vec![value]).unwrap();
+                        state.commit_transaction().unwrap();
+                    }
+                }
+
+                // Only the latest two commits are left to revert, before the state takes them
+                let mut backend = FileBackend::open(&path).unwrap();
+                assert_eq!(
+                    backend.take_journal(),
+                    VecDeque::from([batch(&[(1, 2)]), batch(&[(1, 3)])])
+                );
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_unfinished_batch_is_dropped() {
+                let path = log_path("unfinished");
+                {
+                    let mut backend = FileBackend::open(&path).unwrap();
+                    backend.write(batch(&[(1, 10)])).unwrap();
+                }
+                let whole = fs::metadata(&path).unwrap().len();
+
+                // A crash halfway through the second batch
+                let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+                file.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+
+                let mut backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[1]), Some(&vec![10]));
+                assert_eq!(backend.truncated(), 6);
+                assert_eq!(fs::metadata(&path).unwrap().len(), whole);
+
+                // Later batches are appended after the last whole one
+                backend.write(batch(&[(2, 20)])).unwrap();
+                let backend = FileBackend::open(&path).unwrap();
+                assert_eq!(backend.read(&[2]), Some(&vec![20]));
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_corrupt_batch() {
+                let path = log_path("corrupt");
+                fs::write(&path, [1, 0, 0, 0, 0xff]).unwrap();
+
+                assert!(matches!(
+                    FileBackend::open(&path),
+                    Err(StorageError::DeserializationError(_))
+                ));
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn test_open_missing_directory() {
+                let path = log_path("missing").join("chain.log");
+                assert!(matches!(
+                    FileBackend::open(path),
+                    Err(StorageError::OperationFailed(_))
+                ));
+            }
+        }
+    }
+}
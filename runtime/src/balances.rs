@@ -0,0 +1,499 @@
+use common::account::Account;
+use common::event::BalancesEvent;
+use common::types::{
+    BalancesCall, CheckedAdd, Config, One, Origin, StorageError, StoragePrefix, TransactionError,
+    Zero,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use crate::pallet::{Context, Pallet};
+use crate::plugin::StoragePlugin;
+
+/// Accounts, their balances and the total issuance.
+pub struct Balances<T>(PhantomData<T>);
+
+impl<T: Config> Pallet<T> for Balances<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    const PREFIX: StoragePrefix = StoragePrefix::Account;
+
+    type Call = BalancesCall<T>;
+    type Event = BalancesEvent<T>;
+
+    fn dispatch<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        origin: Origin<T::Hash>,
+        call: &BalancesCall<T>,
+    ) -> Result<(), TransactionError<T>> {
+        match *call {
+            BalancesCall::Transfer { amount, from, to } => {
+                // Moving funds out of an account requires the account's own signature
+                if origin != Origin::Signed(from) {
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                // Both accounts must exist and the sender must be able to afford the amount
+                let from_account = Self::account(ctx.plugin, from)?;
+                Self::account(ctx.plugin, to)?;
+                Self::ensure_can_withdraw(&from_account, amount)?;
+
+                // Update the sender's account
+                Self::set_account(
+                    ctx,
+                    &Account {
+                        balance: from_account.balance - amount,
+                        ..from_account
+                    },
+                )?;
+
+                // Update the receiver's account, read again in case it is the sender
+                let to_account = Self::account(ctx.plugin, to)?;
+                Self::set_account(
+                    ctx,
+                    &Account {
+                        balance: to_account
+                            .balance
+                            .checked_add(&amount)
+                            .ok_or(TransactionError::Overflow)?,
+                        ..to_account
+                    },
+                )?;
+
+                ctx.deposit_event(BalancesEvent::Transferred { from, to, amount });
+            }
+            BalancesCall::Mint { amount, to } => {
+                // New funds can only be issued by the chain itself
+                if origin != Origin::Root {
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                // Minted funds are new to the supply
+                let issuance = Self::total_issuance(ctx.plugin)?
+                    .checked_add(&amount)
+                    .ok_or(TransactionError::Overflow)?;
+
+                // Update the receiver's account
+                let to_account = Self::account(ctx.plugin, to)?;
+                Self::set_account(
+                    ctx,
+                    &Account {
+                        balance: to_account
+                            .balance
+                            .checked_add(&amount)
+                            .ok_or(TransactionError::Overflow)?,
+                        ..to_account
+                    },
+                )?;
+                Self::set_total_issuance(ctx, issuance)?;
+
+                ctx.deposit_event(BalancesEvent::Minted { to, amount });
+            }
+            BalancesCall::Burn { amount, from } => {
+                // Only the owner of the funds can burn them
+                if origin != Origin::Signed(from) {
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                // Update the sender's account, which cannot burn more than it holds
+                let from_account = Self::account(ctx.plugin, from)?;
+                Self::ensure_can_withdraw(&from_account, amount)?;
+                Self::set_account(
+                    ctx,
+                    &Account {
+                        balance: from_account.balance - amount,
+                        ..from_account
+                    },
+                )?;
+
+                // Burnt funds leave the supply
+                Self::set_total_issuance(ctx, Self::total_issuance(ctx.plugin)? - amount)?;
+
+                ctx.deposit_event(BalancesEvent::Burned { from, amount });
+            }
+            BalancesCall::AccountCreation {
+                account_id,
+                balance,
+            } => {
+                // The initial balance is new to the supply, so only the chain itself creates
+                // accounts this way, e.g. from the genesis config
+                if origin != Origin::Root {
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                // The account must not exist yet
+                match Self::account(ctx.plugin, account_id) {
+                    Err(TransactionError::AccountNotFound(_)) => {}
+                    Ok(_) => return Err(TransactionError::AccountAlreadyExists(account_id)),
+                    Err(e) => return Err(e),
+                }
+
+                // Add the account to the state
+                Self::set_account(
+                    ctx,
+                    &Account {
+                        account_id,
+                        balance,
+                        nonce: T::Nonce::zero(),
+                    },
+                )?;
+
+                // The initial balance is issued along with the account
+                let issuance = Self::total_issuance(ctx.plugin)?
+                    .checked_add(&balance)
+                    .ok_or(TransactionError::Overflow)?;
+                Self::set_total_issuance(ctx, issuance)?;
+
+                ctx.deposit_event(BalancesEvent::AccountCreated {
+                    account_id,
+                    balance,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Config> Balances<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    /// Read an account, a missing one fails the transaction.
+    pub fn account<S: StoragePlugin>(
+        plugin: &S,
+        account_id: T::Hash,
+    ) -> Result<Account<T>, TransactionError<T>> {
+        match plugin.get(Self::PREFIX, account_id) {
+            Err(StorageError::KeyNotFound(_)) => Err(TransactionError::AccountNotFound(account_id)),
+            result => result.map_err(TransactionError::Storage),
+        }
+    }
+
+    pub fn set_account<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        account: &Account<T>,
+    ) -> Result<(), StorageError> {
+        ctx.plugin.set(Self::PREFIX, account.account_id, account)
+    }
+
+    pub fn ensure_can_withdraw(
+        account: &Account<T>,
+        amount: T::Funds,
+    ) -> Result<(), TransactionError<T>> {
+        if account.balance < amount {
+            return Err(TransactionError::InsufficientBalance {
+                account_id: account.account_id,
+                balance: account.balance,
+                amount,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add `amount` to the balance of an account, creating it if needed. The total issuance is
+    /// left to the caller.
+    pub fn credit<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        account_id: T::Hash,
+        amount: T::Funds,
+    ) -> Result<(), TransactionError<T>> {
+        let account = match Self::account(ctx.plugin, account_id) {
+            Ok(account) => Account {
+                balance: account
+                    .balance
+                    .checked_add(&amount)
+                    .ok_or(TransactionError::Overflow)?,
+                ..account
+            },
+            Err(TransactionError::AccountNotFound(_)) => {
+                ctx.deposit_event(BalancesEvent::AccountCreated {
+                    account_id,
+                    balance: amount,
+                });
+                Account {
+                    account_id,
+                    balance: amount,
+                    nonce: T::Nonce::zero(),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        Self::set_account(ctx, &account)?;
+        Ok(())
+    }
+
+    /// Check that `nonce` is the next one of the signer and that it can pay `fee`, then consume
+    /// the nonce and withdraw the fee. Where the fee goes is left to the caller.
+    pub fn charge_transaction<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        signer: T::Hash,
+        nonce: T::Nonce,
+        fee: T::Funds,
+    ) -> Result<(), TransactionError<T>> {
+        let account = Self::account(ctx.plugin, signer)?;
+        if nonce != account.nonce {
+            return Err(TransactionError::InvalidNonce {
+                expected: account.nonce,
+                got: nonce,
+            });
+        }
+        if account.balance < fee {
+            return Err(TransactionError::CannotPayFee {
+                account_id: signer,
+                balance: account.balance,
+                fee,
+            });
+        }
+
+        Self::set_account(
+            ctx,
+            &Account {
+                balance: account.balance - fee,
+                nonce: account.nonce + T::Nonce::one(),
+                ..account
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Funds in existence across all accounts, nothing has been issued before the first account.
+    pub fn total_issuance<S: StoragePlugin>(plugin: &S) -> Result<T::Funds, StorageError> {
+        match plugin.get(StoragePrefix::TotalIssuance, ()) {
+            Err(StorageError::KeyNotFound(_)) => Ok(T::Funds::from(0)),
+            result => result,
+        }
+    }
+
+    pub fn set_total_issuance<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        total_issuance: T::Funds,
+    ) -> Result<(), StorageError> {
+        ctx.plugin
+            .set(StoragePrefix::TotalIssuance, (), &total_issuance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::Plugin;
+    use common::event::Event;
+    use common::mock::FeelessMockConfig as MockConfig;
+
+    const ALICE: [u8; 32] = [1; 32];
+    const DAVE: [u8; 32] = [2; 32];
+
+    // Dispatch `calls` one after the other on top of ALICE with 100, stopping at the first failure
+    fn dispatch(
+        calls: Vec<(Origin<[u8; 32]>, BalancesCall<MockConfig>)>,
+    ) -> (
+        Plugin,
+        Vec<Event<MockConfig>>,
+        Result<(), TransactionError<MockConfig>>,
+    ) {
+        let mut plugin = Plugin::new();
+        let mut events = Vec::new();
+        let mut ctx = Context::new(&mut plugin, &mut events);
+        let genesis = BalancesCall::AccountCreation {
+            account_id: ALICE,
+            balance: 100,
+        };
+        Balances::dispatch(&mut ctx, Origin::Root, &genesis).unwrap();
+
+        let result = calls
+            .iter()
+            .try_for_each(|(origin, call)| Balances::dispatch(&mut ctx, *origin, call));
+        (plugin, events, result)
+    }
+
+    fn balance(plugin: &Plugin, account_id: [u8; 32]) -> u128 {
+        Balances::<MockConfig>::account(plugin, account_id)
+            .unwrap()
+            .balance
+    }
+
+    mod dispatch {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_transfer() {
+                let (plugin, events, result) = dispatch(vec![
+                    (
+                        Origin::Root,
+                        BalancesCall::AccountCreation {
+                            account_id: DAVE,
+                            balance: 0,
+                        },
+                    ),
+                    (
+                        Origin::Signed(ALICE),
+                        BalancesCall::Transfer {
+                            from: ALICE,
+                            to: DAVE,
+                            amount: 30,
+                        },
+                    ),
+                ]);
+
+                assert!(result.is_ok());
+                assert_eq!(balance(&plugin, ALICE), 70);
+                assert_eq!(balance(&plugin, DAVE), 30);
+                assert!(matches!(
+                    events.last(),
+                    Some(Event::Balances(BalancesEvent::Transferred {
+                        amount: 30,
+                        ..
+                    }))
+                ));
+            }
+
+            #[test]
+            fn test_mint_and_burn_follow_issuance() {
+                let (plugin, _, result) = dispatch(vec![
+                    (
+                        Origin::Root,
+                        BalancesCall::Mint {
+                            to: ALICE,
+                            amount: 50,
+                        },
+                    ),
+                    (
+                        Origin::Signed(ALICE),
+                        BalancesCall::Burn {
+                            from: ALICE,
+                            amount: 20,
+                        },
+                    ),
+                ]);
+
+                assert!(result.is_ok());
+                assert_eq!(balance(&plugin, ALICE), 130);
+                assert_eq!(
+                    Balances::<MockConfig>::total_issuance(&plugin).unwrap(),
+                    130
+                );
+            }
+
+            #[test]
+            fn test_credit_creates_account() {
+                let mut plugin = Plugin::new();
+                let mut events = Vec::new();
+                let mut ctx = Context::<MockConfig>::new(&mut plugin, &mut events);
+                Balances::credit(&mut ctx, DAVE, 10).unwrap();
+                Balances::credit(&mut ctx, DAVE, 5).unwrap();
+
+                assert_eq!(balance(&plugin, DAVE), 15);
+                assert_eq!(events.len(), 1);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_transfer_to_missing_account() {
+                let (_, _, result) = dispatch(vec![(
+                    Origin::Signed(ALICE),
+                    BalancesCall::Transfer {
+                        from: ALICE,
+                        to: DAVE,
+                        amount: 30,
+                    },
+                )]);
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::AccountNotFound(account_id)) if account_id == DAVE
+                ));
+            }
+
+            #[test]
+            fn test_transfer_requires_sender_origin() {
+                let (plugin, _, result) = dispatch(vec![(
+                    Origin::Signed(DAVE),
+                    BalancesCall::Transfer {
+                        from: ALICE,
+                        to: DAVE,
+                        amount: 30,
+                    },
+                )]);
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(balance(&plugin, ALICE), 100);
+            }
+
+            #[test]
+            fn test_mint_requires_root() {
+                let (plugin, _, result) = dispatch(vec![(
+                    Origin::Signed(ALICE),
+                    BalancesCall::Mint {
+                        to: ALICE,
+                        amount: 50,
+                    },
+                )]);
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(balance(&plugin, ALICE), 100);
+            }
+
+            #[test]
+            fn test_account_creation_requires_root() {
+                let (plugin, _, result) = dispatch(vec![(
+                    Origin::None,
+                    BalancesCall::AccountCreation {
+                        account_id: DAVE,
+                        balance: 10,
+                    },
+                )]);
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert!(Balances::<MockConfig>::account(&plugin, DAVE).is_err());
+            }
+
+            #[test]
+            fn test_mint_overflow() {
+                let (plugin, _, result) = dispatch(vec![(
+                    Origin::Root,
+                    BalancesCall::Mint {
+                        to: ALICE,
+                        amount: u128::MAX,
+                    },
+                )]);
+                assert!(matches!(result, Err(TransactionError::Overflow)));
+                assert_eq!(balance(&plugin, ALICE), 100);
+                assert_eq!(
+                    Balances::<MockConfig>::total_issuance(&plugin).unwrap(),
+                    100
+                );
+            }
+
+            #[test]
+            fn test_account_creation_overflow() {
+                let (_, _, result) = dispatch(vec![(
+                    Origin::Root,
+                    BalancesCall::AccountCreation {
+                        account_id: DAVE,
+                        balance: u128::MAX,
+                    },
+                )]);
+                assert!(matches!(result, Err(TransactionError::Overflow)));
+            }
+
+            #[test]
+            fn test_account_creation_of_existing_account() {
+                let (_, _, result) = dispatch(vec![(
+                    Origin::Root,
+                    BalancesCall::AccountCreation {
+                        account_id: ALICE,
+                        balance: 10,
+                    },
+                )]);
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::AccountAlreadyExists(_))
+                ));
+            }
+        }
+    }
+}
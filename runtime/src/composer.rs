@@ -0,0 +1,233 @@
+use common::event::Event;
+use common::extrinsics::SignedTransaction;
+use common::types::{CheckedAdd, CheckedSub, Config, FeeDestination, Get, Origin, StorageError};
+use common::types::{StoragePrefix, TransactionError, TransactionType};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use crate::balances::Balances;
+use crate::pallet::{Context, Pallet};
+use crate::plugin::StoragePlugin;
+
+/// The runtime put together from its pallets.
+///
+/// Every variant of the outer call is routed to the pallet owning it, adding a pallet only takes
+/// a new variant of `TransactionType` and a new arm here. Calls that are not owned by any pallet
+/// (the coinbase inherent and sudo) are handled by the runtime itself.
+pub struct Runtime<T>(PhantomData<T>);
+
+impl<T: Config> Runtime<T>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    pub fn dispatch<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        origin: Origin<T::Hash>,
+        call: &TransactionType<T>,
+    ) -> Result<(), TransactionError<T>> {
+        match call {
+            TransactionType::Balances(call) => Balances::<T>::dispatch(ctx, origin, call),
+            TransactionType::Coinbase { author } => {
+                // Inherents are put in the block by its author, nobody signs them
+                if origin != Origin::None {
+                    return Err(TransactionError::BadOrigin);
+                }
+                // The reward is new to the supply
+                let amount = T::BlockReward::get();
+                let issuance = Balances::<T>::total_issuance(ctx.plugin)?
+                    .checked_add(&amount)
+                    .ok_or(TransactionError::Overflow)?;
+                Balances::<T>::credit(ctx, *author, amount)?;
+                Balances::<T>::set_total_issuance(ctx, issuance)?;
+
+                ctx.deposit_event(Event::Rewarded {
+                    author: *author,
+                    amount,
+                });
+                Ok(())
+            }
+            TransactionType::Sudo { call } => {
+                // The sudo key must have signed, there is none unless set at genesis
+                let sudo_key = match ctx.plugin.get(StoragePrefix::Sudo, ()) {
+                    Err(StorageError::KeyNotFound(_)) => None,
+                    result => Some(result?),
+                };
+                match (origin, sudo_key) {
+                    (Origin::Signed(signer), Some(sudo_key)) if signer == sudo_key => {}
+                    _ => return Err(TransactionError::BadOrigin),
+                }
+
+                Self::dispatch(ctx, Origin::Root, call)
+            }
+        }
+    }
+
+    /// Charge the signer of `transaction` before its call is dispatched by the block authored by
+    /// `author`: its nonce is consumed and `fee` handed over to the configured destination.
+    /// Unsigned transactions are free.
+    pub fn pre_dispatch<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        transaction: &SignedTransaction<T>,
+        fee: T::Funds,
+        author: T::Hash,
+    ) -> Result<(), TransactionError<T>> {
+        if let Some(signer) = transaction.signer() {
+            Balances::<T>::charge_transaction(ctx, signer, transaction.nonce, fee)?;
+            if fee > T::Funds::from(0) {
+                Self::route_fee(ctx, signer, fee, author)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Hand a fee withdrawn from `payer` over to the configured destination
+    fn route_fee<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        payer: T::Hash,
+        fee: T::Funds,
+        author: T::Hash,
+    ) -> Result<(), TransactionError<T>> {
+        match T::FeeDestination::get() {
+            // The treasury comes into existence with the first fee it receives
+            FeeDestination::Treasury(treasury) => Balances::<T>::credit(ctx, treasury, fee)?,
+            FeeDestination::Author => Balances::<T>::credit(ctx, author, fee)?,
+            FeeDestination::Burn => {
+                let issuance = Balances::<T>::total_issuance(ctx.plugin)?
+                    .checked_sub(&fee)
+                    .ok_or(TransactionError::Overflow)?;
+                Balances::<T>::set_total_issuance(ctx, issuance)?;
+            }
+        }
+
+        ctx.deposit_event(Event::FeePaid {
+            who: payer,
+            amount: fee,
+        });
+        Ok(())
+    }
+
+    /// Run the `on_initialize` hook of every pallet, returning the weight they consumed together
+    /// with the runtime's own overhead.
+    pub fn on_initialize<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        block_height: &T::HeightType,
+    ) -> Result<T::WeightType, StorageError> {
+        let mut weight = T::BlockOverhead::get();
+        weight += Balances::<T>::on_initialize(ctx, block_height)?;
+        Ok(weight)
+    }
+
+    /// Run the `on_finalize` hook of every pallet, returning the weight they consumed together.
+    pub fn on_finalize<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        block_height: &T::HeightType,
+    ) -> Result<T::WeightType, StorageError> {
+        let mut weight = T::WeightType::from(0);
+        weight += Balances::<T>::on_finalize(ctx, block_height)?;
+        Ok(weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::Plugin;
+    use common::mock::FeelessMockConfig as MockConfig;
+    use common::types::BalancesCall;
+
+    const SUDO: [u8; 32] = [1; 32];
+
+    fn mint() -> TransactionType<MockConfig> {
+        BalancesCall::Mint {
+            to: SUDO,
+            amount: 50,
+        }
+        .into()
+    }
+
+    fn sudo(call: TransactionType<MockConfig>) -> TransactionType<MockConfig> {
+        TransactionType::Sudo {
+            call: Box::new(call),
+        }
+    }
+
+    // Dispatch `call` on top of SUDO holding 100 and the sudo key
+    fn dispatch(
+        origin: Origin<[u8; 32]>,
+        call: TransactionType<MockConfig>,
+    ) -> (Plugin, Result<(), TransactionError<MockConfig>>) {
+        let mut plugin = Plugin::new();
+        let mut events = Vec::new();
+        plugin.set(StoragePrefix::Sudo, (), &SUDO).unwrap();
+        let mut ctx = Context::new(&mut plugin, &mut events);
+        let genesis = BalancesCall::AccountCreation {
+            account_id: SUDO,
+            balance: 100,
+        };
+        Runtime::dispatch(&mut ctx, Origin::Root, &genesis.into()).unwrap();
+
+        let result = Runtime::dispatch(&mut ctx, origin, &call);
+        (plugin, result)
+    }
+
+    mod dispatch {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_sudo_dispatches_as_root() {
+                let (plugin, result) = dispatch(Origin::Signed(SUDO), sudo(mint()));
+                assert!(result.is_ok());
+                assert_eq!(
+                    Balances::<MockConfig>::total_issuance(&plugin).unwrap(),
+                    150
+                );
+            }
+
+            #[test]
+            fn test_coinbase_rewards_author() {
+                let author = [9; 32];
+                let (plugin, result) = dispatch(Origin::None, TransactionType::Coinbase { author });
+                assert!(result.is_ok());
+                assert_eq!(
+                    Balances::<MockConfig>::account(&plugin, author)
+                        .unwrap()
+                        .balance,
+                    100
+                );
+                assert_eq!(
+                    Balances::<MockConfig>::total_issuance(&plugin).unwrap(),
+                    200
+                );
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_signed_coinbase() {
+                let author = [9; 32];
+                let (plugin, result) =
+                    dispatch(Origin::Signed(author), TransactionType::Coinbase { author });
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert!(Balances::<MockConfig>::account(&plugin, author).is_err());
+            }
+
+            #[test]
+            fn test_sudo_signed_by_someone_else() {
+                let (_, result) = dispatch(Origin::Signed([2; 32]), sudo(mint()));
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+            }
+
+            #[test]
+            fn test_nested_sudo() {
+                // The inner sudo is dispatched as Root, which is not the sudo key
+                let (_, result) = dispatch(Origin::Signed(SUDO), sudo(sudo(mint())));
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+            }
+        }
+    }
+}
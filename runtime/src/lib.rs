@@ -1,34 +1,230 @@
-use common::types::StorageError;
+use backend::{Backend, Changes, MemoryBackend};
+use common::trie::{StorageProof, Trie};
+use common::types::{StorageError, StoragePrefix};
+use plugin::{Plugin, StoragePlugin};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Debug;
+pub mod backend;
+pub mod balances;
+pub mod composer;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod pallet;
 pub mod plugin;
+pub mod proof;
 pub mod stf;
 
-#[derive(Serialize)]
-pub struct State {
-    data: HashMap<Vec<u8>, Vec<u8>>,
+/// Number of latest commits a [`State`] keeps the reverse diffs of, unless told otherwise.
+pub const DEFAULT_PRUNING_DEPTH: usize = 256;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct State<B: Backend = MemoryBackend> {
+    data: B,
+    // Uncommitted write layers, the last one being the innermost open transaction
+    #[serde(skip)]
+    overlays: Vec<HashMap<Vec<u8>, Vec<u8>>>,
+    // What every outermost commit overwrote, the last one being the latest commit
+    #[serde(skip)]
+    journal: VecDeque<Changes>,
+    // Number of commits kept in the journal, older ones are dropped
+    #[serde(skip)]
+    pruning_depth: usize,
+    // Root of what reads currently see, dropped by any write it covers
+    #[serde(skip)]
+    root: CachedRoot,
+}
+
+// Being derived from the pairs, the cached root is left out of comparisons
+#[derive(Debug, Clone, Default)]
+struct CachedRoot(OnceCell<[u8; 32]>);
+
+impl PartialEq for CachedRoot {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
+    pub fn new() -> Self {
+        Self::with_backend(MemoryBackend::new())
+    }
+}
+
+impl<B: Backend> State<B> {
+    // State on top of whatever `backend` already holds, the commits it journaled included
+    pub fn with_backend(mut backend: B) -> Self {
+        let journal = backend.take_journal();
+        let mut state = State {
+            data: backend,
+            overlays: Vec::new(),
+            journal,
+            pruning_depth: DEFAULT_PRUNING_DEPTH,
+            root: CachedRoot::default(),
+        };
+        state.prune();
+        state
+    }
+
+    // Keep what the latest `depth` commits overwrote, older commits can no longer be reverted
+    // or viewed
+    pub fn set_pruning_depth(&mut self, depth: usize) {
+        self.pruning_depth = depth;
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        while self.journal.len() > self.pruning_depth {
+            self.journal.pop_front();
+        }
+    }
+
+    // Committed state as it was before the latest `depth` commits, none once they are pruned
+    pub fn view(&self, depth: usize) -> Option<StateView<'_>> {
+        if depth > self.journal.len() {
+            return None;
+        }
+
+        // A key takes the value from before the oldest of the commits that touched it
+        let mut undo = Changes::new();
+        for changes in self.journal.iter().rev().take(depth) {
+            undo.extend(changes.clone());
+        }
+        Some(StateView {
+            data: &self.data,
+            undo,
+        })
+    }
+
     pub fn get(&self, key: Vec<u8>) -> Option<&Vec<u8>> {
-        self.data.get(&key)
+        // Look through the open transactions first, innermost to outermost
+        self.overlays
+            .iter()
+            .rev()
+            .find_map(|overlay| overlay.get(&key))
+            .or_else(|| self.data.read(&key))
     }
 
     pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), StorageError> {
-        self.data.insert(key, value);
+        if !is_bookkeeping(&key) {
+            self.root = CachedRoot::default();
+        }
+        match self.overlays.last_mut() {
+            Some(overlay) => {
+                overlay.insert(key, value);
+                Ok(())
+            }
+            None => self.data.write(Changes::from([(key, Some(value))])),
+        }
+    }
+
+    // Open a new transaction, every write from now on is kept apart until it is committed
+    pub fn start_transaction(&mut self) {
+        self.overlays.push(HashMap::new());
+    }
+
+    // Merge the innermost transaction into its parent (or into the committed data)
+    pub fn commit_transaction(&mut self) -> Result<(), StorageError> {
+        let overlay = self.overlays.pop().ok_or_else(|| {
+            StorageError::OperationFailed("No open transaction to commit".to_string())
+        })?;
+
+        match self.overlays.last_mut() {
+            Some(parent) => {
+                parent.extend(overlay);
+                Ok(())
+            }
+            None => {
+                // Keep what the commit overwrites so it can be reverted later on
+                let undo = overlay
+                    .keys()
+                    .map(|key| (key.clone(), self.data.read(key).cloned()))
+                    .collect();
+                // Committing leaves reads as they were, unless the writes are lost on the way
+                if let Err(e) = self.data.commit(
+                    overlay.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+                    &undo,
+                    self.pruning_depth,
+                ) {
+                    self.root = CachedRoot::default();
+                    return Err(e);
+                }
+                self.journal.push_back(undo);
+                self.prune();
+                Ok(())
+            }
+        }
+    }
+
+    // Undo the latest outermost commit, once every open transaction is closed. Writes made outside
+    // of a transaction are not journaled, and are lost if they touched the same keys
+    pub fn revert_commit(&mut self) -> Result<(), StorageError> {
+        if !self.overlays.is_empty() {
+            return Err(StorageError::OperationFailed(
+                "Cannot revert a commit with open transactions".to_string(),
+            ));
+        }
+        let undo = self
+            .journal
+            .pop_back()
+            .ok_or_else(|| StorageError::OperationFailed("No commit to revert".to_string()))?;
+
+        self.root = CachedRoot::default();
+        if let Err(e) = self.data.revert(undo.clone()) {
+            self.journal.push_back(undo);
+            return Err(e);
+        }
         Ok(())
     }
 
-    pub fn new() -> Self {
-        State {
-            data: HashMap::new(),
+    // Discard every write made since the innermost transaction was opened
+    pub fn rollback_transaction(&mut self) -> Result<(), StorageError> {
+        self.overlays.pop().ok_or_else(|| {
+            StorageError::OperationFailed("No open transaction to roll back".to_string())
+        })?;
+        self.root = CachedRoot::default();
+        Ok(())
+    }
+
+    pub fn transaction_depth(&self) -> usize {
+        self.overlays.len()
+    }
+
+    // Every key/value pair as currently seen by reads, open transactions included
+    pub fn pairs(&self) -> BTreeMap<&Vec<u8>, &Vec<u8>> {
+        let mut pairs: BTreeMap<&Vec<u8>, &Vec<u8>> = self.data.entries().collect();
+        for overlay in &self.overlays {
+            pairs.extend(overlay.iter());
         }
+        pairs
+    }
+
+    // Merkle-Patricia trie root over the whole state, block bookkeeping aside. The trie is only
+    // rebuilt after writes it covers
+    pub fn root(&self) -> [u8; 32] {
+        *self
+            .root
+            .0
+            .get_or_init(|| Trie::from_pairs(rooted(self.pairs())).root())
+    }
+
+    // Read proof for `keys` against the current root
+    pub fn read_proof(&self, keys: &[Vec<u8>]) -> StorageProof {
+        Trie::from_pairs(rooted(self.pairs())).prove(keys)
     }
 
     // DEBUGGING
     pub fn print_state(&self) {
         println!("--- State Dump ---");
-        for (key, value) in &self.data {
+        for (key, value) in self.data.entries() {
             let key_str = hex::encode(key);
             let value_str = hex::encode(value);
 
@@ -42,6 +238,71 @@ impl State {
     }
 }
 
+/// Read-only committed state as it was some commits ago, see [`State::view`].
+pub struct StateView<'a> {
+    data: &'a (dyn Backend + 'a),
+    // Values the commits since then overwrote, `None` for keys they created
+    undo: Changes,
+}
+
+impl StateView<'_> {
+    pub fn read(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        match self.undo.get(key) {
+            Some(value) => value.as_ref(),
+            None => self.data.read(key),
+        }
+    }
+
+    /// Decode the value under `prefix` and `key`, as [`StoragePlugin::get`] does.
+    pub fn get<P, K, V>(&self, prefix: P, key: K) -> Result<V, StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: DeserializeOwned,
+    {
+        let full_key = <Plugin>::create_full_key(prefix, key)?;
+        let encoded = self
+            .read(&full_key)
+            .ok_or_else(|| StorageError::KeyNotFound(format!("{:?}", full_key)))?;
+        bincode::deserialize(encoded).map_err(|e| StorageError::DeserializationError(e.to_string()))
+    }
+
+    pub fn pairs(&self) -> BTreeMap<&Vec<u8>, &Vec<u8>> {
+        let mut pairs: BTreeMap<&Vec<u8>, &Vec<u8>> = self.data.entries().collect();
+        for (key, value) in &self.undo {
+            match value {
+                Some(value) => pairs.insert(key, value),
+                None => pairs.remove(key),
+            };
+        }
+        pairs
+    }
+
+    // Merkle-Patricia trie root over the whole state as it was then, block bookkeeping aside
+    pub fn root(&self) -> [u8; 32] {
+        Trie::from_pairs(rooted(self.pairs())).root()
+    }
+
+    // Read proof for `keys` against the root of the state as it was then
+    pub fn read_proof(&self, keys: &[Vec<u8>]) -> StorageProof {
+        Trie::from_pairs(rooted(self.pairs())).prove(keys)
+    }
+}
+
+// The pairs the state root covers, see `StoragePrefix::BOOKKEEPING`
+fn rooted<'a>(pairs: BTreeMap<&'a Vec<u8>, &'a Vec<u8>>) -> BTreeMap<&'a Vec<u8>, &'a Vec<u8>> {
+    pairs
+        .into_iter()
+        .filter(|(key, _)| !is_bookkeeping(key))
+        .collect()
+}
+
+fn is_bookkeeping(key: &[u8]) -> bool {
+    StoragePrefix::BOOKKEEPING
+        .iter()
+        .any(|prefix| key.starts_with(&bincode::serialize(prefix).expect("Prefixes always encode")))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -182,7 +443,7 @@ mod tests {
             #[test]
             fn test_multiple_inserts() {
                 let mut state = State::new();
-                let pairs = vec![
+                let pairs = [
                     (vec![1], vec![10]),
                     (vec![2], vec![20]),
                     (vec![3], vec![30]),
@@ -204,7 +465,7 @@ mod tests {
             #[test]
             fn test_multiple_inserts_with_duplicate_keys() {
                 let mut state = State::new();
-                let pairs = vec![
+                let pairs = [
                     (vec![1], vec![10]),
                     (vec![1], vec![20]), // Duplicate key
                     (vec![3], vec![30]),
@@ -222,7 +483,7 @@ mod tests {
             #[test]
             fn test_multiple_inserts_with_empty_key() {
                 let mut state = State::new();
-                let pairs = vec![
+                let pairs = [
                     (vec![1], vec![10]),
                     (vec![], vec![]), // Empty key
                     (vec![3], vec![30]),
@@ -238,4 +499,301 @@ mod tests {
             }
         }
     }
+
+    mod transactions {
+        mod success {
+            use crate::State;
+
+            #[test]
+            fn test_commit_transaction() {
+                let mut state = State::new();
+                state.start_transaction();
+                assert!(state.insert(vec![1], vec![10]).is_ok());
+
+                // Reads see uncommitted writes
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+                assert!(state.data.is_empty());
+
+                assert!(state.commit_transaction().is_ok());
+                assert_eq!(state.transaction_depth(), 0);
+                assert_eq!(state.data.get(&vec![1]), Some(&vec![10]));
+            }
+
+            #[test]
+            fn test_rollback_transaction() {
+                let mut state = State::new();
+                assert!(state.insert(vec![1], vec![10]).is_ok());
+                let before = state.clone();
+
+                state.start_transaction();
+                assert!(state.insert(vec![1], vec![20]).is_ok());
+                assert!(state.insert(vec![2], vec![30]).is_ok());
+                assert!(state.rollback_transaction().is_ok());
+
+                assert_eq!(state, before);
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+                assert!(state.get(vec![2]).is_none());
+            }
+
+            #[test]
+            fn test_revert_commits() {
+                let mut state = State::new();
+                assert!(state.insert(vec![1], vec![10]).is_ok());
+                let root = state.root();
+
+                state.start_transaction();
+                assert!(state.insert(vec![1], vec![20]).is_ok());
+                assert!(state.insert(vec![2], vec![30]).is_ok());
+                assert!(state.commit_transaction().is_ok());
+                let second_root = state.root();
+
+                state.start_transaction();
+                assert!(state.insert(vec![2], vec![40]).is_ok());
+                assert!(state.commit_transaction().is_ok());
+
+                // Latest first, a key the commit created is removed again
+                assert!(state.revert_commit().is_ok());
+                assert_eq!(state.root(), second_root);
+                assert!(state.revert_commit().is_ok());
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+                assert!(state.get(vec![2]).is_none());
+                assert_eq!(state.root(), root);
+            }
+        }
+
+        mod failure {
+            use crate::State;
+            use common::types::StorageError;
+
+            #[test]
+            fn test_revert_without_commit() {
+                let mut state = State::new();
+                assert!(state.insert(vec![1], vec![10]).is_ok());
+                assert!(matches!(
+                    state.revert_commit(),
+                    Err(StorageError::OperationFailed(_))
+                ));
+                assert_eq!(state.get(vec![1]), Some(&vec![10]));
+            }
+
+            #[test]
+            fn test_revert_with_open_transaction() {
+                let mut state = State::new();
+                state.start_transaction();
+                assert!(state.commit_transaction().is_ok());
+
+                state.start_transaction();
+                assert!(matches!(
+                    state.revert_commit(),
+                    Err(StorageError::OperationFailed(_))
+                ));
+            }
+
+            #[test]
+            fn test_commit_without_transaction() {
+                let mut state = State::new();
+                assert!(matches!(
+                    state.commit_transaction(),
+                    Err(StorageError::OperationFailed(_))
+                ));
+            }
+
+            #[test]
+            fn test_rollback_without_transaction() {
+                let mut state = State::new();
+                assert!(matches!(
+                    state.rollback_transaction(),
+                    Err(StorageError::OperationFailed(_))
+                ));
+            }
+        }
+    }
+
+    mod views {
+        use crate::State;
+
+        // Three commits on top of key 1 holding 10: 1 -> 20, then 2 -> 30, then 1 -> 40
+        fn committed_thrice() -> (State, Vec<[u8; 32]>) {
+            let mut state = State::new();
+            state.insert(vec![1], vec![10]).unwrap();
+            let mut roots = vec![state.root()];
+            for (key, value) in [(1, 20), (2, 30), (1, 40)] {
+                state.start_transaction();
+                state.insert(vec![key], vec![value]).unwrap();
+                state.commit_transaction().unwrap();
+                roots.push(state.root());
+            }
+            (state, roots)
+        }
+
+        mod success {
+            use super::*;
+            use crate::plugin::{Plugin, StoragePlugin};
+
+            #[test]
+            fn test_view_past_commits() {
+                let (state, roots) = committed_thrice();
+
+                let latest = state.view(0).unwrap();
+                assert_eq!(latest.read(&[1]), Some(&vec![40]));
+                assert_eq!(latest.root(), roots[3]);
+
+                let view = state.view(2).unwrap();
+                assert_eq!(view.read(&[1]), Some(&vec![20]));
+                assert_eq!(view.root(), roots[1]);
+
+                // Keys created since are not there yet
+                let view = state.view(3).unwrap();
+                assert_eq!(view.read(&[1]), Some(&vec![10]));
+                assert_eq!(view.read(&[2]), None);
+                assert_eq!(view.root(), roots[0]);
+
+                // Viewing leaves the state untouched
+                assert_eq!(state.root(), roots[3]);
+            }
+
+            #[test]
+            fn test_typed_reads() {
+                let mut state = State::new();
+                state.start_transaction();
+                let key = <Plugin>::create_full_key("prefix", 1u8).unwrap();
+                state
+                    .insert(key, bincode::serialize(&7u32).unwrap())
+                    .unwrap();
+                state.commit_transaction().unwrap();
+
+                let view = state.view(0).unwrap();
+                assert_eq!(view.get::<_, _, u32>("prefix", 1u8).unwrap(), 7);
+                assert!(state
+                    .view(1)
+                    .unwrap()
+                    .get::<_, _, u32>("prefix", 1u8)
+                    .is_err());
+            }
+
+            #[test]
+            fn test_pruning_keeps_latest_commits() {
+                let (mut state, roots) = committed_thrice();
+                state.set_pruning_depth(2);
+
+                assert_eq!(state.view(2).unwrap().root(), roots[1]);
+                assert!(state.revert_commit().is_ok());
+                assert!(state.revert_commit().is_ok());
+                assert_eq!(state.root(), roots[1]);
+            }
+        }
+
+        mod failure {
+            use super::*;
+            use common::types::StorageError;
+
+            #[test]
+            fn test_view_beyond_journal() {
+                let (state, _) = committed_thrice();
+                assert!(state.view(4).is_none());
+            }
+
+            #[test]
+            fn test_pruned_commits_are_gone() {
+                let (mut state, roots) = committed_thrice();
+                state.set_pruning_depth(1);
+
+                assert!(state.view(2).is_none());
+                assert!(state.revert_commit().is_ok());
+                assert!(matches!(
+                    state.revert_commit(),
+                    Err(StorageError::OperationFailed(_))
+                ));
+                assert_eq!(state.root(), roots[2]);
+            }
+        }
+    }
+
+    mod state_root {
+        mod success {
+            use crate::State;
+
+            #[test]
+            fn test_root_is_insertion_order_independent() {
+                let mut first = State::new();
+                let mut second = State::new();
+                first.insert(vec![1], vec![10]).unwrap();
+                first.insert(vec![2], vec![20]).unwrap();
+                second.insert(vec![2], vec![20]).unwrap();
+                second.insert(vec![1], vec![10]).unwrap();
+
+                assert_eq!(first.root(), second.root());
+            }
+
+            #[test]
+            fn test_root_includes_open_transactions() {
+                let mut state = State::new();
+                state.insert(vec![1], vec![10]).unwrap();
+                let committed_root = state.root();
+
+                state.start_transaction();
+                state.insert(vec![1], vec![20]).unwrap();
+                assert_ne!(state.root(), committed_root);
+
+                state.rollback_transaction().unwrap();
+                assert_eq!(state.root(), committed_root);
+            }
+
+            #[test]
+            fn test_root_leaves_out_block_bookkeeping() {
+                use crate::plugin::{Plugin, StoragePlugin};
+                use common::types::StoragePrefix;
+
+                let mut state = State::new();
+                state.insert(vec![1], vec![10]).unwrap();
+                let root = state.root();
+
+                for prefix in StoragePrefix::BOOKKEEPING {
+                    let key = <Plugin>::create_full_key(prefix, 1u64).unwrap();
+                    state.insert(key.clone(), vec![20]).unwrap();
+                    assert_eq!(state.get(key), Some(&vec![20]));
+                }
+                assert_eq!(state.root(), root);
+            }
+
+            #[test]
+            fn test_root_follows_reverted_commit() {
+                let mut state = State::new();
+                state.insert(vec![1], vec![10]).unwrap();
+                let committed_root = state.root();
+
+                state.start_transaction();
+                state.insert(vec![1], vec![20]).unwrap();
+                state.commit_transaction().unwrap();
+                assert_ne!(state.root(), committed_root);
+
+                state.revert_commit().unwrap();
+                assert_eq!(state.root(), committed_root);
+            }
+
+            #[test]
+            fn test_cached_root_is_left_out_of_comparisons() {
+                let mut first = State::new();
+                let mut second = State::new();
+                first.insert(vec![1], vec![10]).unwrap();
+                second.insert(vec![1], vec![10]).unwrap();
+                first.root();
+
+                assert_eq!(first, second);
+            }
+        }
+
+        mod failure {
+            use crate::State;
+
+            #[test]
+            fn test_root_changes_on_insert() {
+                let mut state = State::new();
+                let empty_root = state.root();
+
+                state.insert(vec![1], vec![10]).unwrap();
+                assert_ne!(state.root(), empty_root);
+            }
+        }
+    }
 }
@@ -0,0 +1,372 @@
+use common::trie::StorageProof;
+use common::types::StorageError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::Cell;
+use std::fmt::Debug;
+
+use crate::plugin::{StoragePlugin, Transactional};
+use crate::{State, StateView};
+
+/// What a [`MockPlugin`] does instead of the operation a fault is set for.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Fail with the given error, a failing write is not applied.
+    Fail(StorageError),
+    /// Cut the last byte off the encoded value, as written or as read, so that it no longer decodes.
+    Corrupt,
+    /// Panic, as storage going away under the node would.
+    Panic,
+}
+
+/// Instrumented [`StoragePlugin`] for tests, over a plain in-memory [`State`].
+///
+/// Every read and write going through it is counted. The ones under a prefix set with
+/// [`MockPlugin::fail_writes`] or [`MockPlugin::fail_reads`] fail with the given error instead,
+/// and a [`Fault`] can be set for any single upcoming one with [`MockPlugin::fault_write`] or
+/// [`MockPlugin::fault_read`].
+#[derive(Default)]
+pub struct MockPlugin {
+    state: State,
+    // Encoded prefixes along with the error their writes and reads fail with
+    failing_writes: Vec<(Vec<u8>, StorageError)>,
+    failing_reads: Vec<(Vec<u8>, StorageError)>,
+    // Faults by index of the write or read they are set for
+    write_faults: Vec<(usize, Fault)>,
+    read_faults: Vec<(usize, Fault)>,
+    writes: usize,
+    reads: Cell<usize>,
+}
+
+impl MockPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail every write under `prefix` with `error`, from now on.
+    pub fn fail_writes<P: Serialize>(&mut self, prefix: P, error: StorageError) {
+        self.failing_writes.push((Self::encode(prefix), error));
+    }
+
+    /// Fail every read under `prefix` with `error`, from now on.
+    pub fn fail_reads<P: Serialize>(&mut self, prefix: P, error: StorageError) {
+        self.failing_reads.push((Self::encode(prefix), error));
+    }
+
+    /// Set `fault` for the write coming after `skip` more writes, `0` being the very next one.
+    /// The fault fires once.
+    pub fn fault_write(&mut self, skip: usize, fault: Fault) {
+        self.write_faults.push((self.writes + skip, fault));
+    }
+
+    /// Set `fault` for the read coming after `skip` more reads, `0` being the very next one.
+    /// The fault fires once.
+    pub fn fault_read(&mut self, skip: usize, fault: Fault) {
+        self.read_faults.push((self.reads.get() + skip, fault));
+    }
+
+    /// Let every operation through again.
+    pub fn clear_failures(&mut self) {
+        self.failing_writes.clear();
+        self.failing_reads.clear();
+        self.write_faults.clear();
+        self.read_faults.clear();
+    }
+
+    /// Writes attempted so far, failed ones included.
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// Reads attempted so far, failed ones included.
+    pub fn reads(&self) -> usize {
+        self.reads.get()
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    fn encode<P: Serialize>(prefix: P) -> Vec<u8> {
+        bincode::serialize(&prefix).expect("Prefixes are always serializable")
+    }
+
+    fn injected<P: Serialize>(
+        failures: &[(Vec<u8>, StorageError)],
+        prefix: &P,
+    ) -> Result<(), StorageError> {
+        let encoded = Self::encode(prefix);
+        match failures.iter().find(|(failing, _)| *failing == encoded) {
+            Some((_, error)) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    // Fault set for the operation at `index`, a panic fires right away
+    fn fault(faults: &[(usize, Fault)], index: usize) -> Option<&Fault> {
+        let fault = faults
+            .iter()
+            .find(|(at, _)| *at == index)
+            .map(|(_, fault)| fault);
+        if let Some(Fault::Panic) = fault {
+            panic!("Injected storage panic at operation {}", index);
+        }
+        fault
+    }
+
+    fn corrupt(mut encoded: Vec<u8>) -> Vec<u8> {
+        encoded.pop();
+        encoded
+    }
+
+    fn decode<V: DeserializeOwned>(encoded: &[u8]) -> Result<V, StorageError> {
+        bincode::deserialize(encoded).map_err(|e| StorageError::DeserializationError(e.to_string()))
+    }
+}
+
+impl Transactional for MockPlugin {
+    fn start_transaction(&mut self) {
+        self.state.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) -> Result<(), StorageError> {
+        self.state.commit_transaction()
+    }
+
+    fn rollback_transaction(&mut self) -> Result<(), StorageError> {
+        self.state.rollback_transaction()
+    }
+}
+
+impl StoragePlugin for MockPlugin {
+    fn set<P, K, V>(&mut self, prefix: P, key: K, value: &V) -> Result<(), StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug,
+    {
+        let index = self.writes;
+        self.writes += 1;
+        Self::injected(&self.failing_writes, &prefix)?;
+
+        let full_key = Self::create_full_key(prefix, key)?;
+        let encoded = bincode::serialize(value)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let encoded = match Self::fault(&self.write_faults, index) {
+            Some(Fault::Fail(error)) => return Err(error.clone()),
+            Some(Fault::Corrupt) => Self::corrupt(encoded),
+            _ => encoded,
+        };
+        self.state.insert(full_key, encoded)
+    }
+
+    fn get<P, K, V>(&self, prefix: P, key: K) -> Result<V, StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug,
+    {
+        let index = self.reads.get();
+        self.reads.set(index + 1);
+        Self::injected(&self.failing_reads, &prefix)?;
+
+        let full_key = Self::create_full_key(prefix, key)?;
+        let fault = Self::fault(&self.read_faults, index);
+        if let Some(Fault::Fail(error)) = fault {
+            return Err(error.clone());
+        }
+        let encoded = self
+            .state
+            .get(full_key.clone())
+            .ok_or_else(|| StorageError::KeyNotFound(format!("{:?}", full_key)))?;
+        match fault {
+            Some(Fault::Corrupt) => Self::decode(&Self::corrupt(encoded.clone())),
+            _ => Self::decode(encoded),
+        }
+    }
+
+    fn values<P, V>(&self, prefix: P) -> Result<Vec<V>, StorageError>
+    where
+        P: Serialize,
+        V: DeserializeOwned,
+    {
+        let index = self.reads.get();
+        self.reads.set(index + 1);
+        Self::injected(&self.failing_reads, &prefix)?;
+
+        let encoded_prefix = Self::encode(&prefix);
+        let fault = Self::fault(&self.read_faults, index);
+        if let Some(Fault::Fail(error)) = fault {
+            return Err(error.clone());
+        }
+        self.state
+            .pairs()
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(&encoded_prefix))
+            .map(|(_, encoded)| match fault {
+                Some(Fault::Corrupt) => Self::decode(&Self::corrupt(encoded.clone())),
+                _ => Self::decode(encoded),
+            })
+            .collect()
+    }
+
+    fn state_root(&self) -> [u8; 32] {
+        self.state.root()
+    }
+
+    fn read_proof(&self, keys: &[Vec<u8>]) -> StorageProof {
+        self.state.read_proof(keys)
+    }
+
+    fn revert_last_commit(&mut self) -> Result<(), StorageError> {
+        self.state.revert_commit()
+    }
+
+    fn view(&self, depth: usize) -> Result<StateView<'_>, StorageError> {
+        self.state.view(depth).ok_or_else(|| {
+            StorageError::DataNotFound(format!("State {} commits back is pruned", depth))
+        })
+    }
+
+    fn set_pruning_depth(&mut self, depth: usize) {
+        self.state.set_pruning_depth(depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod injected_failures {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_operations_are_counted() {
+                let mut plugin = MockPlugin::new();
+                plugin.set("prefix", 1u8, &10u32).unwrap();
+                let value: u32 = plugin.get("prefix", 1u8).unwrap();
+
+                assert_eq!(value, 10);
+                assert_eq!(plugin.writes(), 1);
+                assert_eq!(plugin.reads(), 1);
+            }
+
+            #[test]
+            fn test_other_prefixes_go_through() {
+                let mut plugin = MockPlugin::new();
+                plugin.fail_writes("failing", StorageError::DataInsertionError("disk".into()));
+
+                assert!(plugin.set("prefix", 1u8, &10u32).is_ok());
+                assert_eq!(plugin.get::<_, _, u32>("prefix", 1u8).unwrap(), 10);
+            }
+
+            #[test]
+            fn test_clear_failures() {
+                let mut plugin = MockPlugin::new();
+                plugin.fail_writes("prefix", StorageError::DataInsertionError("disk".into()));
+                plugin.clear_failures();
+
+                assert!(plugin.set("prefix", 1u8, &10u32).is_ok());
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_failing_write_is_not_applied() {
+                let mut plugin = MockPlugin::new();
+                let error = StorageError::DataInsertionError("disk".into());
+                plugin.fail_writes("prefix", error.clone());
+
+                assert_eq!(plugin.set("prefix", 1u8, &10u32), Err(error));
+                assert_eq!(plugin.state_root(), MockPlugin::new().state_root());
+            }
+
+            #[test]
+            fn test_failing_read() {
+                let mut plugin = MockPlugin::new();
+                plugin.set("prefix", 1u8, &10u32).unwrap();
+                let error = StorageError::DeserializationError("corrupt".into());
+                plugin.fail_reads("prefix", error.clone());
+
+                assert_eq!(plugin.get::<_, _, u32>("prefix", 1u8), Err(error.clone()));
+                assert_eq!(plugin.values::<_, u32>("prefix"), Err(error));
+            }
+        }
+    }
+
+    mod faults {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_fault_fires_once() {
+                let mut plugin = MockPlugin::new();
+                plugin.set("prefix", 1u8, &10u32).unwrap();
+                plugin.fault_write(
+                    1,
+                    Fault::Fail(StorageError::DataInsertionError("disk".into())),
+                );
+
+                // Counted from when the fault is set
+                assert!(plugin.set("prefix", 2u8, &20u32).is_ok());
+                assert!(plugin.set("prefix", 3u8, &30u32).is_err());
+                assert!(plugin.set("prefix", 3u8, &30u32).is_ok());
+                assert_eq!(plugin.writes(), 4);
+            }
+
+            #[test]
+            fn test_corrupt_read_leaves_value_intact() {
+                let mut plugin = MockPlugin::new();
+                plugin.set("prefix", 1u8, &10u32).unwrap();
+                plugin.fault_read(0, Fault::Corrupt);
+
+                assert!(plugin.get::<_, _, u32>("prefix", 1u8).is_err());
+                assert_eq!(plugin.get::<_, _, u32>("prefix", 1u8).unwrap(), 10);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_failing_write_is_not_applied() {
+                let mut plugin = MockPlugin::new();
+                let error = StorageError::DataInsertionError("disk".into());
+                plugin.fault_write(0, Fault::Fail(error.clone()));
+
+                assert_eq!(plugin.set("prefix", 1u8, &10u32), Err(error));
+                assert!(matches!(
+                    plugin.get::<_, _, u32>("prefix", 1u8),
+                    Err(StorageError::KeyNotFound(_))
+                ));
+            }
+
+            #[test]
+            fn test_corrupt_write_no_longer_decodes() {
+                let mut plugin = MockPlugin::new();
+                plugin.fault_write(0, Fault::Corrupt);
+                plugin.set("prefix", 1u8, &10u32).unwrap();
+
+                assert!(matches!(
+                    plugin.get::<_, _, u32>("prefix", 1u8),
+                    Err(StorageError::DeserializationError(_))
+                ));
+                assert!(matches!(
+                    plugin.values::<_, u32>("prefix"),
+                    Err(StorageError::DeserializationError(_))
+                ));
+            }
+
+            #[test]
+            #[should_panic(expected = "Injected storage panic")]
+            fn test_panicking_read() {
+                let mut plugin = MockPlugin::new();
+                plugin.fault_read(0, Fault::Panic);
+                let _: Result<u32, StorageError> = plugin.get("prefix", 1u8);
+            }
+        }
+    }
+}
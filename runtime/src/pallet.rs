@@ -0,0 +1,194 @@
+use common::event::Event;
+use common::types::{Config, Origin, StorageError, StoragePrefix, TransactionError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+use crate::plugin::{Plugin, StoragePlugin};
+
+/// Storage and event sink handed to pallets while a block is being applied.
+pub struct Context<'a, T: Config, S: StoragePlugin = Plugin> {
+    pub plugin: &'a mut S,
+    events: &'a mut Vec<Event<T>>,
+}
+
+impl<'a, T: Config, S: StoragePlugin> Context<'a, T, S> {
+    pub fn new(plugin: &'a mut S, events: &'a mut Vec<Event<T>>) -> Self {
+        Context { plugin, events }
+    }
+
+    pub fn deposit_event(&mut self, event: impl Into<Event<T>>) {
+        self.events.push(event.into());
+    }
+
+    /// Events deposited so far.
+    pub fn events(&self) -> &[Event<T>] {
+        self.events
+    }
+
+    /// Run `f` in a nested storage transaction, see [`crate::plugin::Transactional::with_transaction`].
+    /// When `f` fails, the events it deposited are dropped along with its writes.
+    pub fn with_transaction<R, E, F>(&mut self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Context<'_, T, S>) -> Result<R, E>,
+        E: From<StorageError>,
+    {
+        let events_before = self.events.len();
+        let events = &mut *self.events;
+        let result = self
+            .plugin
+            .with_transaction(|plugin| f(&mut Context::new(plugin, events)));
+        if result.is_err() {
+            self.events.truncate(events_before);
+        }
+        result
+    }
+}
+
+/// A self-contained module of the runtime, owning its calls, events and storage.
+///
+/// Pallets are put together by [`crate::composer::Runtime`], which routes every variant of the
+/// outer call to the pallet owning it.
+pub trait Pallet<T: Config>
+where
+    T: Serialize + DeserializeOwned + Debug,
+{
+    /// Storage prefix of the items owned by the pallet.
+    const PREFIX: StoragePrefix;
+
+    type Call;
+    type Event: Into<Event<T>>;
+
+    /// Dispatch a call of the pallet on behalf of `origin`. The STF rolls back every write and
+    /// event of the call when it fails.
+    fn dispatch<S: StoragePlugin>(
+        ctx: &mut Context<T, S>,
+        origin: Origin<T::Hash>,
+        call: &Self::Call,
+    ) -> Result<(), TransactionError<T>>;
+
+    /// Run at the start of every block, before its extrinsics. Returns the weight it consumed,
+    /// which is counted against the block's weight limit.
+    fn on_initialize<S: StoragePlugin>(
+        _ctx: &mut Context<T, S>,
+        _block_height: &T::HeightType,
+    ) -> Result<T::WeightType, StorageError> {
+        Ok(T::WeightType::from(0))
+    }
+
+    /// Run at the end of every block, after its extrinsics. Returns the weight it consumed,
+    /// which is counted against the block's weight limit.
+    fn on_finalize<S: StoragePlugin>(
+        _ctx: &mut Context<T, S>,
+        _block_height: &T::HeightType,
+    ) -> Result<T::WeightType, StorageError> {
+        Ok(T::WeightType::from(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::event::BalancesEvent;
+    use common::mock::FeelessMockConfig as MockConfig;
+    use common::types::Height;
+
+    // Pallet relying on the default hooks
+    struct Noop;
+    impl Pallet<MockConfig> for Noop {
+        const PREFIX: StoragePrefix = StoragePrefix::Account;
+
+        type Call = ();
+        type Event = BalancesEvent<MockConfig>;
+
+        fn dispatch<S: StoragePlugin>(
+            ctx: &mut Context<MockConfig, S>,
+            _origin: Origin<[u8; 32]>,
+            _call: &(),
+        ) -> Result<(), TransactionError<MockConfig>> {
+            ctx.deposit_event(BalancesEvent::Minted {
+                to: [0; 32],
+                amount: 0,
+            });
+            Ok(())
+        }
+    }
+
+    mod hooks {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_default_hooks_leave_state_untouched() {
+                let mut plugin = Plugin::new();
+                let mut events = Vec::new();
+                let mut ctx = Context::new(&mut plugin, &mut events);
+
+                assert_eq!(Noop::on_initialize(&mut ctx, &Height::from(1)).unwrap(), 0);
+                assert_eq!(Noop::on_finalize(&mut ctx, &Height::from(1)).unwrap(), 0);
+                assert!(events.is_empty());
+                assert_eq!(plugin.get_state(), Plugin::new().get_state());
+            }
+
+            #[test]
+            fn test_pallet_events_are_wrapped() {
+                let mut plugin = Plugin::new();
+                let mut events = Vec::new();
+                let mut ctx = Context::new(&mut plugin, &mut events);
+
+                assert!(Noop::dispatch(&mut ctx, Origin::None, &()).is_ok());
+                assert!(matches!(
+                    events.as_slice(),
+                    [Event::Balances(BalancesEvent::Minted { amount: 0, .. })]
+                ));
+            }
+        }
+    }
+
+    mod with_transaction {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_writes_and_events_are_kept() {
+                let mut plugin = Plugin::new();
+                let mut events = Vec::new();
+                let mut ctx = Context::<MockConfig>::new(&mut plugin, &mut events);
+
+                let result: Result<(), TransactionError<MockConfig>> =
+                    ctx.with_transaction(|ctx| {
+                        ctx.plugin.set("prefix", "key", &1u32)?;
+                        Noop::dispatch(ctx, Origin::None, &())
+                    });
+                assert!(result.is_ok());
+                assert_eq!(ctx.events().len(), 1);
+                assert_eq!(plugin.get::<_, _, u32>("prefix", "key").unwrap(), 1);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_writes_and_events_are_dropped() {
+                let mut plugin = Plugin::new();
+                let mut events = Vec::new();
+                let mut ctx = Context::<MockConfig>::new(&mut plugin, &mut events);
+                Noop::dispatch(&mut ctx, Origin::None, &()).unwrap();
+
+                let result: Result<(), TransactionError<MockConfig>> =
+                    ctx.with_transaction(|ctx| {
+                        ctx.plugin.set("prefix", "key", &1u32)?;
+                        Noop::dispatch(ctx, Origin::None, &())?;
+                        Err(TransactionError::BadOrigin)
+                    });
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+
+                // Only the event deposited before the transaction is left
+                assert_eq!(ctx.events().len(), 1);
+                assert!(plugin.get::<_, _, u32>("prefix", "key").is_err());
+                assert_eq!(plugin.get_state().transaction_depth(), 0);
+            }
+        }
+    }
+}
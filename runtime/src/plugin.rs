@@ -1,17 +1,108 @@
-use crate::State;
+use crate::backend::{Backend, MemoryBackend};
+use crate::{State, StateView};
+use common::trie::StorageProof;
 use common::types::StorageError;
 use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::Debug;
 
-pub trait StoragePlugin<P, K, V> {
-    fn set(&mut self, prefix: P, key: K, value: &V) -> Result<(), StorageError>;
-    fn get(&self, prefix: P, key: K) -> Result<V, StorageError>;
-    fn create_full_key(prefix: P, key: K) -> Result<Vec<u8>, StorageError>;
+/// Nested storage transactions, every layer can be committed into its parent or rolled back on its own.
+pub trait Transactional {
+    fn start_transaction(&mut self);
+    fn commit_transaction(&mut self) -> Result<(), StorageError>;
+    fn rollback_transaction(&mut self) -> Result<(), StorageError>;
+
+    /// Run `f` inside a new transaction layer. The layer is committed if `f` returns `Ok`
+    /// and rolled back if it returns `Err`. Calls can be nested to any depth.
+    fn with_transaction<R, E, F>(&mut self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Self) -> Result<R, E>,
+        E: From<StorageError>,
+    {
+        self.start_transaction();
+        match f(self) {
+            Ok(result) => {
+                self.commit_transaction()?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.rollback_transaction()?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Typed storage the STF runs on, values are kept under a prefix and a key.
+///
+/// The STF is agnostic over the implementation, [`Plugin`] being the one used by the node.
+pub trait StoragePlugin: Transactional {
+    fn set<P, K, V>(&mut self, prefix: P, key: K, value: &V) -> Result<(), StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug;
+
+    fn get<P, K, V>(&self, prefix: P, key: K) -> Result<V, StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug;
+
+    /// Decode every value stored under `prefix`, in key order.
+    fn values<P, V>(&self, prefix: P) -> Result<Vec<V>, StorageError>
+    where
+        P: Serialize,
+        V: DeserializeOwned;
+
+    /// Merkle root over everything stored but the block bookkeeping, open transactions included.
+    ///
+    /// See [`common::types::StoragePrefix::BOOKKEEPING`].
+    fn state_root(&self) -> [u8; 32];
+
+    /// Read proof for the given full keys against [`StoragePlugin::state_root`].
+    fn read_proof(&self, keys: &[Vec<u8>]) -> StorageProof;
+
+    /// Undo the latest transaction committed into the backend, leaving the storage as it was
+    /// before the transaction was opened. Commits are reverted latest first.
+    fn revert_last_commit(&mut self) -> Result<(), StorageError>;
+
+    /// Read-only view of the storage as it was before the latest `depth` commits.
+    fn view(&self, depth: usize) -> Result<StateView<'_>, StorageError>;
+
+    /// Number of latest commits that can still be reverted or viewed, older ones are pruned.
+    fn set_pruning_depth(&mut self, depth: usize);
+
+    /// Key a value is stored under, the encoded prefix followed by the encoded key.
+    fn create_full_key<P, K>(prefix: P, key: K) -> Result<Vec<u8>, StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+    {
+        let encoded_prefix = bincode::serialize(&prefix).map_err(|e| {
+            eprintln!("Failed to serialize prefix: {}", e);
+            StorageError::SerializationError("Failed to serialize prefix".to_string())
+        })?;
+
+        let encoded_key = bincode::serialize(&key).map_err(|e| {
+            eprintln!("Failed to serialize key: {}", e);
+            StorageError::SerializationError("Failed to serialize key".to_string())
+        })?;
+
+        let full_key = encoded_prefix.into_iter().chain(encoded_key).collect();
+
+        Ok(full_key)
+    }
 }
 
 #[derive(Serialize)]
-pub struct Plugin {
-    state: State,
+pub struct Plugin<B: Backend = MemoryBackend> {
+    state: State<B>,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Plugin {
@@ -20,21 +111,44 @@ impl Plugin {
             state: State::new(),
         }
     }
+}
+
+impl<B: Backend> Plugin<B> {
+    // Plugin over the state already held by `backend`, e.g. a `FileBackend` left by an earlier run
+    pub fn with_backend(backend: B) -> Self {
+        Plugin {
+            state: State::with_backend(backend),
+        }
+    }
 
     // DEBUGGING
-    pub fn get_state(&self) -> &State {
+    pub fn get_state(&self) -> &State<B> {
         &self.state
     }
 }
 
-impl<P, K, V> StoragePlugin<P, K, V> for Plugin
-where
-    P: Serialize + Debug,
-    K: Serialize + Debug,
-    V: Serialize + DeserializeOwned + Debug,
-{
-    fn set(&mut self, prefix: P, key: K, value: &V) -> Result<(), StorageError> {
-        let full_key = <Self as StoragePlugin<P, K, V>>::create_full_key(prefix, key).unwrap();
+impl<B: Backend> Transactional for Plugin<B> {
+    fn start_transaction(&mut self) {
+        self.state.start_transaction();
+    }
+
+    fn commit_transaction(&mut self) -> Result<(), StorageError> {
+        self.state.commit_transaction()
+    }
+
+    fn rollback_transaction(&mut self) -> Result<(), StorageError> {
+        self.state.rollback_transaction()
+    }
+}
+
+impl<B: Backend> StoragePlugin for Plugin<B> {
+    fn set<P, K, V>(&mut self, prefix: P, key: K, value: &V) -> Result<(), StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug,
+    {
+        let full_key = Self::create_full_key(prefix, key).unwrap();
 
         let encoded_value = bincode::serialize(value).unwrap();
         self.state
@@ -44,8 +158,13 @@ where
         Ok(())
     }
 
-    fn get(&self, prefix: P, key: K) -> Result<V, StorageError> {
-        let full_key = <Self as StoragePlugin<P, K, V>>::create_full_key(prefix, key)
+    fn get<P, K, V>(&self, prefix: P, key: K) -> Result<V, StorageError>
+    where
+        P: Serialize + Debug,
+        K: Serialize + Debug,
+        V: Serialize + DeserializeOwned + Debug,
+    {
+        let full_key = Self::create_full_key(prefix, key)
             .map_err(|e| StorageError::KeyCreationError(format!("{:?}", e)))?;
 
         let encoded_data = self
@@ -59,23 +178,45 @@ where
         })
     }
 
-    fn create_full_key(prefix: P, key: K) -> Result<Vec<u8>, StorageError> {
-        let encoded_prefix = bincode::serialize(&prefix).map_err(|e| {
-            eprintln!("Failed to serialize prefix: {}", e);
-            StorageError::SerializationError("Failed to serialize prefix".to_string())
-        })?;
+    fn values<P, V>(&self, prefix: P) -> Result<Vec<V>, StorageError>
+    where
+        P: Serialize,
+        V: DeserializeOwned,
+    {
+        let encoded_prefix = bincode::serialize(&prefix)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
-        let encoded_key = bincode::serialize(&key).map_err(|e| {
-            eprintln!("Failed to serialize key: {}", e);
-            StorageError::SerializationError("Failed to serialize key".to_string())
-        })?;
-
-        let full_key = encoded_prefix
+        self.state
+            .pairs()
             .into_iter()
-            .chain(encoded_key.into_iter())
-            .collect();
+            .filter(|(key, _)| key.starts_with(&encoded_prefix))
+            .map(|(_, value)| {
+                bincode::deserialize(value)
+                    .map_err(|e| StorageError::DeserializationError(e.to_string()))
+            })
+            .collect()
+    }
 
-        Ok(full_key)
+    fn state_root(&self) -> [u8; 32] {
+        self.state.root()
+    }
+
+    fn read_proof(&self, keys: &[Vec<u8>]) -> StorageProof {
+        self.state.read_proof(keys)
+    }
+
+    fn revert_last_commit(&mut self) -> Result<(), StorageError> {
+        self.state.revert_commit()
+    }
+
+    fn view(&self, depth: usize) -> Result<StateView<'_>, StorageError> {
+        self.state.view(depth).ok_or_else(|| {
+            StorageError::DataNotFound(format!("State {} commits back is pruned", depth))
+        })
+    }
+
+    fn set_pruning_depth(&mut self, depth: usize) {
+        self.state.set_pruning_depth(depth);
     }
 }
 
@@ -142,8 +283,7 @@ mod tests {
                 let prefix = "test_prefix";
                 let key = "test_key";
 
-                let full_key_result =
-                    <Plugin as StoragePlugin<_, _, u32>>::create_full_key(prefix, key);
+                let full_key_result = <Plugin>::create_full_key(prefix, key);
                 assert!(full_key_result.is_ok());
 
                 let full_key = full_key_result.unwrap();
@@ -160,8 +300,8 @@ mod tests {
             fn test_set_and_get_multiple_values() {
                 let mut plugin = Plugin::new();
                 let prefix = "test_prefix";
-                let keys = vec!["key1", "key2", "key3"];
-                let values = vec![1u32, 2u32, 3u32];
+                let keys = ["key1", "key2", "key3"];
+                let values = [1u32, 2u32, 3u32];
 
                 // Set multiple values
                 for (key, value) in keys.iter().zip(values.iter()) {
@@ -225,4 +365,128 @@ mod tests {
             }
         }
     }
+
+    mod values {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_values_under_prefix() {
+                let mut plugin = Plugin::new();
+                plugin.set("prefix", "key1", &1u32).unwrap();
+                plugin.set("prefix", "key2", &2u32).unwrap();
+                plugin.set("other", "key1", &3u32).unwrap();
+
+                // Pending writes are seen as well
+                plugin.start_transaction();
+                plugin.set("prefix", "key3", &4u32).unwrap();
+
+                let values: Vec<u32> = plugin.values("prefix").unwrap();
+                assert_eq!(values, vec![1, 2, 4]);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_values_of_other_type() {
+                let mut plugin = Plugin::new();
+                plugin.set("prefix", "key", &1u8).unwrap();
+
+                let values: Result<Vec<u64>, StorageError> = plugin.values("prefix");
+                assert!(matches!(values, Err(StorageError::DeserializationError(_))));
+            }
+        }
+    }
+
+    mod with_transaction {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_with_transaction_commits_on_ok() {
+                let mut plugin = Plugin::new();
+
+                let result: Result<(), StorageError> =
+                    plugin.with_transaction(|plugin| plugin.set("prefix", "key", &42u32));
+                assert!(result.is_ok());
+
+                let get_result: Result<u32, StorageError> = plugin.get("prefix", "key");
+                assert_eq!(get_result.unwrap(), 42);
+                assert_eq!(plugin.get_state().transaction_depth(), 0);
+            }
+
+            #[test]
+            fn test_nested_rollback_keeps_outer_writes() {
+                let mut plugin = Plugin::new();
+
+                let result: Result<(), StorageError> = plugin.with_transaction(|plugin| {
+                    plugin.set("prefix", "outer", &1u32)?;
+
+                    // The inner layer fails on its own, the outer one carries on
+                    let inner: Result<(), StorageError> = plugin.with_transaction(|plugin| {
+                        plugin.set("prefix", "inner", &2u32)?;
+                        plugin.set("prefix", "outer", &3u32)?;
+                        Err(StorageError::OperationFailed("inner".to_string()))
+                    });
+                    assert!(inner.is_err());
+
+                    let outer: u32 = plugin.get("prefix", "outer")?;
+                    assert_eq!(outer, 1);
+                    Ok(())
+                });
+                assert!(result.is_ok());
+
+                let outer: Result<u32, StorageError> = plugin.get("prefix", "outer");
+                let inner: Result<u32, StorageError> = plugin.get("prefix", "inner");
+                assert_eq!(outer.unwrap(), 1);
+                assert!(matches!(inner, Err(StorageError::KeyNotFound(_))));
+            }
+
+            #[test]
+            fn test_deep_nesting() {
+                let mut plugin = Plugin::new();
+
+                fn nest(plugin: &mut Plugin, depth: u32) -> Result<(), StorageError> {
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    plugin.with_transaction(|plugin| {
+                        plugin.set("depth", depth, &depth)?;
+                        nest(plugin, depth - 1)
+                    })
+                }
+
+                assert!(nest(&mut plugin, 64).is_ok());
+                assert_eq!(plugin.get_state().transaction_depth(), 0);
+
+                let deepest: Result<u32, StorageError> = plugin.get("depth", 1u32);
+                assert_eq!(deepest.unwrap(), 1);
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_with_transaction_rolls_back_on_err() {
+                let mut plugin = Plugin::new();
+                plugin.set("prefix", "key", &1u32).unwrap();
+
+                let result: Result<(), StorageError> = plugin.with_transaction(|plugin| {
+                    plugin.set("prefix", "key", &2u32)?;
+                    plugin.set("prefix", "other", &3u32)?;
+                    Err(StorageError::OperationFailed("abort".to_string()))
+                });
+                assert!(matches!(result, Err(StorageError::OperationFailed(_))));
+
+                let key: Result<u32, StorageError> = plugin.get("prefix", "key");
+                let other: Result<u32, StorageError> = plugin.get("prefix", "other");
+                assert_eq!(key.unwrap(), 1);
+                assert!(other.is_err());
+                assert_eq!(plugin.get_state().transaction_depth(), 0);
+            }
+        }
+    }
 }
@@ -0,0 +1,160 @@
+use common::account::Account;
+use common::trie::{self, StorageProof};
+use common::types::{Config, ProofError, StoragePrefix};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+
+use crate::plugin::{Plugin, StoragePlugin};
+
+/// Decode the value stored under `prefix` and `key` from a read proof, trusting nothing but `state_root`.
+///
+/// Returns `Ok(None)` if the proof shows there is no such value.
+pub fn verify_value_proof<P, K, V>(
+    state_root: &[u8; 32],
+    prefix: P,
+    key: K,
+    proof: &StorageProof,
+) -> Result<Option<V>, ProofError>
+where
+    P: Serialize + Debug,
+    K: Serialize + Debug,
+    V: Serialize + DeserializeOwned + Debug,
+{
+    let full_key = <Plugin>::create_full_key(prefix, key)
+        .map_err(|e| ProofError::ValueDecodeError(e.to_string()))?;
+
+    trie::verify_proof(state_root, proof, &full_key)?
+        .map(|encoded| {
+            bincode::deserialize(&encoded).map_err(|e| ProofError::ValueDecodeError(e.to_string()))
+        })
+        .transpose()
+}
+
+/// Decode an account from a read proof built by [`crate::stf::Stf::account_proof`].
+pub fn verify_account_proof<T>(
+    state_root: &T::Hash,
+    account_id: T::Hash,
+    proof: &StorageProof,
+) -> Result<Option<Account<T>>, ProofError>
+where
+    T: Config + Serialize + DeserializeOwned + Debug,
+{
+    let root: [u8; 32] = state_root
+        .as_ref()
+        .try_into()
+        .map_err(|_| ProofError::InvalidRoot(hex::encode(state_root)))?;
+    verify_value_proof(&root, StoragePrefix::Account, account_id, proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stf::{GenesisConfig, SimpleStf, Stf};
+    use common::block::{Block, BlockTrait, Header};
+    use common::extrinsics::SignedTransaction;
+    use common::mock::MockConfig;
+    use common::types::{Height, StorageError};
+
+    // An STF holding ALICE ([0; 32]) with 100 and DAVE ([1; 32]) with 50 from genesis, and its
+    // first block, authored by [2; 32]
+    fn stf_with_accounts() -> (SimpleStf<MockConfig>, Block<MockConfig>) {
+        let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+        stf.apply_genesis(&GenesisConfig {
+            accounts: vec![([0; 32], 100), ([1; 32], 50)],
+            sudo_key: None,
+        })
+        .unwrap();
+        let mut block = Block {
+            header: Header {
+                block_height: Height::from(1),
+                parent_hash: [0; 32],
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author: [2; 32],
+            },
+            extrinsics: Vec::new(),
+        };
+        block
+            .add_extrinsic(SignedTransaction::coinbase([2; 32]))
+            .unwrap();
+        stf.execute_authored_block(&mut block).unwrap();
+        (stf, block)
+    }
+
+    mod verify_account_proof {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_verify_account_proof() {
+                let (stf, block) = stf_with_accounts();
+                let proof = stf
+                    .account_proof(block.hash(), &[[0; 32], [1; 32]])
+                    .unwrap();
+                let root = block.header.state_root;
+
+                let alice = verify_account_proof::<MockConfig>(&root, [0; 32], &proof).unwrap();
+                let dave = verify_account_proof::<MockConfig>(&root, [1; 32], &proof).unwrap();
+                assert_eq!(alice.unwrap().balance, 100);
+                assert_eq!(dave.unwrap().balance, 50);
+            }
+
+            #[test]
+            fn test_verify_absent_account_proof() {
+                let (stf, block) = stf_with_accounts();
+                let proof = stf.account_proof(block.hash(), &[[9; 32]]).unwrap();
+
+                let account =
+                    verify_account_proof::<MockConfig>(&block.header.state_root, [9; 32], &proof);
+                assert!(matches!(account, Ok(None)));
+            }
+        }
+
+        mod failure {
+            use super::super::*;
+
+            #[test]
+            fn test_account_proof_of_unknown_block() {
+                let (stf, _) = stf_with_accounts();
+                assert!(matches!(
+                    stf.account_proof([7; 32], &[[0; 32]]),
+                    Err(StorageError::KeyNotFound(_))
+                ));
+            }
+
+            #[test]
+            fn test_verify_account_proof_against_wrong_root() {
+                let (stf, block) = stf_with_accounts();
+                let proof = stf.account_proof(block.hash(), &[[0; 32]]).unwrap();
+
+                let account = verify_account_proof::<MockConfig>(&[7; 32], [0; 32], &proof);
+                assert!(matches!(account, Err(ProofError::MissingNode(_))));
+            }
+
+            #[test]
+            fn test_verify_account_not_covered_by_proof() {
+                let (stf, block) = stf_with_accounts();
+                let proof = stf.account_proof(block.hash(), &[[0; 32]]).unwrap();
+
+                // The proof says nothing about DAVE's path
+                let account =
+                    verify_account_proof::<MockConfig>(&block.header.state_root, [1; 32], &proof);
+                assert!(account.is_err());
+            }
+
+            #[test]
+            fn test_verify_value_proof_with_wrong_type() {
+                let (stf, block) = stf_with_accounts();
+                let proof = stf.account_proof(block.hash(), &[[1; 32]]).unwrap();
+                let root = block.header.state_root;
+
+                // An account does not decode as a block hash
+                let value: Result<Option<Vec<[u8; 32]>>, ProofError> =
+                    verify_value_proof(&root, StoragePrefix::Account, [1u8; 32], &proof);
+                assert!(matches!(value, Err(ProofError::ValueDecodeError(_))));
+            }
+        }
+    }
+}
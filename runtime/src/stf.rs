@@ -1,47 +1,411 @@
 use common::account::Account;
 use common::block::{Block, BlockTrait};
-use common::types::{Config, StoragePrefix};
-use common::types::{StfError, StorageError, TransactionType};
+use common::event::{BlockEvents, Event};
+use common::extrinsics::SignedTransaction;
+use common::receipt::Receipt;
+use common::trie::StorageProof;
+use common::types::{BalancesCall, Config, FeeMultiplier, Get, One, Origin};
+use common::types::{StfError, StorageError, StoragePrefix, TransactionError, TransactionType};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::error::Error;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::sync::mpsc::{self, Receiver, Sender};
 
+use crate::balances::Balances;
+use crate::composer::Runtime;
+use crate::pallet::{Context, Pallet};
 use crate::plugin::{Plugin, StoragePlugin};
+use crate::StateView;
 
 pub trait Stf<T: Config>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
     fn validate_block(&mut self, block: Block<T>) -> Result<(), Box<dyn Error>>;
-    fn execute_block(&mut self, block: Block<T>) -> Result<(), StfError>;
+    fn execute_block(&mut self, block: Block<T>) -> Result<Vec<Receipt<T>>, StfError>;
+    fn execute_authored_block(&mut self, block: &mut Block<T>)
+        -> Result<Vec<Receipt<T>>, StfError>;
+    fn revert_block(&mut self) -> Result<(), StfError>;
+    fn on_initialize(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError>;
+    fn on_finalize(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError>;
+    fn hooks_weight(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError>;
     fn validate_account(&mut self, account: Account<T>) -> Result<(), Box<dyn Error>>;
+    fn compute_state_root(&mut self, block: &Block<T>) -> Result<T::Hash, StfError>;
+    fn state_root(&self) -> T::Hash;
+    fn account_proof(
+        &self,
+        block_hash: T::Hash,
+        account_ids: &[T::Hash],
+    ) -> Result<StorageProof, StorageError>;
     fn get_block_hash(&self, block_height: T::HeightType) -> Result<T::Hash, StorageError>;
+    fn get_block(&self, block_hash: T::Hash) -> Result<Block<T>, StorageError>;
+    fn state_at(&self, block_hash: T::Hash) -> Result<StateView<'_>, StorageError>;
     fn get_account(&self, account_id: T::Hash) -> Result<Account<T>, StorageError>;
+    fn get_receipts(&self, block_height: T::HeightType) -> Result<Vec<Receipt<T>>, StorageError>;
+    fn get_events(&self, block_height: T::HeightType) -> Result<Vec<Event<T>>, StorageError>;
+    fn total_issuance(&self) -> Result<T::Funds, StorageError>;
+    fn fee_multiplier(&self) -> Result<FeeMultiplier, StorageError>;
+    fn sudo_key(&self) -> Result<Option<T::Hash>, StorageError>;
+    fn best_block(&self) -> Result<Option<T::HeightType>, StorageError>;
+    fn check_issuance(&self) -> Result<(), StfError>;
 }
 
-pub struct SimpleStf<T: Config>
+/// State the chain starts from, see [`SimpleStf::apply_genesis`].
+pub struct GenesisConfig<T: Config> {
+    /// Accounts and their initial balance, issued along with them.
+    pub accounts: Vec<(T::Hash, T::Funds)>,
+    /// Account allowed to dispatch calls as Root through `TransactionType::Sudo`.
+    pub sudo_key: Option<T::Hash>,
+}
+
+pub struct SimpleStf<T: Config, S: StoragePlugin = Plugin>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    plugin: Plugin,
+    plugin: S,
+    // Check the issuance invariant after every block, see `set_check_invariants`
+    check_invariants: bool,
+    // Events emitted by the block being applied
+    events: Vec<Event<T>>,
+    event_subscribers: Vec<Sender<BlockEvents<T>>>,
     phantom: PhantomData<T>,
 }
 
-impl<T: Config> SimpleStf<T>
+impl<T: Config, S: StoragePlugin> SimpleStf<T, S>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    pub fn new(plugin: Plugin) -> Self {
+    // The STF picks up from whatever state `plugin` already holds, see `Stf::best_block`
+    pub fn new(plugin: S) -> Self {
         SimpleStf {
             plugin,
+            check_invariants: false,
+            events: Vec::new(),
+            event_subscribers: Vec::new(),
             phantom: PhantomData,
         }
     }
+
+    // Receive the events of every block executed from now on, once the block is committed
+    pub fn subscribe_events(&mut self) -> Receiver<BlockEvents<T>> {
+        let (sender, receiver) = mpsc::channel();
+        self.event_subscribers.push(sender);
+        receiver
+    }
+
+    // Storage the STF runs on, e.g. for tests to set faults on
+    pub fn plugin_mut(&mut self) -> &mut S {
+        &mut self.plugin
+    }
+
+    // Number of latest blocks whose state is kept around, for `Stf::state_at` and to revert them.
+    // The state of older blocks is pruned
+    pub fn set_pruning_depth(&mut self, depth: usize) {
+        self.plugin.set_pruning_depth(depth);
+    }
+
+    // Debug mode: fail any block after which the balances no longer add up to the total issuance.
+    // Every account is read on each block, so this is meant for tests and debugging only
+    pub fn set_check_invariants(&mut self, check_invariants: bool) {
+        self.check_invariants = check_invariants;
+    }
+
+    // Set up the state the chain starts from, before the genesis block is executed. The accounts
+    // are created as Root, the only origin allowed to create them with funds
+    pub fn apply_genesis(&mut self, genesis: &GenesisConfig<T>) -> Result<(), StfError> {
+        if self.best_block()?.is_some() {
+            return Err(StfError::InvalidGenesis(
+                "A block has already been executed".to_string(),
+            ));
+        }
+
+        let events = &mut self.events;
+        self.plugin.with_transaction(|plugin| {
+            let ctx = &mut Context::new(plugin, events);
+            if let Some(sudo_key) = genesis.sudo_key {
+                ctx.plugin.set(StoragePrefix::Sudo, (), &sudo_key)?;
+            }
+            for &(account_id, balance) in &genesis.accounts {
+                let call = BalancesCall::AccountCreation {
+                    account_id,
+                    balance,
+                };
+                Balances::<T>::dispatch(ctx, Origin::Root, &call)
+                    .map_err(|e| StfError::InvalidGenesis(e.to_string()))?;
+            }
+            Ok::<_, StfError>(())
+        })?;
+
+        // Genesis events belong to no block
+        self.events.clear();
+        Ok(())
+    }
+
+    // Execute the block in its own storage transaction, see `apply_block`
+    fn execute(&mut self, block: &mut Block<T>, seal: bool) -> Result<Vec<Receipt<T>>, StfError> {
+        println!("\nBLOCK HEIGHT: {}", block.header.block_height);
+
+        // Every write of the block lands in its own storage transaction, so that
+        // any failure leaves the state exactly as it was before the block
+        self.events.clear();
+        let check_invariants = self.check_invariants;
+        let events = &mut self.events;
+        let receipts = self.plugin.with_transaction(|plugin| {
+            Self::apply_block(
+                &mut Context::new(plugin, events),
+                block,
+                check_invariants,
+                seal,
+            )
+        })?;
+        self.notify_subscribers(block.header.block_height.clone());
+        Ok(receipts)
+    }
+
+    // Apply the block on top of the current state, writes are not rolled back here on failure.
+    // When sealing, the header gets the state root the block leads to instead of being checked
+    // against it
+    fn apply_block(
+        ctx: &mut Context<T, S>,
+        block: &mut Block<T>,
+        check_invariants: bool,
+        seal: bool,
+    ) -> Result<Vec<Receipt<T>>, StfError> {
+        let receipts = Self::apply_extrinsics(ctx, block)?;
+
+        if check_invariants {
+            Self::check_issuance_of(ctx.plugin)?;
+        }
+
+        // The header must carry the root of the state the block leads to. It is taken before the
+        // block itself is stored, as the block hash depends on it
+        let state_root = T::Hash::from(ctx.plugin.state_root());
+        if seal {
+            block.header.state_root = state_root;
+        } else if block.header.state_root != state_root {
+            return Err(StfError::StateRootMismatch {
+                expected: hex::encode(block.header.state_root),
+                computed: hex::encode(state_root),
+            });
+        }
+
+        // Add the block to the state. B# -> BH & BH -> B
+        let block_hash = block.hash();
+        ctx.plugin
+            .set(
+                StoragePrefix::Block,
+                &block.header.block_height,
+                &block_hash,
+            )
+            .map_err(StfError::Storage)?;
+        ctx.plugin.set(StoragePrefix::Block, block_hash, block)?;
+        ctx.plugin
+            .set(StoragePrefix::BestBlock, (), &block.header.block_height)?;
+
+        Ok(receipts)
+    }
+
+    // Apply every extrinsic of the block and store their receipts, writes are not rolled back here on failure
+    fn apply_extrinsics(
+        ctx: &mut Context<T, S>,
+        block: &Block<T>,
+    ) -> Result<Vec<Receipt<T>>, StfError> {
+        // Executing a block does not rely on it having been validated first, only the genesis
+        // block comes without a coinbase
+        if block.header.block_height != T::HeightType::from(0) {
+            Self::check_inherents(block)?;
+        }
+
+        let mut block_weight = T::WeightType::from(0);
+        let mut receipts = Vec::new();
+
+        // The hooks run whatever the extrinsics, and take their share of the block's weight
+        let hooks_weight = Runtime::on_initialize(ctx, &block.header.block_height)?;
+        Self::consume_weight(&mut block_weight, hooks_weight)?;
+
+        for (index, transaction) in block.extrinsics().iter().enumerate() {
+            let index = index as u32;
+
+            // The block cannot be trusted to respect the weight limit, so check as we go
+            Self::consume_weight(&mut block_weight, transaction.weight())?;
+
+            let result = match Self::apply_extrinsic(ctx, transaction, block.header.author) {
+                // Storage failures are not the transaction's doing, the whole block is aborted
+                Err(TransactionError::Storage(e)) => return Err(StfError::Storage(e)),
+                result => result,
+            };
+            if let Err(e) = &result {
+                ctx.deposit_event(Event::ExtrinsicFailed {
+                    index,
+                    error: e.clone(),
+                });
+            }
+            receipts.push(Receipt {
+                index,
+                weight: transaction.weight(),
+                result,
+            });
+
+            // Add the transaction to the state
+            ctx.plugin.set(
+                StoragePrefix::Extrinsic,
+                (&block.header.block_height, index),
+                transaction,
+            )?;
+        }
+
+        let hooks_weight = Runtime::on_finalize(ctx, &block.header.block_height)?;
+        Self::consume_weight(&mut block_weight, hooks_weight)?;
+
+        // The fee multiplier of the next block follows how full this one is, hooks included
+        let fee_multiplier = Self::fee_multiplier_of(ctx.plugin)?
+            .next(block_weight.into(), T::TargetBlockWeight::get().into());
+        ctx.plugin
+            .set(StoragePrefix::FeeMultiplier, (), &fee_multiplier)?;
+
+        // Receipts and events are part of the state the block leads to
+        ctx.plugin.set(
+            StoragePrefix::Receipt,
+            &block.header.block_height,
+            &receipts,
+        )?;
+        let events = ctx.events().to_vec();
+        ctx.plugin
+            .set(StoragePrefix::Event, &block.header.block_height, &events)?;
+
+        Ok(receipts)
+    }
+
+    // Check that a single extrinsic of the block authored by `author` can be accepted, charge
+    // its signer and dispatch it. A transaction that cannot be charged leaves no trace
+    fn apply_extrinsic(
+        ctx: &mut Context<T, S>,
+        transaction: &SignedTransaction<T>,
+        author: T::Hash,
+    ) -> Result<(), TransactionError<T>> {
+        // A signature, when there is one, must be valid before anything is dispatched. Only
+        // inherents come without one, any other call is refused here rather than by its pallet
+        match transaction.signature {
+            Some(_) if !transaction.verify_signature() => {
+                return Err(TransactionError::InvalidSignature)
+            }
+            None if !transaction.is_inherent() => return Err(TransactionError::BadOrigin),
+            _ => {}
+        }
+
+        // The nonce is consumed and the fee kept as soon as the transaction is accepted, even if
+        // the call fails below, so that it can never be replayed nor hold back the signer's next ones.
+        // The charge gets its own transaction, a fee that cannot be routed is not withdrawn either
+        let fee = Self::fee_multiplier_of(ctx.plugin)?
+            .apply(transaction.fee()?)
+            .ok_or(TransactionError::Overflow)?;
+        ctx.with_transaction(|ctx| Runtime::pre_dispatch(ctx, transaction, fee, author))?;
+
+        // The call gets its own nested transaction, reverting it keeps the nonce consumed
+        // and drops the events it emitted
+        let origin = match transaction.signer() {
+            Some(signer) => Origin::Signed(signer),
+            None => Origin::None,
+        };
+        ctx.with_transaction(|ctx| Runtime::dispatch(ctx, origin, &transaction.transaction_type))
+    }
+
+    // Add `weight` to the weight used so far by the block, failing once it exceeds the limit
+    fn consume_weight(
+        block_weight: &mut T::WeightType,
+        weight: T::WeightType,
+    ) -> Result<(), StfError> {
+        *block_weight += weight;
+        if *block_weight > T::MaxBlockWeight::get() {
+            return Err(StfError::BlockExecutionError(format!(
+                "Block weight exceeded. Max allowed: {}, Current: {}",
+                T::MaxBlockWeight::get(),
+                block_weight
+            )));
+        }
+        Ok(())
+    }
+
+    // Factor applied to the fees of the block being built, one until a block has been executed
+    fn fee_multiplier_of(plugin: &S) -> Result<FeeMultiplier, StorageError> {
+        match plugin.get(StoragePrefix::FeeMultiplier, ()) {
+            Err(StorageError::KeyNotFound(_)) => Ok(FeeMultiplier::one()),
+            result => result,
+        }
+    }
+
+    // Check that the balances of all accounts add up to the total issuance
+    fn check_issuance_of(plugin: &S) -> Result<(), StfError> {
+        let accounts: Vec<Account<T>> = plugin.values(StoragePrefix::Account)?;
+        let balances = accounts
+            .iter()
+            .fold(T::Funds::from(0), |sum, account| sum + account.balance);
+        let issuance = Balances::<T>::total_issuance(plugin)?;
+
+        if balances != issuance {
+            return Err(StfError::IssuanceMismatch {
+                issuance: format!("{:?}", issuance),
+                balances: format!("{:?}", balances),
+            });
+        }
+
+        Ok(())
+    }
+
+    // What the pallets work with while the block is applied
+    fn context(&mut self) -> Context<'_, T, S> {
+        Context::new(&mut self.plugin, &mut self.events)
+    }
+
+    // Check that the coinbase inherent comes first in the block, rewards its author and is not repeated
+    fn check_inherents(block: &Block<T>) -> Result<(), StfError> {
+        match block
+            .extrinsics
+            .first()
+            .map(|extrinsic| &extrinsic.transaction_type)
+        {
+            Some(TransactionType::Coinbase { author }) if *author == block.header.author => {}
+            Some(TransactionType::Coinbase { .. }) => {
+                return Err(StfError::InvalidInherent(
+                    "Coinbase does not reward the block author".to_string(),
+                ));
+            }
+            _ => {
+                return Err(StfError::InvalidInherent(
+                    "Block does not start with a coinbase".to_string(),
+                ));
+            }
+        }
+
+        let coinbases = block
+            .extrinsics
+            .iter()
+            .filter(|extrinsic| extrinsic.is_inherent())
+            .count();
+        if coinbases > 1 {
+            return Err(StfError::InvalidInherent(format!(
+                "Block contains {} coinbases",
+                coinbases
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Send the events of a committed block to every subscriber still listening
+    fn notify_subscribers(&mut self, block_height: T::HeightType) {
+        let block_events = BlockEvents {
+            block_height,
+            events: std::mem::take(&mut self.events),
+        };
+        self.event_subscribers
+            .retain(|subscriber| subscriber.send(block_events.clone()).is_ok());
+    }
 }
 
-impl<T: Config> Stf<T> for SimpleStf<T>
+impl<T: Config, S: StoragePlugin> Stf<T> for SimpleStf<T, S>
 where
     T: Serialize + Debug + DeserializeOwned,
 {
@@ -58,7 +422,7 @@ where
         // Check if the parent block exists from State
         let parent_block_key: Result<T::Hash, StorageError> = self.plugin.get(
             StoragePrefix::Block,
-            block.header.block_height - T::HeightType::from(1),
+            block.header.block_height.clone() - T::HeightType::from(1),
         );
 
         // If parent block does not exist... big no-no
@@ -71,6 +435,20 @@ where
             return Err("Parent hash is invalid for this block.".into());
         }
 
+        // Check that the block rewards its author exactly once
+        Self::check_inherents(&block)?;
+
+        // Check that the header commits to the extrinsics found in the body
+        let computed_extrinsics_root = block.extrinsics_root();
+        if block.header.extrinsics_root != computed_extrinsics_root {
+            return Err(Box::new(StfError::ExtrinsicsRootMismatch {
+                expected: hex::encode(block.header.extrinsics_root),
+                computed: hex::encode(computed_extrinsics_root),
+            }));
+        }
+
+        // The claimed state root is checked by `execute_block`, which runs the block only once
+
         // TODO: Think of what could potentially be a trigger event which would check consensus and fetch the accepted chain
         // - Parent hash is currently set in the consensus layer, perhaps this should be from main...
         // - Block height comes from main...
@@ -78,168 +456,52 @@ where
         Ok(())
     }
 
-    fn execute_block(&mut self, block: Block<T>) -> Result<(), StfError> {
-        // Add the block to the state. B# -> BH & BH -> B
-        println!("\nBLOCK HEIGHT: {}", &block.header.block_height);
-        let block_hash = block.hash();
-        self.plugin
-            .set(
-                StoragePrefix::Block,
-                &block.header.block_height,
-                &block_hash,
-            )
-            .map_err(StfError::Storage)?;
-        self.plugin.set(StoragePrefix::Block, &block_hash, &block)?;
-
-        for transaction in block.extrinsics() {
-            // Apply the transaction, then update state
-            match transaction.transaction_type {
-                TransactionType::Transfer {
-                    amount, from, to, ..
-                } => {
-                    // Get the sender and receiver accounts
-                    let from_account: Result<Account<T>, StorageError> =
-                        self.plugin.get(StoragePrefix::Account, from);
-                    let to_account: Result<Account<T>, StorageError> =
-                        self.plugin.get(StoragePrefix::Account, to);
-
-                    // Check if the accounts exist, if they don't, skip the transaction
-                    if from_account.is_err() {
-                        eprintln!("Sender account does not exist.");
-                        continue;
-                    }
-
-                    if to_account.is_err() {
-                        eprintln!("Receiver account does not exist.");
-                        continue;
-                    }
-
-                    // Check if the sender has enough balance, if they don't, skip the transaction
-                    if from_account.clone().unwrap().balance < amount {
-                        eprintln!("Sender does not have enough balance.");
-                        continue;
-                    }
+    fn execute_block(&mut self, mut block: Block<T>) -> Result<Vec<Receipt<T>>, StfError> {
+        self.execute(&mut block, false)
+    }
 
-                    // Update the sender's account
-                    let updated_from_account: Account<T> = Account {
-                        account_id: from_account.clone().unwrap().account_id,
-                        balance: from_account.clone().unwrap().balance - amount,
-                    };
-                    // Push
-                    self.plugin
-                        .set(
-                            StoragePrefix::Account,
-                            &from_account.clone().unwrap().account_id,
-                            &updated_from_account,
-                        )
-                        .map_err(StfError::Storage)?;
-
-                    // Update the receiver's account
-                    let updated_to_account: Account<T> = Account {
-                        account_id: to_account.clone().unwrap().account_id,
-                        balance: to_account.clone().unwrap().balance + amount,
-                    };
-                    // Push
-                    self.plugin
-                        .set(
-                            StoragePrefix::Account,
-                            &to_account.unwrap().account_id,
-                            &updated_to_account,
-                        )
-                        .map_err(StfError::Storage)?;
-                }
-                TransactionType::Mint { amount, to, .. } => {
-                    // Get the receiver's account
-                    let to_account: Result<Account<T>, StorageError> =
-                        self.plugin.get(StoragePrefix::Account, to);
-
-                    // Check if the account exists, if it doesn't, skip the transaction
-                    if to_account.is_err() {
-                        eprintln!("Receiver account does not exist.");
-                        continue;
-                    }
+    // Execute a block being authored and seal it with the state root it leads to, running its
+    // extrinsics once rather than dry-running them through `compute_state_root` first
+    fn execute_authored_block(
+        &mut self,
+        block: &mut Block<T>,
+    ) -> Result<Vec<Receipt<T>>, StfError> {
+        self.execute(block, true)
+    }
 
-                    // Update the receiver's account
-                    let updated_to_account: Account<T> = Account {
-                        account_id: to_account.clone().unwrap().account_id,
-                        balance: to_account.clone().unwrap().balance + amount,
-                    };
-                    // Push
-                    self.plugin
-                        .set(
-                            StoragePrefix::Account,
-                            to_account.unwrap().account_id,
-                            &updated_to_account,
-                        )
-                        .map_err(StfError::Storage)?;
-                }
-                TransactionType::Burn { amount, from, .. } => {
-                    // Get the sender's account
-                    let from_account: Result<Account<T>, StorageError> =
-                        self.plugin.get(StoragePrefix::Account, from);
-
-                    // Check if the account exists, if it doesn't, skip the transaction
-                    if from_account.is_err() {
-                        eprintln!("Receiver account does not exist.");
-                        continue;
-                    }
+    // Undo the last executed block, every write it made included. Nothing else may have been
+    // committed to the storage since the block was executed
+    fn revert_block(&mut self) -> Result<(), StfError> {
+        if self.best_block()?.is_none() {
+            return Err(StfError::BlockExecutionError(
+                "No block to revert".to_string(),
+            ));
+        }
+        Ok(self.plugin.revert_last_commit()?)
+    }
 
-                    // Check if the sender has enough balance, if they don't, set them to zero???
-                    // TODO: Ask about this
-                    if from_account.clone().unwrap().balance < amount {
-                        eprintln!("Receiver does not have enough balance to burn that amount...");
-                        continue;
-                    }
+    fn on_initialize(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError> {
+        Ok(Runtime::on_initialize(&mut self.context(), block_height)?)
+    }
 
-                    // Update the sender's account
-                    let updated_from_account: Account<T> = Account {
-                        account_id: from_account.clone().unwrap().account_id,
-                        balance: from_account.clone().unwrap().balance - amount,
-                    };
-                    // Push
-                    self.plugin
-                        .set(
-                            StoragePrefix::Account,
-                            from_account.unwrap().account_id,
-                            &updated_from_account,
-                        )
-                        .map_err(StfError::Storage)?;
-                }
-                TransactionType::AccountCreation {
-                    account_id,
-                    balance,
-                    ..
-                } => {
-                    // Create the account
-                    let account = Account {
-                        account_id,
-                        balance,
-                    };
-
-                    // Validate the account
-                    match self.validate_account(account.clone()) {
-                        Ok(_) => {
-                            // Add the account to the state
-                            self.plugin
-                                .set(StoragePrefix::Account, account.clone().account_id, &account)
-                                .map_err(StfError::Storage)?;
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                        }
-                    }
-                }
-            }
+    fn on_finalize(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError> {
+        Ok(Runtime::on_finalize(&mut self.context(), block_height)?)
+    }
 
-            // TODO: If something happened, think about a rollback...
-            // Add the transaction to the state
-            self.plugin.set(
-                StoragePrefix::Extrinsic,
-                &block.header.block_height,
-                transaction,
-            )?;
-        }
-        Ok(())
+    // Weight the hooks take in the block at `block_height`, for its author to leave room for them.
+    // The hooks are dry-run and their writes rolled back, as in `compute_state_root`
+    fn hooks_weight(&mut self, block_height: &T::HeightType) -> Result<T::WeightType, StfError> {
+        self.plugin.start_transaction();
+        let mut events: Vec<Event<T>> = Vec::new();
+        let mut ctx = Context::new(&mut self.plugin, &mut events);
+        let result = Runtime::on_initialize(&mut ctx, block_height).and_then(|mut weight| {
+            weight += Runtime::on_finalize(&mut ctx, block_height)?;
+            Ok(weight)
+        });
+        self.plugin
+            .rollback_transaction()
+            .map_err(StfError::Storage)?;
+        Ok(result?)
     }
 
     // Check if the account already exists, this validation is used for the account creation transaction
@@ -255,13 +517,96 @@ where
         Ok(())
     }
 
+    // Dry-run the block's extrinsics and return the state root it leads to, leaving the state untouched
+    fn compute_state_root(&mut self, block: &Block<T>) -> Result<T::Hash, StfError> {
+        self.events.clear();
+        self.plugin.start_transaction();
+        let result = Self::apply_extrinsics(&mut self.context(), block);
+        let result = result.map(|_| self.state_root());
+        self.plugin
+            .rollback_transaction()
+            .map_err(StfError::Storage)?;
+        result
+    }
+
+    fn state_root(&self) -> T::Hash {
+        T::Hash::from(self.plugin.state_root())
+    }
+
+    // Read proof of the given accounts against the state root in the header of the given block,
+    // see `state_at` and `proof::verify_account_proof`
+    fn account_proof(
+        &self,
+        block_hash: T::Hash,
+        account_ids: &[T::Hash],
+    ) -> Result<StorageProof, StorageError> {
+        let keys = account_ids
+            .iter()
+            .map(|account_id| S::create_full_key(StoragePrefix::Account, *account_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.state_at(block_hash)?.read_proof(&keys))
+    }
+
     fn get_block_hash(&self, block_height: T::HeightType) -> Result<T::Hash, StorageError> {
         self.plugin.get(StoragePrefix::Block, block_height)
     }
 
+    fn get_block(&self, block_hash: T::Hash) -> Result<Block<T>, StorageError> {
+        self.plugin.get(StoragePrefix::Block, block_hash)
+    }
+
+    // State right after the given block of the best chain, as long as it is within the pruning depth
+    fn state_at(&self, block_hash: T::Hash) -> Result<StateView<'_>, StorageError> {
+        let block = self.get_block(block_hash)?;
+        let best_block: u64 = self
+            .best_block()?
+            .ok_or_else(|| StorageError::DataNotFound("No block executed yet".to_string()))?
+            .into();
+        let block_height: u64 = block.header.block_height.into();
+        self.plugin.view((best_block - block_height) as usize)
+    }
+
     fn get_account(&self, account_id: T::Hash) -> Result<Account<T>, StorageError> {
         self.plugin.get(StoragePrefix::Account, account_id)
     }
+
+    fn get_receipts(&self, block_height: T::HeightType) -> Result<Vec<Receipt<T>>, StorageError> {
+        self.plugin.get(StoragePrefix::Receipt, block_height)
+    }
+
+    fn get_events(&self, block_height: T::HeightType) -> Result<Vec<Event<T>>, StorageError> {
+        self.plugin.get(StoragePrefix::Event, block_height)
+    }
+
+    // Funds in existence across all accounts, nothing has been issued before the first account
+    fn total_issuance(&self) -> Result<T::Funds, StorageError> {
+        Balances::<T>::total_issuance(&self.plugin)
+    }
+
+    // Factor applied to the fees of the block being built, one until a block has been executed
+    fn fee_multiplier(&self) -> Result<FeeMultiplier, StorageError> {
+        Self::fee_multiplier_of(&self.plugin)
+    }
+
+    fn sudo_key(&self) -> Result<Option<T::Hash>, StorageError> {
+        match self.plugin.get(StoragePrefix::Sudo, ()) {
+            Err(StorageError::KeyNotFound(_)) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    // Height of the last executed block, none before genesis
+    fn best_block(&self) -> Result<Option<T::HeightType>, StorageError> {
+        match self.plugin.get(StoragePrefix::BestBlock, ()) {
+            Err(StorageError::KeyNotFound(_)) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    // Check that the balances of all accounts add up to the total issuance
+    fn check_issuance(&self) -> Result<(), StfError> {
+        Self::check_issuance_of(&self.plugin)
+    }
 }
 
 #[cfg(test)]
@@ -270,19 +615,101 @@ mod tests {
     use crate::plugin::Plugin;
     use common::account::Account;
     use common::block::Block;
-    use common::types::{Height, MaxBlockHeight, MaxBlockWeight, StfError};
+    use common::block::Header;
+    use common::crypto::Keyring;
+    use common::event::BalancesEvent;
+    use common::mock::FeelessMockConfig as MockConfig;
+    use common::types::{
+        BlockReward, BurnFees, FeeMultiplier, Height, MaxBlockHeight, MaxBlockWeight, NoFee,
+        NoOverhead, One, StfError, TargetBlockWeight,
+    };
     use serde::Deserialize;
 
-    // Mock implementation of Config trait for testing
-    #[derive(Serialize, Deserialize, Debug)]
-    struct MockConfig;
-    impl Config for MockConfig {
-        type MaxBlockWeight = MaxBlockWeight;
-        type MaxBlockHeight = MaxBlockHeight;
-        type WeightType = u64;
-        type HeightType = Height;
-        type Hash = [u8; 32];
-        type Funds = u128;
+    fn alice() -> [u8; 32] {
+        Keyring::Alice.account_id::<MockConfig>()
+    }
+
+    fn dave() -> [u8; 32] {
+        Keyring::Dave.account_id::<MockConfig>()
+    }
+
+    // ALICE and DAVE with 100 and 50, ALICE holding the sudo key
+    fn genesis() -> GenesisConfig<MockConfig> {
+        GenesisConfig {
+            accounts: vec![(alice(), 100), (dave(), 50)],
+            sudo_key: Some(alice()),
+        }
+    }
+
+    // `genesis`, then `extrinsics` in block 1
+    fn execute_with<S: StoragePlugin>(
+        stf: &mut SimpleStf<MockConfig, S>,
+        extrinsics: Vec<SignedTransaction<MockConfig>>,
+    ) -> Result<Vec<Receipt<MockConfig>>, StfError> {
+        stf.apply_genesis(&genesis())?;
+        execute_first_block(stf, extrinsics)
+    }
+
+    // `extrinsics` in block 1, after the coinbase rewarding [0; 32], sealed
+    fn execute_first_block<S: StoragePlugin>(
+        stf: &mut SimpleStf<MockConfig, S>,
+        extrinsics: Vec<SignedTransaction<MockConfig>>,
+    ) -> Result<Vec<Receipt<MockConfig>>, StfError> {
+        let mut block = Block {
+            header: Header {
+                block_height: Height::from(1),
+                parent_hash: [0; 32],
+                state_root: [0; 32],
+                extrinsics_root: [0; 32],
+                block_weight: 0,
+                author: [0; 32],
+            },
+            extrinsics: Vec::new(),
+        };
+        for extrinsic in extrinsics {
+            block.add_extrinsic(extrinsic).unwrap();
+        }
+        add_coinbase(&mut block);
+        stf.execute_authored_block(&mut block)
+    }
+
+    // Put the coinbase rewarding the author first in `block`, unless it already starts with one
+    fn add_coinbase<C: Config + Serialize>(block: &mut Block<C>) {
+        if !block
+            .extrinsics
+            .first()
+            .is_some_and(SignedTransaction::is_inherent)
+        {
+            let extrinsics = std::mem::take(&mut block.extrinsics);
+            block.header.block_weight = C::WeightType::from(0);
+            for extrinsic in
+                std::iter::once(SignedTransaction::coinbase(block.header.author)).chain(extrinsics)
+            {
+                block.add_extrinsic(extrinsic).unwrap();
+            }
+        }
+    }
+
+    // Add the coinbase and put the state root `block` leads to in its header, as its author does
+    // when sealing it
+    fn seal<C, S>(stf: &mut SimpleStf<C, S>, block: &mut Block<C>)
+    where
+        C: Config + Serialize + DeserializeOwned + Debug,
+        S: StoragePlugin,
+    {
+        add_coinbase(block);
+        block.header.state_root = stf.compute_state_root(block).unwrap();
+    }
+
+    // `call` dispatched as Root, signed by ALICE who holds the sudo key in `execute_with`
+    fn sudo(call: TransactionType<MockConfig>, nonce: u64) -> SignedTransaction<MockConfig> {
+        SignedTransaction::new_signed(
+            TransactionType::Sudo {
+                call: Box::new(call),
+            },
+            nonce,
+            &Keyring::Alice.pair(),
+        )
     }
 
     mod validate_block {
@@ -299,29 +726,36 @@ mod tests {
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
 
                 // Create a mock parent block and add it to the state
-                let parent_block: Block<MockConfig> = Block {
+                let mut parent_block: Block<MockConfig> = Block {
                     header: Header {
                         block_height: Height::from(1),
                         parent_hash: [0; 32],
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
+                seal(&mut stf, &mut parent_block);
                 assert!(stf.execute_block(parent_block.clone()).is_ok());
 
-                // Create a new block
-                let new_block = Block {
+                // Create a new block, on top of the parent as it was sealed by the STF
+                let mut new_block = Block {
                     header: Header {
                         block_height: Height::from(2),
-                        parent_hash: parent_block.hash(),
+                        parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
+                new_block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
+                new_block.header.state_root = stf.compute_state_root(&new_block).unwrap();
 
                 assert!(stf.validate_block(new_block).is_ok());
             }
@@ -338,18 +772,20 @@ mod tests {
                 let plugin = Plugin::new();
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
 
-                let block = Block {
+                let mut block = Block {
                     header: Header {
                         block_height: Height::zero(),
                         parent_hash: [0; 32],
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
 
                 // Place a block into the chain
+                seal(&mut stf, &mut block);
                 assert!(stf.execute_block(block.clone()).is_ok());
 
                 // Now validate the same block
@@ -370,12 +806,71 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
 
                 assert!(stf.validate_block(block).is_err());
             }
+
+            #[test]
+            fn test_validate_block_with_invalid_extrinsics_root() {
+                let plugin = Plugin::new();
+                let mut stf = SimpleStf::<MockConfig>::new(plugin);
+
+                let mut parent_block: Block<MockConfig> = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                seal(&mut stf, &mut parent_block);
+                assert!(stf.execute_block(parent_block).is_ok());
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(2),
+                        parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
+                block
+                    .add_extrinsic(SignedTransaction::new(TransactionType::Balances(
+                        BalancesCall::AccountCreation {
+                            account_id: [0; 32],
+                            balance: 100,
+                        },
+                    )))
+                    .unwrap();
+                block.header.state_root = stf.compute_state_root(&block).unwrap();
+
+                // Tamper with the body after the header was built
+                block.extrinsics[1] = SignedTransaction::new(TransactionType::Balances(
+                    BalancesCall::AccountCreation {
+                        account_id: [0; 32],
+                        balance: 1_000_000,
+                    },
+                ));
+
+                let err = stf.validate_block(block).unwrap_err();
+                assert!(matches!(
+                    err.downcast_ref::<StfError>(),
+                    Some(StfError::ExtrinsicsRootMismatch { .. })
+                ));
+            }
         }
     }
 
@@ -389,30 +884,100 @@ mod tests {
 
             use super::*;
 
+            #[test]
+            fn test_execute_block_stores_state_root() {
+                let mut first = SimpleStf::<MockConfig>::new(Plugin::new());
+                let mut second = SimpleStf::<MockConfig>::new(Plugin::new());
+                first.apply_genesis(&genesis()).unwrap();
+                second.apply_genesis(&genesis()).unwrap();
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block
+                    .add_extrinsic(SignedTransaction::new_signed(
+                        types::TransactionType::Balances(types::BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 30,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    ))
+                    .unwrap();
+
+                seal(&mut first, &mut block);
+                let expected_root = block.header.state_root;
+                assert!(first.execute_block(block.clone()).is_ok());
+                assert!(second.execute_block(block).is_ok());
+
+                // The stored header carries the post-execution root, the same on every node
+                let block_hash = first.get_block_hash(Height::from(1)).unwrap();
+                let stored: Block<MockConfig> =
+                    first.plugin.get(StoragePrefix::Block, block_hash).unwrap();
+                assert_eq!(stored.header.state_root, expected_root);
+                assert_eq!(stored.hash(), block_hash);
+                assert_eq!(first.state_root(), second.state_root());
+            }
+
+            #[test]
+            fn test_authored_block_is_sealed() {
+                let mut author = SimpleStf::<MockConfig>::new(Plugin::new());
+                let mut other = SimpleStf::<MockConfig>::new(Plugin::new());
+                author.apply_genesis(&genesis()).unwrap();
+                other.apply_genesis(&genesis()).unwrap();
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
+                let expected_root = other.compute_state_root(&block).unwrap();
+
+                // Executed once by its author, the block is sealed with the root it leads to
+                assert!(author.execute_authored_block(&mut block).is_ok());
+                assert_eq!(block.header.state_root, expected_root);
+                assert_eq!(author.state_root(), expected_root);
+                assert_eq!(
+                    author.get_block_hash(Height::from(1)).unwrap(),
+                    block.hash()
+                );
+                assert!(other.execute_block(block).is_ok());
+            }
+
             #[test]
             fn test_execute_block_with_transfer() {
                 let plugin = Plugin::new();
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
+                stf.apply_genesis(&genesis()).unwrap();
 
                 // Create a block with a transfer transaction
-                let transaction =
-                    extrinsics::SignedTransaction::new(types::TransactionType::Transfer {
-                        from: [0; 32],
-                        to: [1; 32],
+                let transaction = extrinsics::SignedTransaction::new_signed(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
                         amount: 30,
-                    });
-
-                let acc_alice: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [0; 32],
-                        balance: 100,
-                    });
-
-                let acc_dave: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [1; 32],
-                        balance: 50,
-                    });
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                );
 
                 let mut block = Block {
                     header: Header {
@@ -421,23 +986,19 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
 
-                block.add_extrinsic(acc_alice).unwrap();
-                block.add_extrinsic(acc_dave).unwrap();
                 block.add_extrinsic(transaction).unwrap();
 
+                seal(&mut stf, &mut block);
                 assert!(stf.execute_block(block).is_ok());
 
                 // Check updated balances
-                let updated_from: Account<MockConfig> = stf
-                    .get_account(<tests::MockConfig as Config>::Hash::from([0; 32]))
-                    .unwrap();
-                let updated_to: Account<MockConfig> = stf
-                    .get_account(<tests::MockConfig as Config>::Hash::from([1; 32]))
-                    .unwrap();
+                let updated_from: Account<MockConfig> = stf.get_account(alice()).unwrap();
+                let updated_to: Account<MockConfig> = stf.get_account(dave()).unwrap();
 
                 assert_eq!(
                     updated_from.balance,
@@ -447,6 +1008,10 @@ mod tests {
                     updated_to.balance,
                     <tests::MockConfig as Config>::Funds::from(80u128)
                 );
+
+                // Only the signer's nonce moves
+                assert_eq!(updated_from.nonce, 1);
+                assert_eq!(updated_to.nonce, 0);
             }
         }
 
@@ -461,62 +1026,1901 @@ mod tests {
             fn test_execute_block_with_insufficient_balance() {
                 let plugin = Plugin::new();
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
+                stf.apply_genesis(&genesis()).unwrap();
 
                 // Create account with insufficient balance
-                let acc_alice: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [0; 32],
-                        balance: 100,
-                    });
 
-                let acc_dave: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [1; 32],
-                        balance: 50,
+                // Create a block with a transfer transaction
+                let transaction: SignedTransaction<MockConfig> =
+                    extrinsics::SignedTransaction::new_signed(
+                        types::TransactionType::Balances(types::BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 150,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    );
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+
+                block.add_extrinsic(transaction).unwrap();
+
+                seal(&mut stf, &mut block);
+                assert!(stf.execute_block(block).is_ok());
+
+                // Check that balances remain unchanged
+                let updated_from: Account<MockConfig> =
+                    stf.plugin.get(StoragePrefix::Account, alice()).unwrap();
+                let updated_to: Account<MockConfig> =
+                    stf.plugin.get(StoragePrefix::Account, dave()).unwrap();
+                assert_eq!(updated_from.balance, 100);
+                assert_eq!(updated_to.balance, 50);
+
+                // The transaction was accepted, so its nonce is consumed even though the call failed
+                assert_eq!(updated_from.nonce, 1);
+            }
+
+            // `transaction` in block 1, on top of `genesis`
+            fn execute_transfer(
+                transaction: SignedTransaction<MockConfig>,
+            ) -> SimpleStf<MockConfig> {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert!(execute_with(&mut stf, vec![transaction]).is_ok());
+                stf
+            }
+
+            fn assert_balances_unchanged(stf: &SimpleStf<MockConfig>) {
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 50);
+            }
+
+            #[test]
+            fn test_execute_block_with_unsigned_transfer() {
+                let stf = execute_transfer(SignedTransaction::new(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    }),
+                ));
+                assert_balances_unchanged(&stf);
+            }
+
+            #[test]
+            fn test_execute_block_with_transfer_signed_by_receiver() {
+                // DAVE signs a transfer out of ALICE's account
+                let stf = execute_transfer(SignedTransaction::new_signed(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    }),
+                    0,
+                    &Keyring::Dave.pair(),
+                ));
+                assert_balances_unchanged(&stf);
+            }
+
+            #[test]
+            fn test_execute_block_with_forged_signature() {
+                // A valid signature over a smaller amount does not cover the tampered call
+                let mut transaction = SignedTransaction::new_signed(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 1,
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                );
+                transaction.transaction_type =
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
                     });
+                let stf = execute_transfer(transaction);
+                assert_balances_unchanged(&stf);
+            }
+
+            #[test]
+            fn test_execute_block_with_future_nonce() {
+                let stf = execute_transfer(SignedTransaction::new_signed(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    }),
+                    1,
+                    &Keyring::Alice.pair(),
+                ));
+                assert_balances_unchanged(&stf);
+                assert_eq!(stf.get_account(alice()).unwrap().nonce, 0);
+            }
+
+            #[test]
+            fn test_execute_block_with_replayed_transaction() {
+                let transaction = SignedTransaction::new_signed(
+                    types::TransactionType::Balances(types::BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                );
+                let mut stf = execute_transfer(transaction.clone());
+
+                // The same signed transaction submitted again in a later block
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(2),
+                        parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block.add_extrinsic(transaction).unwrap();
+                seal(&mut stf, &mut block);
+                assert!(stf.execute_block(block).is_ok());
+
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 70);
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 80);
+                assert_eq!(stf.get_account(alice()).unwrap().nonce, 1);
+            }
+
+            #[test]
+            fn test_execute_block_rolls_back_on_failure() {
+                let plugin = Plugin::new();
+                let mut stf = SimpleStf::<MockConfig>::new(plugin);
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![(alice(), 100)],
+                    sudo_key: Some(alice()),
+                })
+                .unwrap();
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                seal(&mut stf, &mut block);
+                assert!(stf.execute_block(block.clone()).is_ok());
+
+                let state_before = stf.plugin.get_state().clone();
+
+                // Bypass `add_extrinsic` so the block goes over the weight limit halfway through
+                let mut overweight_block = Block {
+                    header: Header {
+                        block_height: Height::from(2),
+                        parent_hash: block.hash(),
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: vec![SignedTransaction::coinbase([0; 32])],
+                };
+                overweight_block.extrinsics.push(sudo(
+                    types::TransactionType::Balances(types::BalancesCall::AccountCreation {
+                        account_id: dave(),
+                        balance: 50,
+                    }),
+                    0,
+                ));
+                for _ in 0..20 {
+                    overweight_block.extrinsics.push(SignedTransaction::new(
+                        types::TransactionType::Balances(types::BalancesCall::Mint {
+                            to: alice(),
+                            amount: 10,
+                        }),
+                    ));
+                }
+
+                assert!(matches!(
+                    stf.execute_block(overweight_block),
+                    Err(StfError::BlockExecutionError(_))
+                ));
+
+                // Nothing from the failed block made it into the state
+                assert_eq!(stf.plugin.get_state(), &state_before);
+                assert!(stf.get_account(dave()).is_err());
+                assert!(stf.get_block_hash(Height::from(2)).is_err());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            }
+
+            #[test]
+            fn test_execute_block_with_invalid_state_root() {
+                let plugin = Plugin::new();
+                let mut stf = SimpleStf::<MockConfig>::new(plugin);
+
+                let mut parent_block: Block<MockConfig> = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                seal(&mut stf, &mut parent_block);
+                assert!(stf.execute_block(parent_block).is_ok());
+
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(2),
+                        parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block
+                    .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                    .unwrap();
+                block
+                    .add_extrinsic(SignedTransaction::new(TransactionType::Balances(
+                        BalancesCall::AccountCreation {
+                            account_id: [0; 32],
+                            balance: 100,
+                        },
+                    )))
+                    .unwrap();
+                let state_before = stf.plugin.get_state().clone();
+
+                // Claim the root of the state before the block instead of after it
+                block.header.state_root = stf.state_root();
+
+                assert!(matches!(
+                    stf.execute_block(block),
+                    Err(StfError::StateRootMismatch { .. })
+                ));
+
+                // Nothing from the block made it into the state
+                assert_eq!(stf.plugin.get_state(), &state_before);
+                assert!(stf.get_block_hash(Height::from(2)).is_err());
+            }
+        }
+    }
+
+    mod total_issuance {
+        use super::*;
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_total_issuance_starts_at_zero() {
+                let stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert_eq!(stf.total_issuance().unwrap(), 0);
+                assert!(stf.check_issuance().is_ok());
+            }
+
+            #[test]
+            fn test_total_issuance_tracks_supply() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+
+                let extrinsics = vec![
+                    sudo(
+                        TransactionType::Balances(BalancesCall::Mint {
+                            to: dave(),
+                            amount: 30,
+                        }),
+                        0,
+                    ),
+                    SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Burn {
+                            from: alice(),
+                            amount: 20,
+                        }),
+                        1,
+                        &Keyring::Alice.pair(),
+                    ),
+                    SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 10,
+                        }),
+                        2,
+                        &Keyring::Alice.pair(),
+                    ),
+                ];
+                assert!(execute_with(&mut stf, extrinsics).is_ok());
+
+                // 150 created, 100 rewarded, 30 minted and 20 burnt, transfers leave it as is
+                assert_eq!(stf.total_issuance().unwrap(), 260);
+                assert!(stf.check_issuance().is_ok());
+            }
+
+            #[test]
+            fn test_failed_calls_leave_issuance_untouched() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+
+                let extrinsics = vec![
+                    // Burning more than the balance
+                    SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Burn {
+                            from: alice(),
+                            amount: 1000,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    ),
+                    // Creating an account that already exists
+                    sudo(
+                        TransactionType::Balances(BalancesCall::AccountCreation {
+                            account_id: dave(),
+                            balance: 1000,
+                        }),
+                        1,
+                    ),
+                ];
+                assert!(execute_with(&mut stf, extrinsics).is_ok());
+
+                // Only the coinbase added to it
+                assert_eq!(stf.total_issuance().unwrap(), 250);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_check_issuance_detects_mismatch() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert!(execute_with(&mut stf, Vec::new()).is_ok());
+
+                // Credit DAVE behind the STF's back
+                let mut account = stf.get_account(dave()).unwrap();
+                account.balance += 1;
+                stf.plugin
+                    .set(StoragePrefix::Account, dave(), &account)
+                    .unwrap();
+
+                assert!(matches!(
+                    stf.check_issuance(),
+                    Err(StfError::IssuanceMismatch { .. })
+                ));
+            }
+
+            #[test]
+            fn test_debug_mode_rejects_unbalanced_block() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+
+                // Issuance falls short of the funds the accounts hold
+                stf.apply_genesis(&genesis()).unwrap();
+                Balances::set_total_issuance(&mut stf.context(), 10).unwrap();
+                let state_before = stf.plugin.get_state().clone();
+
+                assert!(matches!(
+                    execute_first_block(&mut stf, Vec::new()),
+                    Err(StfError::IssuanceMismatch { .. })
+                ));
+                assert_eq!(stf.plugin.get_state(), &state_before);
+            }
+        }
+    }
+
+    mod receipts {
+        use super::*;
+
+        fn transfer(amount: u128, nonce: u64) -> SignedTransaction<MockConfig> {
+            SignedTransaction::new_signed(
+                TransactionType::Balances(BalancesCall::Transfer {
+                    from: alice(),
+                    to: dave(),
+                    amount,
+                }),
+                nonce,
+                &Keyring::Alice.pair(),
+            )
+        }
+
+        // Result of the only extrinsic of block 1 besides its coinbase
+        fn last_result(
+            extrinsic: SignedTransaction<MockConfig>,
+        ) -> Result<(), TransactionError<MockConfig>> {
+            let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+            let mut receipts = execute_with(&mut stf, vec![extrinsic]).unwrap();
+            receipts.pop().unwrap().result
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_receipts_are_returned_and_stored() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let receipts =
+                    execute_with(&mut stf, vec![transfer(30, 0), transfer(1000, 1)]).unwrap();
+
+                // One receipt per extrinsic, the coinbase included
+                assert_eq!(receipts.len(), 3);
+                for (index, receipt) in receipts.iter().enumerate() {
+                    assert_eq!(receipt.index, index as u32);
+                }
+                assert_eq!(receipts[1].weight, 10);
+                assert!(receipts[1].is_success());
+                assert!(matches!(
+                    receipts[2].result,
+                    Err(TransactionError::InsufficientBalance { amount: 1000, .. })
+                ));
+
+                let stored = stf.get_receipts(Height::from(1)).unwrap();
+                assert_eq!(stored.len(), receipts.len());
+                assert!(matches!(
+                    stored[2].result,
+                    Err(TransactionError::InsufficientBalance { .. })
+                ));
+            }
+
+            #[test]
+            fn test_transfer_to_self() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let receipts = execute_with(
+                    &mut stf,
+                    vec![SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Transfer {
+                            from: alice(),
+                            to: alice(),
+                            amount: 30,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    )],
+                )
+                .unwrap();
+
+                assert!(receipts[0].is_success());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_receipt_account_not_found() {
+                let result = last_result(sudo(
+                    TransactionType::Balances(BalancesCall::Mint {
+                        to: [9; 32],
+                        amount: 10,
+                    }),
+                    0,
+                ));
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::AccountNotFound(account_id)) if account_id == [9; 32]
+                ));
+            }
+
+            #[test]
+            fn test_receipt_insufficient_balance() {
+                let result = last_result(SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Burn {
+                        from: alice(),
+                        amount: 101,
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                ));
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::InsufficientBalance {
+                        balance: 100,
+                        amount: 101,
+                        ..
+                    })
+                ));
+            }
+
+            #[test]
+            fn test_receipt_account_already_exists() {
+                let result = last_result(sudo(
+                    TransactionType::Balances(BalancesCall::AccountCreation {
+                        account_id: dave(),
+                        balance: 10,
+                    }),
+                    0,
+                ));
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::AccountAlreadyExists(_))
+                ));
+            }
+
+            #[test]
+            fn test_receipt_overflow() {
+                let result = last_result(sudo(
+                    TransactionType::Balances(BalancesCall::Mint {
+                        to: dave(),
+                        amount: u128::MAX,
+                    }),
+                    0,
+                ));
+                assert!(matches!(result, Err(TransactionError::Overflow)));
+            }
+
+            #[test]
+            fn test_receipt_invalid_nonce() {
+                let result = last_result(transfer(30, 5));
+                assert!(matches!(
+                    result,
+                    Err(TransactionError::InvalidNonce {
+                        expected: 0,
+                        got: 5
+                    })
+                ));
+            }
+
+            #[test]
+            fn test_receipt_transfer_not_signed_by_sender() {
+                let result = last_result(SignedTransaction::new(TransactionType::Balances(
+                    BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    },
+                )));
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+            }
+
+            #[test]
+            fn test_receipt_invalid_signature() {
+                let mut extrinsic = transfer(1, 0);
+                extrinsic.transaction_type = TransactionType::Balances(BalancesCall::Transfer {
+                    from: alice(),
+                    to: dave(),
+                    amount: 30,
+                });
+                let result = last_result(extrinsic);
+                assert!(matches!(result, Err(TransactionError::InvalidSignature)));
+            }
+        }
+    }
+
+    mod events {
+        use super::*;
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_events_are_stored_per_block() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let extrinsics = vec![
+                    SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 30,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    ),
+                    sudo(
+                        TransactionType::Balances(BalancesCall::Mint {
+                            to: dave(),
+                            amount: 5,
+                        }),
+                        1,
+                    ),
+                ];
+                assert!(execute_with(&mut stf, extrinsics).is_ok());
+
+                let events = stf.get_events(Height::from(1)).unwrap();
+                // The coinbase creates the author's account, then rewards it
+                assert_eq!(events.len(), 4);
+                assert!(matches!(events[1], Event::Rewarded { amount: 100, .. }));
+                assert!(matches!(
+                    events[2],
+                    Event::Balances(BalancesEvent::Transferred { from, to, amount: 30 }) if from == alice() && to == dave()
+                ));
+                assert!(matches!(
+                    events[3],
+                    Event::Balances(BalancesEvent::Minted { amount: 5, .. })
+                ));
+            }
+
+            #[test]
+            fn test_failed_extrinsic_emits_only_failure() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let extrinsics = vec![SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Burn {
+                        from: alice(),
+                        amount: 1000,
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                )];
+                assert!(execute_with(&mut stf, extrinsics).is_ok());
+
+                let events = stf.get_events(Height::from(1)).unwrap();
+                assert_eq!(events.len(), 3);
+                assert!(matches!(
+                    events[2],
+                    Event::ExtrinsicFailed {
+                        index: 1,
+                        error: TransactionError::InsufficientBalance { .. }
+                    }
+                ));
+            }
+
+            #[test]
+            fn test_subscribers_receive_committed_blocks() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let first = stf.subscribe_events();
+                let second = stf.subscribe_events();
+                let mint = sudo(
+                    TransactionType::Balances(BalancesCall::Mint {
+                        to: dave(),
+                        amount: 5,
+                    }),
+                    0,
+                );
+                assert!(execute_with(&mut stf, vec![mint]).is_ok());
+
+                // Genesis events belong to no block
+                for subscriber in [first, second] {
+                    let block_events = subscriber.try_recv().unwrap();
+                    assert_eq!(block_events.block_height, Height::from(1));
+                    assert_eq!(block_events.events.len(), 3);
+                    assert!(subscriber.try_recv().is_err());
+                }
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_no_events_for_unknown_block() {
+                let stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert!(stf.get_events(Height::from(1)).is_err());
+            }
+
+            #[test]
+            fn test_failed_block_is_not_notified() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+                let subscriber = stf.subscribe_events();
+
+                // The debug check fails the block after its events were emitted
+                Balances::set_total_issuance(&mut stf.context(), 10).unwrap();
+                let mint = sudo(
+                    TransactionType::Balances(BalancesCall::Mint {
+                        to: dave(),
+                        amount: 5,
+                    }),
+                    0,
+                );
+                assert!(execute_with(&mut stf, vec![mint]).is_err());
+
+                assert!(subscriber.try_recv().is_err());
+                assert!(stf.get_events(Height::from(1)).is_err());
+            }
+
+            #[test]
+            fn test_dropped_subscriber_is_forgotten() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                drop(stf.subscribe_events());
+                assert!(execute_with(&mut stf, Vec::new()).is_ok());
+
+                assert!(stf.event_subscribers.is_empty());
+            }
+        }
+    }
+
+    mod fees {
+        use super::*;
+        use common::types::{AuthorFees, BaseFee, FeeDestination, Get, LengthFee, WeightFee};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct BurnConfig;
+        impl Config for BurnConfig {
+            type MaxBlockWeight = MaxBlockWeight;
+            type MaxBlockHeight = MaxBlockHeight;
+            type WeightType = u64;
+            type HeightType = Height;
+            type Hash = [u8; 32];
+            type Funds = u128;
+            type Nonce = u64;
+            type BaseFee = BaseFee;
+            type WeightFee = WeightFee;
+            type LengthFee = LengthFee;
+            type FeeDestination = BurnFees;
+            type TargetBlockWeight = TargetBlockWeight;
+            type BlockReward = BlockReward;
+            type BlockOverhead = NoOverhead;
+        }
+
+        const TREASURY: [u8; 32] = [7; 32];
+
+        struct Treasury;
+        impl Get<FeeDestination<[u8; 32]>> for Treasury {
+            fn get() -> FeeDestination<[u8; 32]> {
+                FeeDestination::Treasury(TREASURY)
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct TreasuryConfig;
+        impl Config for TreasuryConfig {
+            type MaxBlockWeight = MaxBlockWeight;
+            type MaxBlockHeight = MaxBlockHeight;
+            type WeightType = u64;
+            type HeightType = Height;
+            type Hash = [u8; 32];
+            type Funds = u128;
+            type Nonce = u64;
+            type BaseFee = BaseFee;
+            type WeightFee = WeightFee;
+            type LengthFee = LengthFee;
+            type FeeDestination = Treasury;
+            type TargetBlockWeight = TargetBlockWeight;
+            type BlockReward = BlockReward;
+            type BlockOverhead = NoOverhead;
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct AuthorConfig;
+        impl Config for AuthorConfig {
+            type MaxBlockWeight = MaxBlockWeight;
+            type MaxBlockHeight = MaxBlockHeight;
+            type WeightType = u64;
+            type HeightType = Height;
+            type Hash = [u8; 32];
+            type Funds = u128;
+            type Nonce = u64;
+            type BaseFee = BaseFee;
+            type WeightFee = WeightFee;
+            type LengthFee = LengthFee;
+            type FeeDestination = AuthorFees;
+            type TargetBlockWeight = TargetBlockWeight;
+            type BlockReward = BlockReward;
+            type BlockOverhead = NoOverhead;
+        }
+
+        // Every unit of weight costs more than a `u128` can hold
+        struct PricyWeight;
+        impl Get<u128> for PricyWeight {
+            fn get() -> u128 {
+                u128::MAX
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct PricyConfig;
+        impl Config for PricyConfig {
+            type MaxBlockWeight = MaxBlockWeight;
+            type MaxBlockHeight = MaxBlockHeight;
+            type WeightType = u64;
+            type HeightType = Height;
+            type Hash = [u8; 32];
+            type Funds = u128;
+            type Nonce = u64;
+            type BaseFee = BaseFee;
+            type WeightFee = PricyWeight;
+            type LengthFee = LengthFee;
+            type FeeDestination = BurnFees;
+            type TargetBlockWeight = TargetBlockWeight;
+            type BlockReward = BlockReward;
+            type BlockOverhead = NoOverhead;
+        }
+
+        fn transfer<C>(from: Keyring, amount: u128) -> SignedTransaction<C>
+        where
+            C: Config<Hash = [u8; 32], Funds = u128, Nonce = u64>,
+        {
+            SignedTransaction::new_signed(
+                TransactionType::Balances(BalancesCall::Transfer {
+                    from: from.account_id::<C>(),
+                    to: dave(),
+                    amount,
+                }),
+                0,
+                &from.pair(),
+            )
+        }
+
+        // ALICE and DAVE with 10000 and 50, then `extrinsics` in the same block, authored by [0; 32]
+        fn execute<C>(extrinsics: Vec<SignedTransaction<C>>) -> (SimpleStf<C>, Vec<Receipt<C>>)
+        where
+            C: Config<
+                    WeightType = u64,
+                    HeightType = Height,
+                    Hash = [u8; 32],
+                    Funds = u128,
+                    Nonce = u64,
+                > + Serialize
+                + DeserializeOwned
+                + Debug,
+        {
+            let mut stf = SimpleStf::<C>::new(Plugin::new());
+            stf.set_check_invariants(true);
+            stf.apply_genesis(&GenesisConfig {
+                accounts: vec![(alice(), 10_000), (dave(), 50)],
+                sudo_key: None,
+            })
+            .unwrap();
+            let mut block = Block {
+                header: Header {
+                    block_height: Height::from(1),
+                    parent_hash: [0; 32],
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author: [0; 32],
+                },
+                extrinsics: Vec::new(),
+            };
+            for extrinsic in extrinsics {
+                block.add_extrinsic(extrinsic).unwrap();
+            }
+            seal(&mut stf, &mut block);
+            let receipts = stf.execute_block(block).unwrap();
+            (stf, receipts)
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_fee_is_burnt() {
+                let transaction = transfer::<BurnConfig>(Keyring::Alice, 30);
+                let fee = transaction.fee().unwrap();
+                let (stf, receipts) = execute(vec![transaction]);
+
+                assert!(receipts[1].is_success());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 10_000 - 30 - fee);
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 80);
+                assert_eq!(
+                    stf.total_issuance().unwrap(),
+                    10_050 + BlockReward::get() - fee
+                );
+
+                let events = stf.get_events(Height::from(1)).unwrap();
+                assert!(events.contains(&Event::FeePaid {
+                    who: alice(),
+                    amount: fee
+                }));
+            }
+
+            #[test]
+            fn test_fee_goes_to_treasury() {
+                let transaction = transfer::<TreasuryConfig>(Keyring::Alice, 30);
+                let fee = transaction.fee().unwrap();
+                let (stf, _) = execute(vec![transaction]);
+
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 10_000 - 30 - fee);
+                assert_eq!(stf.get_account(TREASURY).unwrap().balance, fee);
+                assert_eq!(stf.total_issuance().unwrap(), 10_050 + BlockReward::get());
+            }
+
+            #[test]
+            fn test_fee_goes_to_author() {
+                let transaction = transfer::<AuthorConfig>(Keyring::Alice, 30);
+                let fee = transaction.fee().unwrap();
+                let (stf, _) = execute(vec![transaction]);
+
+                // The block is authored by [0; 32], which also gets the block reward
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 10_000 - 30 - fee);
+                assert_eq!(
+                    stf.get_account([0; 32]).unwrap().balance,
+                    BlockReward::get() + fee
+                );
+                assert_eq!(stf.total_issuance().unwrap(), 10_050 + BlockReward::get());
+            }
+
+            #[test]
+            fn test_fee_is_kept_when_call_fails() {
+                let transaction = transfer::<BurnConfig>(Keyring::Alice, 1_000_000);
+                let fee = transaction.fee().unwrap();
+                let (stf, receipts) = execute(vec![transaction]);
+
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::InsufficientBalance { .. })
+                ));
+                let account = stf.get_account(alice()).unwrap();
+                assert_eq!(account.balance, 10_000 - fee);
+                assert_eq!(account.nonce, 1);
+            }
+
+            #[test]
+            fn test_fee_follows_multiplier() {
+                let transaction = transfer::<BurnConfig>(Keyring::Alice, 30);
+                let fee = transaction.fee().unwrap();
+
+                let mut stf = SimpleStf::<BurnConfig>::new(Plugin::new());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![(alice(), 10_000)],
+                    sudo_key: None,
+                })
+                .unwrap();
+                stf.plugin
+                    .set(StoragePrefix::FeeMultiplier, (), &FeeMultiplier(2_000_000))
+                    .unwrap();
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block.add_extrinsic(transaction).unwrap();
+                seal(&mut stf, &mut block);
+                assert!(stf.execute_block(block).is_ok());
+
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 10_000 - 2 * fee);
+            }
+
+            #[test]
+            fn test_unsigned_transactions_are_free() {
+                // The coinbase is the only extrinsic
+                let (stf, receipts) = execute::<BurnConfig>(Vec::new());
+                assert!(receipts[0].is_success());
+                assert_eq!(
+                    stf.get_account([0; 32]).unwrap().balance,
+                    BlockReward::get()
+                );
+                assert_eq!(stf.total_issuance().unwrap(), 10_050 + BlockReward::get());
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_transaction_that_cannot_pay_is_refused() {
+                // DAVE's 50 do not cover the fee of a signed transaction
+                let transaction = transfer::<BurnConfig>(Keyring::Dave, 1);
+                let (stf, receipts) = execute(vec![transaction]);
+
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::CannotPayFee { balance: 50, .. })
+                ));
+                let account = stf.get_account(dave()).unwrap();
+                assert_eq!(account.balance, 50);
+                assert_eq!(account.nonce, 0);
+                assert_eq!(stf.total_issuance().unwrap(), 10_050 + BlockReward::get());
+            }
+
+            #[test]
+            fn test_fee_that_cannot_be_routed_is_not_withdrawn() {
+                let transaction = transfer::<TreasuryConfig>(Keyring::Alice, 30);
+
+                let mut stf = SimpleStf::<TreasuryConfig>::new(Plugin::new());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![(alice(), 10_000)],
+                    sudo_key: None,
+                })
+                .unwrap();
+                // A treasury that cannot take another unit makes crediting the fee overflow
+                Balances::set_account(
+                    &mut stf.context(),
+                    &Account {
+                        account_id: TREASURY,
+                        balance: u128::MAX,
+                        nonce: 0,
+                    },
+                )
+                .unwrap();
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block.add_extrinsic(transaction).unwrap();
+                seal(&mut stf, &mut block);
+                let receipts = stf.execute_block(block).unwrap();
+
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::Overflow)
+                ));
+                let account = stf.get_account(alice()).unwrap();
+                assert_eq!(account.balance, 10_000);
+                assert_eq!(account.nonce, 0);
+                assert_eq!(stf.total_issuance().unwrap(), 10_000 + BlockReward::get());
+            }
+
+            #[test]
+            fn test_fee_overflow_is_refused() {
+                let transaction = transfer::<PricyConfig>(Keyring::Alice, 30);
+                assert!(matches!(transaction.fee(), Err(TransactionError::Overflow)));
+                let (stf, receipts) = execute(vec![transaction]);
+
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::Overflow)
+                ));
+                let account = stf.get_account(alice()).unwrap();
+                assert_eq!(account.balance, 10_000);
+                assert_eq!(account.nonce, 0);
+            }
+
+            #[test]
+            fn test_burn_beyond_issuance_is_refused() {
+                let transaction = transfer::<BurnConfig>(Keyring::Alice, 30);
+
+                let mut stf = SimpleStf::<BurnConfig>::new(Plugin::new());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![(alice(), 10_000)],
+                    sudo_key: None,
+                })
+                .unwrap();
+                // An issuance below the fee makes burning it underflow
+                Balances::set_total_issuance(&mut stf.context(), 0).unwrap();
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(1),
+                        parent_hash: [0; 32],
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                block.add_extrinsic(transaction).unwrap();
+                seal(&mut stf, &mut block);
+                let receipts = stf.execute_block(block).unwrap();
+
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::Overflow)
+                ));
+                let account = stf.get_account(alice()).unwrap();
+                assert_eq!(account.balance, 10_000);
+                assert_eq!(account.nonce, 0);
+                assert_eq!(stf.total_issuance().unwrap(), BlockReward::get());
+            }
+        }
+    }
+
+    mod fee_multiplier {
+        use super::*;
+
+        fn mints(count: usize) -> Vec<SignedTransaction<MockConfig>> {
+            (0..count as u64)
+                .map(|nonce| {
+                    sudo(
+                        TransactionType::Balances(BalancesCall::Mint {
+                            to: dave(),
+                            amount: 1,
+                        }),
+                        nonce,
+                    )
+                })
+                .collect()
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_multiplier_starts_at_one() {
+                let stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert_eq!(stf.fee_multiplier().unwrap(), FeeMultiplier::one());
+            }
+
+            #[test]
+            fn test_multiplier_follows_fullness() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+
+                // 12 mints of 15, 80 over the target of 100
+                assert!(execute_with(&mut stf, mints(12)).is_ok());
+                assert_eq!(stf.fee_multiplier().unwrap(), FeeMultiplier(1_100_000));
+
+                // An empty block brings it back down, not below one
+                let mut block = Block {
+                    header: Header {
+                        block_height: Height::from(2),
+                        parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
+                        state_root: [0; 32],
+                        extrinsics_root: [0; 32],
+                        block_weight: 0,
+                        author: [0; 32],
+                    },
+                    extrinsics: Vec::new(),
+                };
+                seal(&mut stf, &mut block);
+                assert!(stf.execute_block(block).is_ok());
+                assert_eq!(stf.fee_multiplier().unwrap(), FeeMultiplier::one());
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_failed_block_keeps_multiplier() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+                Balances::set_total_issuance(&mut stf.context(), 10).unwrap();
+
+                assert!(execute_with(&mut stf, mints(12)).is_err());
+                assert_eq!(stf.fee_multiplier().unwrap(), FeeMultiplier::one());
+            }
+        }
+    }
+
+    mod inherents {
+        use super::*;
+
+        // Block on top of the one built by `execute_with`, authored by `author`
+        fn block_on_top(
+            stf: &mut SimpleStf<MockConfig>,
+            author: [u8; 32],
+            extrinsics: Vec<SignedTransaction<MockConfig>>,
+        ) -> Block<MockConfig> {
+            assert!(execute_with(stf, Vec::new()).is_ok());
+            let mut block = Block {
+                header: Header {
+                    block_height: Height::from(2),
+                    parent_hash: stf.get_block_hash(Height::from(1)).unwrap(),
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author,
+                },
+                extrinsics: Vec::new(),
+            };
+            for extrinsic in extrinsics {
+                block.add_extrinsic(extrinsic).unwrap();
+            }
+            block
+        }
+
+        // The block is refused whether it is validated first or not
+        fn assert_invalid_inherent(stf: &mut SimpleStf<MockConfig>, block: Block<MockConfig>) {
+            let err = stf.validate_block(block.clone()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<StfError>(),
+                Some(StfError::InvalidInherent(_))
+            ));
+            assert!(matches!(
+                stf.compute_state_root(&block),
+                Err(StfError::InvalidInherent(_))
+            ));
+            assert!(matches!(
+                stf.execute_block(block),
+                Err(StfError::InvalidInherent(_))
+            ));
+            assert_eq!(stf.best_block().unwrap(), Some(Height::from(1)));
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_coinbase_rewards_author() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+                let mut block =
+                    block_on_top(&mut stf, dave(), vec![SignedTransaction::coinbase(dave())]);
+                seal(&mut stf, &mut block);
+
+                assert!(stf.validate_block(block.clone()).is_ok());
+                assert!(stf.execute_block(block).is_ok());
+
+                // Block 1 rewarded its own author
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 50 + 100);
+                assert_eq!(stf.total_issuance().unwrap(), 150 + 2 * 100);
+                let events = stf.get_events(Height::from(2)).unwrap();
+                assert!(matches!(
+                    events.as_slice(),
+                    [Event::Rewarded { author, amount: 100 }] if *author == dave()
+                ));
+            }
+
+            #[test]
+            fn test_coinbase_creates_author_account() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.set_check_invariants(true);
+                let author = [9; 32];
+                let mut block =
+                    block_on_top(&mut stf, author, vec![SignedTransaction::coinbase(author)]);
+                seal(&mut stf, &mut block);
+
+                assert!(stf.validate_block(block.clone()).is_ok());
+                assert!(stf.execute_block(block).is_ok());
+
+                let account = stf.get_account(author).unwrap();
+                assert_eq!(account.balance, 100);
+                assert_eq!(account.nonce, 0);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_block_without_coinbase() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let block = block_on_top(&mut stf, dave(), Vec::new());
+                assert_invalid_inherent(&mut stf, block);
+            }
+
+            #[test]
+            fn test_coinbase_not_first() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let mint = SignedTransaction::new(TransactionType::Balances(BalancesCall::Mint {
+                    to: dave(),
+                    amount: 1,
+                }));
+                let block = block_on_top(
+                    &mut stf,
+                    dave(),
+                    vec![mint, SignedTransaction::coinbase(dave())],
+                );
+                assert_invalid_inherent(&mut stf, block);
+            }
+
+            #[test]
+            fn test_repeated_coinbase() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let block = block_on_top(
+                    &mut stf,
+                    dave(),
+                    vec![
+                        SignedTransaction::coinbase(dave()),
+                        SignedTransaction::coinbase(dave()),
+                    ],
+                );
+                assert_invalid_inherent(&mut stf, block);
+            }
+
+            #[test]
+            fn test_coinbase_rewards_someone_else() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let block =
+                    block_on_top(&mut stf, dave(), vec![SignedTransaction::coinbase(alice())]);
+                assert_invalid_inherent(&mut stf, block);
+            }
+        }
+    }
+
+    mod origins {
+        use super::*;
+
+        fn mint(amount: u128) -> TransactionType<MockConfig> {
+            TransactionType::Balances(BalancesCall::Mint { to: dave(), amount })
+        }
+
+        fn burn(amount: u128) -> TransactionType<MockConfig> {
+            TransactionType::Balances(BalancesCall::Burn {
+                from: alice(),
+                amount,
+            })
+        }
+
+        // Execute `extrinsic` in block 1 on top of `genesis`, ALICE holding the sudo key. The
+        // coinbase of the block adds 100 to the issuance
+        fn execute(
+            extrinsic: SignedTransaction<MockConfig>,
+        ) -> (
+            SimpleStf<MockConfig>,
+            Result<(), TransactionError<MockConfig>>,
+        ) {
+            let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+            stf.set_check_invariants(true);
+            let mut receipts = execute_with(&mut stf, vec![extrinsic]).unwrap();
+            let result = receipts.pop().unwrap().result;
+            (stf, result)
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_sudo_key_is_unset_by_default() {
+                let stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                assert_eq!(stf.sudo_key().unwrap(), None);
+            }
+
+            #[test]
+            fn test_root_can_mint() {
+                let (stf, result) = execute(sudo(mint(30), 0));
+
+                assert!(result.is_ok());
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 80);
+                assert_eq!(stf.total_issuance().unwrap(), 280);
+            }
 
-                // Create a block with a transfer transaction
-                let transaction: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::Transfer {
-                        from: [0; 32],
-                        to: [1; 32],
-                        amount: 150,
-                    });
+            #[test]
+            fn test_owner_can_burn() {
+                let (stf, result) = execute(SignedTransaction::new_signed(
+                    burn(30),
+                    0,
+                    &Keyring::Alice.pair(),
+                ));
+
+                assert!(result.is_ok());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 70);
+                assert_eq!(stf.total_issuance().unwrap(), 220);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_unsigned_mint() {
+                let (stf, result) = execute(SignedTransaction::new(mint(30)));
+
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 50);
+                assert_eq!(stf.total_issuance().unwrap(), 250);
+            }
+
+            #[test]
+            fn test_unsigned_account_creation() {
+                let (stf, result) = execute(SignedTransaction::new(TransactionType::Balances(
+                    BalancesCall::AccountCreation {
+                        account_id: [9; 32],
+                        balance: 1000,
+                    },
+                )));
+
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert!(stf.get_account([9; 32]).is_err());
+                assert_eq!(stf.total_issuance().unwrap(), 250);
+            }
+
+            #[test]
+            fn test_signed_mint_without_sudo() {
+                // Even the sudo key has to go through `Sudo` to act as Root
+                let (_, result) = execute(SignedTransaction::new_signed(
+                    mint(30),
+                    0,
+                    &Keyring::Alice.pair(),
+                ));
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+            }
+
+            #[test]
+            fn test_sudo_signed_by_someone_else() {
+                let (stf, result) = execute(SignedTransaction::new_signed(
+                    TransactionType::Sudo {
+                        call: Box::new(mint(30)),
+                    },
+                    0,
+                    &Keyring::Dave.pair(),
+                ));
+
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 50);
+            }
+
+            #[test]
+            fn test_sudo_without_sudo_key() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![(alice(), 100)],
+                    sudo_key: None,
+                })
+                .unwrap();
+
+                let receipts = execute_first_block(&mut stf, vec![sudo(mint(30), 0)]).unwrap();
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::BadOrigin)
+                ));
+            }
+
+            #[test]
+            fn test_burn_signed_by_someone_else() {
+                let (stf, result) = execute(SignedTransaction::new_signed(
+                    burn(30),
+                    0,
+                    &Keyring::Dave.pair(),
+                ));
+
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            }
+
+            #[test]
+            fn test_burn_as_root() {
+                // Root cannot take funds out of an account either
+                let (stf, result) = execute(sudo(burn(30), 0));
+
+                assert!(matches!(result, Err(TransactionError::BadOrigin)));
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            }
+        }
+    }
+
+    mod storage_errors {
+        use super::*;
+        use crate::mock::MockPlugin;
+
+        fn transfer() -> SignedTransaction<MockConfig> {
+            SignedTransaction::new_signed(
+                TransactionType::Balances(BalancesCall::Transfer {
+                    from: alice(),
+                    to: dave(),
+                    amount: 30,
+                }),
+                0,
+                &Keyring::Alice.pair(),
+            )
+        }
+
+        // STF on top of `genesis`, for faults to be set before `execute_first_block`
+        fn setup() -> SimpleStf<MockConfig, MockPlugin> {
+            let mut stf = SimpleStf::<MockConfig, MockPlugin>::new(MockPlugin::new());
+            stf.apply_genesis(&genesis()).unwrap();
+            stf
+        }
+
+        // The block of `execute_first_block` left no trace in the state
+        fn assert_aborted(stf: &SimpleStf<MockConfig, MockPlugin>) {
+            assert!(stf.best_block().unwrap().is_none());
+            assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            assert_eq!(stf.plugin.state().transaction_depth(), 0);
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_execute_block_on_other_storage() {
+                let mut stf = SimpleStf::<MockConfig, MockPlugin>::new(MockPlugin::new());
+                assert!(execute_with(&mut stf, vec![transfer()]).is_ok());
+
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 70);
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 80);
+                assert!(stf.plugin.writes() > 0);
+            }
+
+            #[test]
+            fn test_unsigned_call_is_refused_before_dispatch() {
+                // Sudo reads its key before looking at the origin, were it dispatched
+                let mut stf = setup();
+                stf.plugin.fail_reads(
+                    StoragePrefix::Sudo,
+                    StorageError::OperationFailed("disk gone".to_string()),
+                );
+
+                let unsigned = SignedTransaction::new(TransactionType::Sudo {
+                    call: Box::new(TransactionType::Balances(BalancesCall::Mint {
+                        to: dave(),
+                        amount: 30,
+                    })),
+                });
+                let receipts = execute_first_block(&mut stf, vec![unsigned]).unwrap();
+                assert!(matches!(
+                    receipts[1].result,
+                    Err(TransactionError::BadOrigin)
+                ));
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 50);
+            }
+
+            #[test]
+            fn test_block_executes_once_storage_recovers() {
+                let mut stf = setup();
+                stf.plugin.fail_writes(
+                    StoragePrefix::Receipt,
+                    StorageError::DataInsertionError("disk full".to_string()),
+                );
+                assert!(execute_first_block(&mut stf, vec![transfer()]).is_err());
+
+                stf.plugin.clear_failures();
+                assert!(execute_first_block(&mut stf, vec![transfer()]).is_ok());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 70);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_failing_write_aborts_block() {
+                let error = StorageError::DataInsertionError("disk full".to_string());
+                let mut stf = setup();
+                stf.plugin
+                    .fail_writes(StoragePrefix::Receipt, error.clone());
+
+                let result = execute_first_block(&mut stf, vec![transfer()]);
+                assert!(matches!(result, Err(StfError::Storage(e)) if e == error));
+                assert_aborted(&stf);
+            }
+
+            #[test]
+            fn test_failing_read_aborts_block() {
+                // Not the transaction's fault, so it does not merely fail with a receipt
+                let error = StorageError::DeserializationError("corrupt account".to_string());
+                let mut stf = setup();
+                stf.plugin.fail_reads(StoragePrefix::Account, error.clone());
+
+                let result = execute_first_block(&mut stf, vec![transfer()]);
+                assert!(matches!(result, Err(StfError::Storage(e)) if e == error));
+                stf.plugin.clear_failures();
+                assert_aborted(&stf);
+            }
+
+            #[test]
+            fn test_failing_block_write_aborts_block() {
+                let error = StorageError::OperationFailed("disk gone".to_string());
+                let mut stf = setup();
+                stf.plugin.fail_writes(StoragePrefix::Block, error.clone());
+
+                let result = execute_first_block(&mut stf, vec![transfer()]);
+                assert!(matches!(result, Err(StfError::Storage(e)) if e == error));
+                assert_aborted(&stf);
+            }
+        }
+    }
+
+    mod faults {
+        use super::*;
+        use crate::mock::{Fault, MockPlugin};
+        use crate::plugin::Transactional;
+        use std::panic::{self, AssertUnwindSafe};
+
+        fn transfer() -> SignedTransaction<MockConfig> {
+            SignedTransaction::new_signed(
+                TransactionType::Balances(BalancesCall::Transfer {
+                    from: alice(),
+                    to: dave(),
+                    amount: 30,
+                }),
+                0,
+                &Keyring::Alice.pair(),
+            )
+        }
+
+        // STF on top of `genesis`, for `execute_first_block` to run into faults
+        fn setup() -> (SimpleStf<MockConfig, MockPlugin>, [u8; 32]) {
+            let mut stf = SimpleStf::<MockConfig, MockPlugin>::new(MockPlugin::new());
+            stf.apply_genesis(&genesis()).unwrap();
+            let state_root = stf.state_root();
+            (stf, state_root)
+        }
+
+        // Writes and reads `execute_first_block` makes on top of `setup` when nothing goes wrong
+        fn operations() -> (usize, usize) {
+            let (mut stf, _) = setup();
+            let (writes, reads) = (stf.plugin.writes(), stf.plugin.reads());
+            execute_first_block(&mut stf, vec![transfer()]).unwrap();
+            (stf.plugin.writes() - writes, stf.plugin.reads() - reads)
+        }
+
+        fn assert_rolled_back(stf: &SimpleStf<MockConfig, MockPlugin>, state_root: [u8; 32]) {
+            assert_eq!(stf.state_root(), state_root);
+            assert_eq!(stf.plugin.state().transaction_depth(), 0);
+            assert!(stf.best_block().unwrap().is_none());
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_fault_after_the_block() {
+                let (writes, _) = operations();
+                let (mut stf, _) = setup();
+                stf.plugin.fault_write(
+                    writes,
+                    Fault::Fail(StorageError::DataInsertionError("disk full".to_string())),
+                );
+
+                assert!(execute_first_block(&mut stf, vec![transfer()]).is_ok());
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 80);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_every_write_of_the_block_can_fail() {
+                let (writes, _) = operations();
+                for skip in 0..writes {
+                    let (mut stf, state_root) = setup();
+                    let error = StorageError::DataInsertionError("disk full".to_string());
+                    stf.plugin.fault_write(skip, Fault::Fail(error.clone()));
+
+                    let result = execute_first_block(&mut stf, vec![transfer()]);
+                    assert!(matches!(result, Err(StfError::Storage(e)) if e == error));
+                    assert_rolled_back(&stf, state_root);
+                }
+            }
+
+            #[test]
+            fn test_every_read_of_the_block_can_fail() {
+                let (_, reads) = operations();
+                for skip in 0..reads {
+                    let (mut stf, state_root) = setup();
+                    let error = StorageError::OperationFailed("disk gone".to_string());
+                    stf.plugin.fault_read(skip, Fault::Fail(error.clone()));
+
+                    let result = execute_first_block(&mut stf, vec![transfer()]);
+                    assert!(matches!(result, Err(StfError::Storage(e)) if e == error));
+                    assert_rolled_back(&stf, state_root);
+                }
+            }
+
+            #[test]
+            fn test_corrupt_account_fails_decoding() {
+                // The nonce bump comes right after the coinbase, its two writes and the extrinsic
+                // itself, and the transfer reads ALICE back
+                let (mut stf, state_root) = setup();
+                stf.plugin.fault_write(3, Fault::Corrupt);
+
+                let result = execute_first_block(&mut stf, vec![transfer()]);
+                assert!(matches!(
+                    result,
+                    Err(StfError::Storage(StorageError::DeserializationError(_)))
+                ));
+                assert_rolled_back(&stf, state_root);
+            }
+
+            #[test]
+            fn test_panic_leaves_committed_state_untouched() {
+                let (writes, _) = operations();
+                let (mut stf, state_root) = setup();
+                stf.plugin.fault_write(writes / 2, Fault::Panic);
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    execute_first_block(&mut stf, vec![transfer()])
+                }));
+                assert!(result.is_err());
+
+                // Whatever the block wrote is still pending, dropping it is all a restart takes
+                assert!(stf.plugin.state().transaction_depth() > 0);
+                while stf.plugin.state().transaction_depth() > 0 {
+                    stf.plugin.rollback_transaction().unwrap();
+                }
+                assert_rolled_back(&stf, state_root);
+            }
+        }
+    }
+
+    mod hooks {
+        use super::*;
+        use common::types::Get;
+
+        struct Overhead;
+        impl Get<u64> for Overhead {
+            fn get() -> u64 {
+                50
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct OverheadConfig;
+        impl Config for OverheadConfig {
+            type MaxBlockWeight = MaxBlockWeight;
+            type MaxBlockHeight = MaxBlockHeight;
+            type WeightType = u64;
+            type HeightType = Height;
+            type Hash = [u8; 32];
+            type Funds = u128;
+            type Nonce = u64;
+            type BaseFee = NoFee;
+            type WeightFee = NoFee;
+            type LengthFee = NoFee;
+            type FeeDestination = BurnFees;
+            type TargetBlockWeight = TargetBlockWeight;
+            type BlockReward = BlockReward;
+            type BlockOverhead = Overhead;
+        }
+
+        // ALICE and DAVE with 100 and 50, then block 1 with its coinbase and `transfers` of 1 from
+        // ALICE to DAVE
+        fn setup(transfers: u64) -> (SimpleStf<OverheadConfig>, Block<OverheadConfig>) {
+            let mut stf = SimpleStf::<OverheadConfig>::new(Plugin::new());
+            stf.apply_genesis(&GenesisConfig {
+                accounts: vec![(alice(), 100), (dave(), 50)],
+                sudo_key: None,
+            })
+            .unwrap();
+            let mut block = Block {
+                header: Header {
+                    block_height: Height::from(1),
+                    parent_hash: [0; 32],
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author: [0; 32],
+                },
+                extrinsics: Vec::new(),
+            };
+            block
+                .add_extrinsic(SignedTransaction::coinbase([0; 32]))
+                .unwrap();
+            for nonce in 0..transfers {
+                block
+                    .add_extrinsic(SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 1,
+                        }),
+                        nonce,
+                        &Keyring::Alice.pair(),
+                    ))
+                    .unwrap();
+            }
+            (stf, block)
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_default_hooks_weigh_nothing() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                let state_root = stf.state_root();
+
+                assert_eq!(stf.on_initialize(&Height::from(1)).unwrap(), 0);
+                assert_eq!(stf.on_finalize(&Height::from(1)).unwrap(), 0);
+                assert_eq!(stf.hooks_weight(&Height::from(1)).unwrap(), 0);
+                assert_eq!(stf.state_root(), state_root);
+            }
+
+            #[test]
+            fn test_hooks_weight_up_to_the_limit() {
+                // Extrinsics filling the block but for what the hooks use
+                let mut block_weight = MaxBlockWeight::get() - 5;
+                SimpleStf::<MockConfig>::consume_weight(&mut block_weight, 5).unwrap();
+                assert_eq!(block_weight, MaxBlockWeight::get());
+            }
+
+            #[test]
+            fn test_hooks_weight_is_a_dry_run() {
+                let (mut stf, _) = setup(0);
+                let state_root = stf.state_root();
+
+                assert_eq!(stf.hooks_weight(&Height::from(1)).unwrap(), 50);
+                assert_eq!(stf.state_root(), state_root);
+            }
+
+            #[test]
+            fn test_block_filled_up_to_the_hooks_weight() {
+                // 15 transfers of 10 leave exactly the 50 the hooks take
+                let (mut stf, mut block) = setup(15);
+                seal(&mut stf, &mut block);
+
+                let receipts = stf.execute_block(block).unwrap();
+                assert_eq!(receipts.len(), 16);
+                assert!(receipts.iter().all(|receipt| receipt.is_success()));
+                assert_eq!(stf.get_account(dave()).unwrap().balance, 65);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_hooks_weight_over_the_limit() {
+                let mut block_weight = MaxBlockWeight::get() - 5;
+                let result = SimpleStf::<MockConfig>::consume_weight(&mut block_weight, 6);
+                assert!(matches!(result, Err(StfError::BlockExecutionError(_))));
+            }
+
+            #[test]
+            fn test_block_eating_into_the_hooks_weight() {
+                // The transfers fit in the block on their own, not along with the hooks
+                let (mut stf, block) = setup(16);
+                assert!(block.header.block_weight <= MaxBlockWeight::get());
+                let state_root = stf.state_root();
+
+                let result = stf.execute_block(block);
+                assert!(matches!(result, Err(StfError::BlockExecutionError(_))));
+                assert_eq!(stf.state_root(), state_root);
+                assert_eq!(stf.best_block().unwrap(), None);
+            }
+        }
+    }
+
+    mod revert_block {
+        mod success {
+            use super::super::*;
+
+            #[test]
+            fn test_revert_to_previous_block() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                execute_with(&mut stf, Vec::new()).unwrap();
+                let first_hash = stf.get_block_hash(Height::from(1)).unwrap();
+                let first_root = stf.state_root();
 
                 let mut block = Block {
                     header: Header {
-                        block_height: Height::from(1),
-                        parent_hash: [0; 32],
+                        block_height: Height::from(2),
+                        parent_hash: first_hash,
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
+                block
+                    .add_extrinsic(SignedTransaction::new_signed(
+                        TransactionType::Balances(BalancesCall::Transfer {
+                            from: alice(),
+                            to: dave(),
+                            amount: 30,
+                        }),
+                        0,
+                        &Keyring::Alice.pair(),
+                    ))
+                    .unwrap();
+                seal(&mut stf, &mut block);
+                stf.execute_block(block).unwrap();
+
+                // Everything the block wrote is gone, the keys it created included
+                stf.revert_block().unwrap();
+                assert_eq!(stf.state_root(), first_root);
+                assert_eq!(stf.best_block().unwrap(), Some(Height::from(1)));
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+                assert!(stf.get_block_hash(Height::from(2)).is_err());
+                assert_eq!(stf.get_block(first_hash).unwrap().hash(), first_hash);
+
+                // Genesis accounts predate the first block
+                stf.revert_block().unwrap();
+                assert_eq!(stf.best_block().unwrap(), None);
+                assert!(stf.get_block_hash(Height::from(1)).is_err());
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 100);
+            }
+        }
 
-                block.add_extrinsic(acc_alice).unwrap();
-                block.add_extrinsic(acc_dave).unwrap();
-                block.add_extrinsic(transaction).unwrap();
-
-                assert!(stf.execute_block(block).is_ok());
+        mod failure {
+            use super::super::*;
 
-                // Check that balances remain unchanged
-                let updated_from: Account<MockConfig> = stf
-                    .plugin
-                    .get(
-                        StoragePrefix::Account,
-                        <tests::MockConfig as Config>::Hash::from([0; 32]),
-                    )
-                    .unwrap();
-                let updated_to: Account<MockConfig> = stf
-                    .plugin
-                    .get(
-                        StoragePrefix::Account,
-                        <tests::MockConfig as Config>::Hash::from([1; 32]),
-                    )
-                    .unwrap();
-                assert_eq!(updated_from.balance, 100);
-                assert_eq!(updated_to.balance, 50);
+            #[test]
+            fn test_revert_without_block() {
+                let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+                stf.apply_genesis(&genesis()).unwrap();
+
+                assert!(matches!(
+                    stf.revert_block(),
+                    Err(StfError::BlockExecutionError(_))
+                ));
+                assert_eq!(stf.sudo_key().unwrap(), Some(alice()));
             }
         }
     }
@@ -535,12 +2939,13 @@ mod tests {
                 let new_account = Account {
                     account_id: [0; 32],
                     balance: 100,
+                    nonce: 0,
                 };
 
                 stf.plugin
                     .set(
                         StoragePrefix::Account,
-                        &new_account.account_id,
+                        new_account.account_id,
                         &new_account.balance,
                     )
                     .map_err(StfError::Storage)
@@ -551,41 +2956,22 @@ mod tests {
         }
 
         mod failure {
-            use common::block::Header;
-            use common::extrinsics::{self, SignedTransaction};
-            use common::types;
-
             use super::*;
 
             #[test]
             fn test_validate_existing_account() {
                 let plugin = Plugin::new();
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
-
-                let acc_alice: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [0; 32],
-                        balance: 100,
-                    });
-
-                let mut block = Block {
-                    header: Header {
-                        block_height: Height::from(1),
-                        parent_hash: [0; 32],
-                        state_root: [0; 32],
-                        extrinsics_root: [0; 32],
-                        block_weight: 0,
-                    },
-                    extrinsics: Vec::new(),
-                };
-
-                block.add_extrinsic(acc_alice).unwrap();
-
-                assert!(stf.execute_block(block).is_ok());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![([0; 32], 100)],
+                    sudo_key: None,
+                })
+                .unwrap();
 
                 let same_alice_account = Account {
                     account_id: [0; 32],
                     balance: 100,
+                    nonce: 0,
                 };
 
                 // This fails because the account already exists...
@@ -594,6 +2980,109 @@ mod tests {
         }
     }
 
+    mod state_at {
+        use super::*;
+
+        // Block 1 of `execute_with`, then a block 2 where ALICE sends 30 to DAVE
+        fn two_blocks() -> (SimpleStf<MockConfig>, [u8; 32], [u8; 32]) {
+            let mut stf = SimpleStf::<MockConfig>::new(Plugin::new());
+            execute_with(&mut stf, Vec::new()).unwrap();
+            let first_hash = stf.get_block_hash(Height::from(1)).unwrap();
+            let first_root = stf.get_block(first_hash).unwrap().header.state_root;
+
+            let mut block = Block {
+                header: Header {
+                    block_height: Height::from(2),
+                    parent_hash: first_hash,
+                    state_root: [0; 32],
+                    extrinsics_root: [0; 32],
+                    block_weight: 0,
+                    author: [0; 32],
+                },
+                extrinsics: Vec::new(),
+            };
+            block
+                .add_extrinsic(SignedTransaction::new_signed(
+                    TransactionType::Balances(BalancesCall::Transfer {
+                        from: alice(),
+                        to: dave(),
+                        amount: 30,
+                    }),
+                    0,
+                    &Keyring::Alice.pair(),
+                ))
+                .unwrap();
+            seal(&mut stf, &mut block);
+            stf.execute_block(block).unwrap();
+            (stf, first_hash, first_root)
+        }
+
+        mod success {
+            use super::*;
+
+            #[test]
+            fn test_state_after_past_block() {
+                let (stf, first_hash, first_root) = two_blocks();
+
+                let view = stf.state_at(first_hash).unwrap();
+                let account: Account<MockConfig> =
+                    view.get(StoragePrefix::Account, alice()).unwrap();
+                assert_eq!(account.balance, 100);
+                assert_eq!(view.root(), first_root);
+                assert!(view
+                    .get::<_, _, [u8; 32]>(StoragePrefix::Block, Height::from(2))
+                    .is_err());
+
+                // The best block is seen as it is now
+                let best_hash = stf.get_block_hash(Height::from(2)).unwrap();
+                let best_root = stf.get_block(best_hash).unwrap().header.state_root;
+                assert_eq!(stf.state_at(best_hash).unwrap().root(), best_root);
+                assert_eq!(stf.state_root(), best_root);
+                assert_eq!(stf.get_account(alice()).unwrap().balance, 70);
+            }
+
+            #[test]
+            fn test_account_proof_at_past_block() {
+                let (stf, first_hash, first_root) = two_blocks();
+
+                // ALICE's balance at block 1, checked against nothing but its header
+                let proof = stf.account_proof(first_hash, &[alice()]).unwrap();
+                let account =
+                    crate::proof::verify_account_proof::<MockConfig>(&first_root, alice(), &proof)
+                        .unwrap();
+                assert_eq!(account.unwrap().balance, 100);
+            }
+        }
+
+        mod failure {
+            use super::*;
+
+            #[test]
+            fn test_state_of_pruned_block() {
+                let (mut stf, first_hash, _) = two_blocks();
+                stf.set_pruning_depth(0);
+
+                assert!(matches!(
+                    stf.state_at(first_hash),
+                    Err(StorageError::DataNotFound(_))
+                ));
+                assert!(matches!(
+                    stf.revert_block(),
+                    Err(StfError::Storage(StorageError::OperationFailed(_)))
+                ));
+            }
+
+            #[test]
+            fn test_state_of_unknown_block() {
+                let (stf, _, _) = two_blocks();
+                assert!(matches!(
+                    stf.state_at([7; 32]),
+                    Err(StorageError::KeyNotFound(_))
+                ));
+            }
+        }
+    }
+
     mod get_block_hash {
         use super::*;
 
@@ -614,12 +3103,13 @@ mod tests {
                         state_root: [0; 32],
                         extrinsics_root: [0; 32],
                         block_weight: 0,
+                        author: [0; 32],
                     },
                     extrinsics: Vec::new(),
                 };
 
                 stf.plugin
-                    .set(StoragePrefix::Block, &1u64, &block.hash())
+                    .set(StoragePrefix::Block, 1u64, &block.hash())
                     .unwrap();
 
                 assert_eq!(stf.get_block_hash(Height::from(1)).unwrap(), block.hash());
@@ -643,37 +3133,17 @@ mod tests {
         use super::*;
 
         mod success {
-            use common::block::Header;
-            use common::extrinsics::{self, SignedTransaction};
-            use common::types;
-
             use super::*;
 
             #[test]
             fn test_get_existing_account() {
                 let plugin = Plugin::new();
                 let mut stf = SimpleStf::<MockConfig>::new(plugin);
-
-                let acc_alice: SignedTransaction<MockConfig> =
-                    extrinsics::SignedTransaction::new(types::TransactionType::AccountCreation {
-                        account_id: [0; 32],
-                        balance: 100,
-                    });
-
-                let mut block = Block {
-                    header: Header {
-                        block_height: Height::from(1),
-                        parent_hash: [0; 32],
-                        state_root: [0; 32],
-                        extrinsics_root: [0; 32],
-                        block_weight: 0,
-                    },
-                    extrinsics: Vec::new(),
-                };
-
-                block.add_extrinsic(acc_alice).unwrap();
-
-                assert!(stf.execute_block(block).is_ok());
+                stf.apply_genesis(&GenesisConfig {
+                    accounts: vec![([0; 32], 100)],
+                    sudo_key: None,
+                })
+                .unwrap();
 
                 let retrieved_account = stf
                     .get_account(<tests::MockConfig as Config>::Hash::from([0; 32]))